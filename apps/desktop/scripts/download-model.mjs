@@ -1,3 +1,4 @@
+import crypto from "node:crypto";
 import fs from "node:fs";
 import path from "node:path";
 import { Readable } from "node:stream";
@@ -7,10 +8,37 @@ import { fileURLToPath } from "node:url";
 const __filename = fileURLToPath(import.meta.url);
 const __dirname = path.dirname(__filename);
 
-const MODEL_URL =
-  "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-small.bin";
+// Bundles the model the app falls back to before any model is downloaded
+// in-app. Checksums come from the same listing the app's model manager uses.
+const REPOSITORY = "https://huggingface.co/ggerganov/whisper.cpp";
+const CATALOG_URL =
+  "https://huggingface.co/api/models/ggerganov/whisper.cpp/tree/main";
+const MODEL_FILE = "ggml-small.bin";
+const MODEL_URL = `${REPOSITORY}/resolve/main/${MODEL_FILE}`;
 const TARGET_DIR = path.join(__dirname, "../src-tauri/models");
-const TARGET_FILE = path.join(TARGET_DIR, "ggml-small.bin");
+const TARGET_FILE = path.join(TARGET_DIR, MODEL_FILE);
+const PART_FILE = `${TARGET_FILE}.part`;
+
+async function expectedSha256() {
+  const response = await fetch(CATALOG_URL);
+  if (!response.ok) {
+    throw new Error(
+      `Failed to list models: ${response.status} ${response.statusText}`,
+    );
+  }
+  const entries = await response.json();
+  const entry = entries.find((e) => e.path === MODEL_FILE);
+  if (!entry?.lfs?.oid) {
+    throw new Error(`No checksum published for ${MODEL_FILE}`);
+  }
+  return entry.lfs.oid.toLowerCase();
+}
+
+async function sha256Of(file) {
+  const hash = crypto.createHash("sha256");
+  await finished(fs.createReadStream(file).on("data", (d) => hash.update(d)));
+  return hash.digest("hex");
+}
 
 if (!fs.existsSync(TARGET_DIR)) {
   fs.mkdirSync(TARGET_DIR, { recursive: true });
@@ -24,6 +52,7 @@ if (fs.existsSync(TARGET_FILE)) {
 console.log(`Downloading model from ${MODEL_URL}...`);
 
 try {
+  const expected = await expectedSha256();
   const response = await fetch(MODEL_URL);
   if (!response.ok) {
     throw new Error(
@@ -31,13 +60,21 @@ try {
     );
   }
 
-  const fileStream = fs.createWriteStream(TARGET_FILE);
+  const fileStream = fs.createWriteStream(PART_FILE);
   await finished(Readable.fromWeb(response.body).pipe(fileStream));
-  console.log("Model downloaded successfully.");
+
+  const actual = await sha256Of(PART_FILE);
+  if (actual !== expected) {
+    throw new Error(
+      `Checksum mismatch for ${MODEL_FILE}: expected ${expected}, got ${actual}`,
+    );
+  }
+  fs.renameSync(PART_FILE, TARGET_FILE);
+  console.log("Model downloaded and verified.");
 } catch (error) {
   console.error("Error downloading model:", error);
-  if (fs.existsSync(TARGET_FILE)) {
-    fs.unlinkSync(TARGET_FILE); // Clean up partial file
+  if (fs.existsSync(PART_FILE)) {
+    fs.unlinkSync(PART_FILE); // Clean up partial file
   }
   process.exit(1);
 }
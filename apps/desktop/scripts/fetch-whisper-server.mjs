@@ -0,0 +1,87 @@
+import { execFileSync } from "node:child_process";
+import crypto from "node:crypto";
+import fs from "node:fs";
+import os from "node:os";
+import path from "node:path";
+import { Readable } from "node:stream";
+import { finished } from "node:stream/promises";
+import { fileURLToPath } from "node:url";
+
+const __filename = fileURLToPath(import.meta.url);
+const __dirname = path.dirname(__filename);
+
+// whisper-server.exe is too large to keep in git; fetch it from the same
+// whisper.cpp release as the whisper.dll and ggml DLLs in binaries/, which
+// it loads at runtime.
+const WHISPER_VERSION = "v1.8.3";
+const ARCHIVE_URL = `https://github.com/ggml-org/whisper.cpp/releases/download/${WHISPER_VERSION}/whisper-bin-x64.zip`;
+const BINARIES_DIR = path.join(__dirname, "../src-tauri/binaries");
+const TARGET_FILE = path.join(
+  BINARIES_DIR,
+  "whisper-server-x86_64-pc-windows-msvc.exe",
+);
+const BUNDLED_DLL = path.join(BINARIES_DIR, "whisper.dll");
+
+function findFile(dir, name) {
+  for (const entry of fs.readdirSync(dir, { withFileTypes: true })) {
+    const entryPath = path.join(dir, entry.name);
+    if (entry.isDirectory()) {
+      const found = findFile(entryPath, name);
+      if (found) return found;
+    } else if (entry.name.toLowerCase() === name) {
+      return entryPath;
+    }
+  }
+  return null;
+}
+
+function sha256Of(file) {
+  return crypto.createHash("sha256").update(fs.readFileSync(file)).digest("hex");
+}
+
+if (process.platform !== "win32") {
+  console.log("whisper-server.exe is only bundled on Windows, skipping.");
+  process.exit(0);
+}
+
+if (fs.existsSync(TARGET_FILE)) {
+  console.log("whisper-server already exists, skipping download.");
+  process.exit(0);
+}
+
+console.log(`Downloading whisper.cpp ${WHISPER_VERSION} from ${ARCHIVE_URL}...`);
+
+const workDir = fs.mkdtempSync(path.join(os.tmpdir(), "whisper-server-"));
+try {
+  const response = await fetch(ARCHIVE_URL);
+  if (!response.ok) {
+    throw new Error(
+      `Failed to download whisper.cpp: ${response.status} ${response.statusText}`,
+    );
+  }
+  const archive = path.join(workDir, "whisper-bin-x64.zip");
+  await finished(
+    Readable.fromWeb(response.body).pipe(fs.createWriteStream(archive)),
+  );
+  // Windows 10 and later ship bsdtar, which reads zip archives.
+  execFileSync("tar", ["-xf", archive, "-C", workDir]);
+
+  const server = findFile(workDir, "whisper-server.exe");
+  const dll = findFile(workDir, "whisper.dll");
+  if (!server || !dll) {
+    throw new Error("whisper-server.exe or whisper.dll missing from archive");
+  }
+  // A server from another release would not run against the bundled DLLs.
+  if (sha256Of(dll) !== sha256Of(BUNDLED_DLL)) {
+    throw new Error(
+      `whisper.dll in ${WHISPER_VERSION} differs from binaries/whisper.dll; update WHISPER_VERSION`,
+    );
+  }
+  fs.copyFileSync(server, TARGET_FILE);
+  console.log("whisper-server downloaded.");
+} catch (error) {
+  console.error("Error fetching whisper-server:", error);
+  process.exitCode = 1;
+} finally {
+  fs.rmSync(workDir, { recursive: true, force: true });
+}
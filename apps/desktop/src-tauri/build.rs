@@ -1,22 +1,29 @@
 fn main() {
-    // Ensure sidecar binary exists without target-triple suffix for dev mode.
+    // Ensure sidecar binaries exist without target-triple suffix for dev mode.
     // The shell plugin resolves `sidecar("whisper")` to `{exe_dir}/whisper.exe`,
     // but the source binary has the triple suffix. Copy it so dev mode works.
     #[cfg(target_os = "windows")]
     {
         let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR not set");
-        let src = std::path::Path::new(&manifest_dir)
-            .join("binaries")
-            .join("whisper-x86_64-pc-windows-msvc.exe");
 
         if let Ok(out_dir) = std::env::var("OUT_DIR") {
             // OUT_DIR is target/{profile}/build/{crate}-{hash}/out
             // Navigate up to target/{profile}
             let out_path = std::path::PathBuf::from(&out_dir);
             if let Some(profile_dir) = out_path.ancestors().nth(3) {
-                let dst = profile_dir.join("whisper.exe");
-                if src.exists() && !dst.exists() {
-                    let _ = std::fs::copy(&src, &dst);
+                for sidecar in &["whisper", "whisper-server"] {
+                    let src = std::path::Path::new(&manifest_dir)
+                        .join("binaries")
+                        .join(format!("{}-x86_64-pc-windows-msvc.exe", sidecar));
+                    let dst = profile_dir.join(format!("{}.exe", sidecar));
+                    if !src.exists() {
+                        println!(
+                            "cargo:warning={} is missing; run `node scripts/fetch-whisper-server.mjs`",
+                            src.display()
+                        );
+                    } else if !dst.exists() {
+                        let _ = std::fs::copy(&src, &dst);
+                    }
                 }
 
                 // Also copy required DLLs next to the exe
@@ -0,0 +1,630 @@
+use crate::error::AppError;
+use crate::whisper::WhisperManager;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use tauri::AppHandle;
+
+/// A transcribed segment. Times are relative to the start of the chunk.
+#[derive(Debug, Clone, Default)]
+pub struct AsrSegment {
+    pub text: String,
+    pub t_start_ms: i64,
+    pub t_end_ms: i64,
+    /// Mean probability of the segment's words, when the engine reports them.
+    pub confidence: Option<f64>,
+    pub language: Option<String>,
+    /// How sure the engine was of a detected `language`, 0..1.
+    pub language_probability: Option<f64>,
+    /// English translation of `text`, when the session translates its source.
+    pub translation: Option<String>,
+    /// Timed words of `text`, on the same clock as the segment; empty when
+    /// the engine gives no token timing.
+    pub words: Vec<AsrWord>,
+}
+
+/// A segment with only its text and times, for tests.
+#[cfg(test)]
+pub fn segment(text: &str, t_start_ms: i64, t_end_ms: i64) -> AsrSegment {
+    AsrSegment {
+        text: text.to_string(),
+        t_start_ms,
+        t_end_ms,
+        ..Default::default()
+    }
+}
+
+impl AsrSegment {
+    /// Move the segment and its words `offset_ms` later, ending no later
+    /// than `limit_ms`.
+    pub fn shift(&mut self, offset_ms: i64, limit_ms: i64) {
+        self.t_start_ms += offset_ms;
+        self.t_end_ms = limit_ms.min(self.t_end_ms + offset_ms);
+        for word in &mut self.words {
+            word.t_start_ms = limit_ms.min(word.t_start_ms + offset_ms);
+            word.t_end_ms = limit_ms.min(word.t_end_ms + offset_ms);
+        }
+    }
+}
+
+/// A word with its timing and how sure the engine was of it; carried on
+/// ASR_FINAL as `words`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AsrWord {
+    pub text: String,
+    pub t_start_ms: i64,
+    pub t_end_ms: i64,
+    /// 0..1; the mean of the word's token probabilities.
+    pub probability: f64,
+}
+
+/// Join whisper tokens into words. A token starting with a space begins a
+/// new word, any other continues the previous one; special tokens such as
+/// `[_BEG_]` or `<|en|>` are skipped.
+pub fn words_from_tokens(tokens: impl IntoIterator<Item = AsrWord>) -> Vec<AsrWord> {
+    let mut words: Vec<AsrWord> = Vec::new();
+    // Tokens merged into the last word, to average their probabilities.
+    let mut merged = 0;
+    for token in tokens {
+        let special = token.text.starts_with("[_") || token.text.starts_with("<|");
+        if special || token.text.is_empty() {
+            continue;
+        }
+        let starts_word = token.text.starts_with(char::is_whitespace);
+        match words.last_mut() {
+            Some(word) if !starts_word => {
+                word.text.push_str(&token.text);
+                word.t_end_ms = word.t_end_ms.max(token.t_end_ms);
+                word.probability =
+                    (word.probability * merged as f64 + token.probability) / (merged + 1) as f64;
+                merged += 1;
+            }
+            _ => {
+                words.push(AsrWord {
+                    text: token.text.trim_start().to_string(),
+                    ..token
+                });
+                merged = 1;
+            }
+        }
+    }
+    words.retain(|word| !word.text.trim().is_empty());
+    words
+}
+
+/// Segment confidence: the mean word probability, or `None` without words.
+pub fn words_confidence(words: &[AsrWord]) -> Option<f64> {
+    if words.is_empty() {
+        return None;
+    }
+    Some(words.iter().map(|word| word.probability).sum::<f64>() / words.len() as f64)
+}
+
+pub type AsrFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<AsrSegment>, AppError>> + Send + 'a>>;
+
+/// Speech recognition backend used by the transcription loop.
+/// Input is always 16kHz mono s16le PCM.
+pub trait AsrEngine: Send {
+    fn name(&self) -> &'static str;
+
+    fn transcribe<'a>(&'a mut self, samples: &'a [i16]) -> AsrFuture<'a>;
+
+    /// Decode later chunks in `language`, or detect it again with "auto".
+    fn set_language(&mut self, _language: &str) {}
+
+    /// Translate later chunks into English instead of transcribing them.
+    fn set_translate(&mut self, _translate: bool) {}
+
+    /// Release any processes or native resources held by the engine.
+    fn shutdown(&mut self) {}
+}
+
+/// Engine selector accepted by `start_transcription`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum AsrEngineKind {
+    /// whisper.cpp sidecar processes.
+    #[default]
+    Sidecar,
+    /// whisper.cpp linked into the app via whisper-rs.
+    InProcess,
+    /// Deterministic fake transcripts, for tests and CI.
+    Mock,
+}
+
+#[derive(Clone)]
+pub struct AsrConfig {
+    pub model_path: String,
+    pub language: String,
+    /// Decoding threads, from the hardware profile.
+    pub threads: usize,
+}
+
+pub async fn create_engine(
+    kind: AsrEngineKind,
+    app: &AppHandle,
+    config: AsrConfig,
+) -> Result<Box<dyn AsrEngine>, AppError> {
+    if kind != AsrEngineKind::Mock && !std::path::Path::new(&config.model_path).is_file() {
+        return Err(AppError::ModelMissing(format!(
+            "Whisper model not found: {}",
+            config.model_path
+        )));
+    }
+    match kind {
+        AsrEngineKind::Sidecar => {
+            let mut engine = SidecarEngine::new(app.clone(), config);
+            engine.whisper.start(app).await?;
+            Ok(Box::new(engine))
+        }
+        AsrEngineKind::InProcess => create_in_process_engine(config).await,
+        AsrEngineKind::Mock => Ok(Box::new(MockEngine::new(config.language))),
+    }
+}
+
+/// whisper.cpp model sizes, smallest first.
+pub const MODEL_SIZES: [&str; 5] = ["tiny", "base", "small", "medium", "large"];
+
+/// Next smaller `ggml-<size>[.<variant>].bin` next to `model_path` that
+/// exists on disk, preferring the same language variant.
+pub fn smaller_model(model_path: &str) -> Option<String> {
+    let path = std::path::Path::new(model_path);
+    let name = path.file_name()?.to_str()?;
+    let stem = name.strip_prefix("ggml-")?.strip_suffix(".bin")?;
+    let size = stem.split(['.', '-']).next()?;
+    let variant = &stem[size.len()..];
+    let index = MODEL_SIZES.iter().position(|s| *s == size)?;
+
+    MODEL_SIZES[..index].iter().rev().find_map(|smaller| {
+        [
+            format!("ggml-{}{}.bin", smaller, variant),
+            format!("ggml-{}.bin", smaller),
+        ]
+        .into_iter()
+        .map(|file| path.with_file_name(file))
+        .find(|candidate| candidate.is_file())
+        .map(|candidate| candidate.to_string_lossy().into_owned())
+    })
+}
+
+/// Fixed language reported for every segment, or `None` when auto-detecting.
+fn configured_language(language: &str) -> Option<String> {
+    if language == "auto" {
+        None
+    } else {
+        Some(language.to_string())
+    }
+}
+
+/// whisper.cpp running as a Tauri shell sidecar.
+pub struct SidecarEngine {
+    app: AppHandle,
+    whisper: WhisperManager,
+    language: Option<String>,
+}
+
+impl SidecarEngine {
+    pub fn new(app: AppHandle, config: AsrConfig) -> Self {
+        let language = configured_language(&config.language);
+        Self {
+            app,
+            whisper: WhisperManager::new(config.model_path, config.language, config.threads),
+            language,
+        }
+    }
+}
+
+impl AsrEngine for SidecarEngine {
+    fn name(&self) -> &'static str {
+        "sidecar"
+    }
+
+    fn transcribe<'a>(&'a mut self, samples: &'a [i16]) -> AsrFuture<'a> {
+        Box::pin(async move {
+            let results = self.whisper.transcribe(&self.app, samples).await?;
+            Ok(results
+                .into_iter()
+                .map(|result| {
+                    let words = words_from_tokens(result.tokens);
+                    AsrSegment {
+                        text: result.text,
+                        t_start_ms: result.t_start_ms,
+                        t_end_ms: result.t_end_ms,
+                        confidence: words_confidence(&words),
+                        language: result.language.or_else(|| self.language.clone()),
+                        language_probability: result.language_probability,
+                        translation: None,
+                        words,
+                    }
+                })
+                .collect())
+        })
+    }
+
+    fn set_language(&mut self, language: &str) {
+        self.language = configured_language(language);
+        self.whisper.set_language(language);
+    }
+
+    fn set_translate(&mut self, translate: bool) {
+        self.whisper.set_translate(translate);
+    }
+
+    fn shutdown(&mut self) {
+        self.whisper.shutdown();
+    }
+}
+
+#[cfg(feature = "in-process-asr")]
+async fn create_in_process_engine(config: AsrConfig) -> Result<Box<dyn AsrEngine>, AppError> {
+    // Model loading reads the whole ggml file; keep it off the async runtime.
+    let engine = tauri::async_runtime::spawn_blocking(move || InProcessEngine::load(config))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(AppError::ModelLoadFailed)?;
+    Ok(Box::new(engine))
+}
+
+#[cfg(not(feature = "in-process-asr"))]
+async fn create_in_process_engine(_config: AsrConfig) -> Result<Box<dyn AsrEngine>, AppError> {
+    Err(AppError::InvalidInput(
+        "In-process ASR is not available in this build (enable the `in-process-asr` feature)"
+            .to_string(),
+    ))
+}
+
+/// whisper.cpp linked in-process through whisper-rs.
+#[cfg(feature = "in-process-asr")]
+pub struct InProcessEngine {
+    context: whisper_rs::WhisperContext,
+    state: Option<whisper_rs::WhisperState>,
+    language: String,
+    threads: usize,
+    translate: bool,
+}
+
+#[cfg(feature = "in-process-asr")]
+impl InProcessEngine {
+    fn load(config: AsrConfig) -> Result<Self, String> {
+        let context = whisper_rs::WhisperContext::new_with_params(
+            &config.model_path,
+            whisper_rs::WhisperContextParameters::default(),
+        )
+        .map_err(|e| format!("Failed to load whisper model {}: {}", config.model_path, e))?;
+        let state = context
+            .create_state()
+            .map_err(|e| format!("Failed to create whisper state: {}", e))?;
+
+        Ok(Self {
+            context,
+            state: Some(state),
+            language: config.language,
+            threads: config.threads,
+            translate: false,
+        })
+    }
+}
+
+#[cfg(feature = "in-process-asr")]
+impl AsrEngine for InProcessEngine {
+    fn name(&self) -> &'static str {
+        "inProcess"
+    }
+
+    fn set_language(&mut self, language: &str) {
+        self.language = language.to_string();
+    }
+
+    fn set_translate(&mut self, translate: bool) {
+        self.translate = translate;
+    }
+
+    fn transcribe<'a>(&'a mut self, samples: &'a [i16]) -> AsrFuture<'a> {
+        Box::pin(async move {
+            if samples.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let mut state = match self.state.take() {
+                Some(state) => state,
+                None => self.context.create_state().map_err(|e| {
+                    AppError::ModelLoadFailed(format!("Failed to create whisper state: {}", e))
+                })?,
+            };
+            let language = self.language.clone();
+            let threads = self.threads;
+            let translate = self.translate;
+            let mut audio = vec![0.0_f32; samples.len()];
+            whisper_rs::convert_integer_to_float_audio(samples, &mut audio)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+
+            // Decoding is CPU-bound; run it on the blocking pool and hand the
+            // state back afterwards so buffers are reused across chunks.
+            let (state, result) = tauri::async_runtime::spawn_blocking(move || {
+                let result = run_full(&mut state, &language, threads, translate, &audio);
+                (state, result)
+            })
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+            self.state = Some(state);
+            result.map_err(AppError::TranscriptionFailed)
+        })
+    }
+}
+
+#[cfg(feature = "in-process-asr")]
+fn run_full(
+    state: &mut whisper_rs::WhisperState,
+    language: &str,
+    threads: usize,
+    translate: bool,
+    audio: &[f32],
+) -> Result<Vec<AsrSegment>, String> {
+    let mut params =
+        whisper_rs::FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
+    params.set_language(Some(language));
+    params.set_n_threads(threads as i32);
+    params.set_translate(translate);
+    params.set_print_progress(false);
+    params.set_print_realtime(false);
+    params.set_print_special(false);
+    params.set_print_timestamps(false);
+    params.set_token_timestamps(true);
+
+    state
+        .full(params, audio)
+        .map_err(|e| format!("Whisper inference failed: {}", e))?;
+
+    let language_id = state.full_lang_id_from_state().ok();
+    let detected_language = language_id
+        .and_then(whisper_rs::get_lang_str)
+        .map(|lang| lang.to_string());
+    // Only a detected language has a probability; this runs the encoder on
+    // the chunk's first 30 seconds again.
+    let language_probability = match language_id {
+        Some(id) if language == "auto" => state
+            .lang_detect(0, threads)
+            .ok()
+            .and_then(|(_, probabilities)| probabilities.get(id as usize).copied())
+            .map(f64::from),
+        _ => None,
+    };
+    let segment_count = state.full_n_segments().map_err(|e| e.to_string())?;
+
+    let mut segments = Vec::new();
+    for index in 0..segment_count {
+        let text = state
+            .full_get_segment_text(index)
+            .map_err(|e| e.to_string())?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        // whisper.cpp segment times are in centiseconds.
+        let t0 = state
+            .full_get_segment_t0(index)
+            .map_err(|e| e.to_string())?;
+        let t1 = state
+            .full_get_segment_t1(index)
+            .map_err(|e| e.to_string())?;
+
+        let token_count = state.full_n_tokens(index).map_err(|e| e.to_string())?;
+        let mut tokens = Vec::new();
+        // A multi-byte character can be split across tokens; hold the bytes
+        // until they decode, timing the result from the first token.
+        let mut pending: Vec<u8> = Vec::new();
+        let mut pending_start_ms = None;
+        for token in 0..token_count {
+            let data = state
+                .full_get_token_data(index, token)
+                .map_err(|e| e.to_string())?;
+            let bytes = state
+                .full_get_token_bytes(index, token)
+                .map_err(|e| e.to_string())?;
+            pending.extend_from_slice(&bytes);
+            let t_start_ms = *pending_start_ms.get_or_insert(data.t0 * 10);
+            let Ok(text) = std::str::from_utf8(&pending) else {
+                continue;
+            };
+            tokens.push(AsrWord {
+                text: text.to_string(),
+                t_start_ms,
+                t_end_ms: data.t1 * 10,
+                probability: data.p as f64,
+            });
+            pending.clear();
+            pending_start_ms = None;
+        }
+        let words = words_from_tokens(tokens);
+
+        segments.push(AsrSegment {
+            text: text.trim().to_string(),
+            t_start_ms: t0 * 10,
+            t_end_ms: t1 * 10,
+            confidence: words_confidence(&words),
+            language: detected_language.clone(),
+            language_probability,
+            translation: None,
+            words,
+        });
+    }
+
+    Ok(segments)
+}
+
+/// Deterministic engine that never touches a model. Every chunk containing
+/// speech-level audio yields one numbered segment spanning it.
+pub struct MockEngine {
+    language: String,
+    next_index: u32,
+    translate: bool,
+}
+
+impl MockEngine {
+    pub fn new(language: String) -> Self {
+        let language = configured_language(&language).unwrap_or_else(|| "en".to_string());
+        Self {
+            language,
+            next_index: 0,
+            translate: false,
+        }
+    }
+}
+
+impl AsrEngine for MockEngine {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn set_language(&mut self, language: &str) {
+        if let Some(language) = configured_language(language) {
+            self.language = language;
+        }
+    }
+
+    fn set_translate(&mut self, translate: bool) {
+        self.translate = translate;
+    }
+
+    fn transcribe<'a>(&'a mut self, samples: &'a [i16]) -> AsrFuture<'a> {
+        Box::pin(async move {
+            if !crate::audio::contains_speech(samples) {
+                return Ok(Vec::new());
+            }
+
+            // A translation is of the chunk just transcribed.
+            let text = if self.translate {
+                format!("Mock translation segment {}.", self.next_index)
+            } else {
+                self.next_index += 1;
+                format!("Mock transcript segment {}.", self.next_index)
+            };
+            let t_end_ms = (samples.len() as i64 * 1000) / 16000;
+            // Spread the words evenly over the chunk.
+            let count = text.split_whitespace().count() as i64;
+            let words = text
+                .split_whitespace()
+                .enumerate()
+                .map(|(index, word)| AsrWord {
+                    text: word.to_string(),
+                    t_start_ms: t_end_ms * index as i64 / count,
+                    t_end_ms: t_end_ms * (index as i64 + 1) / count,
+                    probability: 1.0,
+                })
+                .collect();
+            Ok(vec![AsrSegment {
+                text,
+                t_start_ms: 0,
+                t_end_ms,
+                confidence: Some(1.0),
+                language: Some(self.language.clone()),
+                language_probability: None,
+                translation: None,
+                words,
+            }])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{VadConfig, VoiceActivitySegmenter};
+    use crate::stitching::TranscriptStitcher;
+
+    /// `speech` seconds of a low voiced tone between `silence` seconds of
+    /// silence, repeated `count` times.
+    fn utterances(count: usize, speech: f64, silence: f64) -> Vec<i16> {
+        let silent = vec![0_i16; (silence * 16_000.0) as usize];
+        let mut samples = silent.clone();
+        for _ in 0..count {
+            samples.extend((0..(speech * 16_000.0) as usize).map(|n| {
+                let t = n as f64 / 16_000.0;
+                (8_000.0 * (std::f64::consts::TAU * 180.0 * t).sin()) as i16
+            }));
+            samples.extend_from_slice(&silent);
+        }
+        samples
+    }
+
+    #[test]
+    fn mock_engine_transcribes_vad_utterances_through_the_stitcher() {
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let mut stitcher = TranscriptStitcher::new();
+        let mut engine = MockEngine::new("auto".to_string());
+
+        let mut finals = Vec::new();
+        for block in utterances(2, 1.5, 1.0).chunks(1_600) {
+            for utterance in vad.push(block) {
+                let window = stitcher.extend_with_context(utterance);
+                let segments =
+                    tauri::async_runtime::block_on(engine.transcribe(&window.samples)).unwrap();
+                finals.extend(stitcher.reconcile(&window, segments));
+            }
+        }
+
+        let texts: Vec<&str> = finals.iter().map(|f| f.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["Mock transcript segment 1.", "Mock transcript segment 2."]
+        );
+        // Each final spans its utterance, pre-roll and trailing silence
+        // included, on the capture clock.
+        assert!((finals[0].t_start_ms - 800).abs() <= 60, "{:?}", finals[0]);
+        assert!((finals[0].t_end_ms - 3_100).abs() <= 60, "{:?}", finals[0]);
+        assert!(
+            (finals[1].t_start_ms - 3_300).abs() <= 60,
+            "{:?}",
+            finals[1]
+        );
+        assert_eq!(finals[1].language.as_deref(), Some("en"));
+        assert!(finals
+            .iter()
+            .all(|f| f.words.first().map(|w| w.t_start_ms) == Some(f.t_start_ms)));
+    }
+
+    #[test]
+    fn mock_engine_skips_silence() {
+        let mut engine = MockEngine::new("en".to_string());
+        let silence = vec![0_i16; 16_000];
+        let segments = tauri::async_runtime::block_on(engine.transcribe(&silence)).unwrap();
+        assert!(segments.is_empty());
+    }
+
+    fn token(text: &str, t_start_ms: i64, t_end_ms: i64, probability: f64) -> AsrWord {
+        AsrWord {
+            text: text.to_string(),
+            t_start_ms,
+            t_end_ms,
+            probability,
+        }
+    }
+
+    #[test]
+    fn tokens_join_into_words() {
+        let words = words_from_tokens([
+            token("[_BEG_]", 0, 0, 1.0),
+            token(" Hel", 0, 100, 0.9),
+            token("lo", 100, 200, 0.5),
+            token(",", 200, 210, 1.0),
+            token("<|en|>", 210, 210, 1.0),
+            token(" ", 210, 300, 0.2),
+            token("", 300, 300, 0.1),
+            token(" world", 300, 500, 0.8),
+        ]);
+
+        let texts: Vec<&str> = words.iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(texts, ["Hello,", "world"]);
+        assert_eq!((words[0].t_start_ms, words[0].t_end_ms), (0, 210));
+        assert!((words[0].probability - 0.8).abs() < 1e-9);
+        assert_eq!(words[1], token("world", 300, 500, 0.8));
+        assert!((words_confidence(&words).unwrap() - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn text_without_word_tokens_has_no_words() {
+        assert!(words_from_tokens([token("[_BEG_]", 0, 0, 1.0)]).is_empty());
+        assert_eq!(words_confidence(&[]), None);
+    }
+}
@@ -1,86 +1,517 @@
+use crate::devices::{self, DeviceSelection};
+#[cfg(target_os = "linux")]
+use crate::monitor;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{FromSample, Sample, SampleFormat, StreamConfig, SupportedStreamConfig};
-use std::sync::{Arc, Mutex};
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Wrapper to make cpal::Stream Send-safe.
 /// The stream is only ever accessed behind a Mutex and never
 /// shared directly across threads.
 #[allow(dead_code)]
-struct SendStream(Option<cpal::Stream>);
+struct SendStream(Option<StreamHandle>);
+
+/// Whatever keeps a source's capture running; dropping it stops capture.
+#[allow(dead_code)]
+enum StreamHandle {
+    Cpal(cpal::Stream),
+    #[cfg(target_os = "linux")]
+    Monitor(MonitorStream),
+}
+
+/// Where a source's audio comes from.
+enum SourceDevice {
+    Cpal(cpal::Device),
+    /// PulseAudio/PipeWire monitor source, by name.
+    #[cfg(target_os = "linux")]
+    Monitor(String),
+}
 
 // SAFETY: cpal::Stream is not Send on all platforms, but we only
-// access it behind a Mutex in Tauri's managed state. The stream
-// callbacks run on cpal's own audio thread and only touch Arc<Mutex>
-// shared data, so this is safe in practice.
+// access it behind a Mutex in Tauri's managed state, or move it there
+// from the blocking thread that reopened it. The stream callbacks run on
+// cpal's own audio thread and only touch their ring producer and atomics,
+// so this is safe in practice.
 unsafe impl Send for SendStream {}
 
+/// Seconds of 16kHz audio each source can hold before the loop drains it.
+const RING_CAPACITY_SECONDS: usize = 30;
+/// Device frames handled per pass through the callback's scratch buffers.
+const CALLBACK_BLOCK_FRAMES: usize = 1024;
+/// A microphone that delivers nothing for this long is treated as lost.
+/// Loopback is exempt: some backends send no packets while nothing plays.
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+/// How often to look for default-device changes and returning devices.
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Delay between attempts to reopen an interrupted source.
+const REOPEN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// How far a source may trail the session clock, covering device and ring
+/// buffering, before the shortfall is filled with silence.
+const CLOCK_LAG_SLACK_SAMPLES: u64 = TARGET_SAMPLE_RATE as u64 / 5;
+/// How far a source may run ahead of the session clock before the excess
+/// is dropped.
+const CLOCK_LEAD_SLACK_SAMPLES: u64 = TARGET_SAMPLE_RATE as u64 / 20;
+
 pub struct AudioDrain {
     pub microphone_samples: Vec<i16>,
     pub system_samples: Vec<i16>,
+    /// Samples the callbacks dropped since the previous drain because the
+    /// ring was full.
+    pub microphone_dropped: u64,
+    pub system_dropped: u64,
+}
+
+impl AudioDrain {
+    /// Throw the drained audio away, e.g. while paused. Its length stays as
+    /// dropped samples so every timeline moves past it.
+    pub fn discard(&mut self) {
+        self.microphone_dropped += std::mem::take(&mut self.microphone_samples).len() as u64;
+        self.system_dropped += std::mem::take(&mut self.system_samples).len() as u64;
+    }
+}
+
+/// State shared between one source's callback and `AudioCapture`.
+/// Only atomics, so the real-time thread never blocks on it.
+struct SourceMeter {
+    /// Live level as `f32` bits.
+    level: AtomicU32,
+    /// Total samples dropped on overflow.
+    dropped: AtomicU64,
+    /// Total samples produced, pushed or dropped; stalls stop it advancing.
+    produced: AtomicU64,
+    /// Set by the error callback when the stream reports a failure.
+    failed: AtomicBool,
+}
+
+impl SourceMeter {
+    fn new() -> Self {
+        Self {
+            level: AtomicU32::new(0.0_f32.to_bits()),
+            dropped: AtomicU64::new(0),
+            produced: AtomicU64::new(0),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+}
+
+/// Consumer side of one source's capture ring.
+struct SourceReader {
+    consumer: HeapCons<i16>,
+    meter: Arc<SourceMeter>,
+    reported_dropped: u64,
+}
+
+impl SourceReader {
+    fn drain_into(&mut self, samples: &mut Vec<i16>) -> u64 {
+        let start = samples.len();
+        samples.resize(start + self.consumer.occupied_len(), 0);
+        let read = self.consumer.pop_slice(&mut samples[start..]);
+        samples.truncate(start + read);
+
+        let dropped = self.meter.dropped.load(Ordering::Relaxed);
+        let newly_dropped = dropped - self.reported_dropped;
+        self.reported_dropped = dropped;
+        newly_dropped
+    }
+}
+
+/// Producer side handed to the cpal callback.
+struct CaptureSink {
+    producer: HeapProd<i16>,
+    meter: Arc<SourceMeter>,
+}
+
+fn capture_ring() -> (CaptureSink, SourceReader) {
+    let ring = HeapRb::<i16>::new(TARGET_SAMPLE_RATE as usize * RING_CAPACITY_SECONDS);
+    let (producer, consumer) = ring.split();
+    let meter = Arc::new(SourceMeter::new());
+    (
+        CaptureSink {
+            producer,
+            meter: meter.clone(),
+        },
+        SourceReader {
+            consumer,
+            meter,
+            reported_dropped: 0,
+        },
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceRole {
+    Microphone,
+    Loopback,
+}
+
+impl SourceRole {
+    pub fn label(self) -> &'static str {
+        match self {
+            SourceRole::Microphone => "Microphone",
+            SourceRole::Loopback => "System audio",
+        }
+    }
+}
+
+/// Something that happened to a capture stream, for the UI.
+pub enum CaptureNotice {
+    Interrupted {
+        role: SourceRole,
+        reason: String,
+    },
+    Recovered {
+        role: SourceRole,
+        device: String,
+        gap_ms: i64,
+    },
+}
+
+/// An open stream on one device plus its bookkeeping.
+struct SourceCapture {
+    role: SourceRole,
+    stream: SendStream,
+    reader: SourceReader,
+    device_name: String,
+    /// Capture start, to keep the sample timeline in step with the clock.
+    started: Instant,
+    /// Samples placed on the timeline so far: drained, dropped or padded.
+    delivered: u64,
+    /// Audio returned ahead of the ring on the next drain: what was left in
+    /// a replaced stream's ring, then silence covering the gap.
+    prefix: Vec<i16>,
+    prefix_dropped: u64,
+    last_produced: u64,
+    last_progress: Instant,
+    /// Set while the stream is down.
+    interrupted_since: Option<Instant>,
+    next_reopen: Instant,
+}
+
+impl SourceCapture {
+    fn open(
+        host: &cpal::Host,
+        role: SourceRole,
+        selection: &DeviceSelection,
+        started: Instant,
+    ) -> Result<Self, String> {
+        let OpenedStream {
+            stream,
+            reader,
+            device_name,
+        } = OpenedStream::open(host, role, selection)?;
+        let now = Instant::now();
+        Ok(Self {
+            role,
+            stream,
+            reader,
+            device_name,
+            started,
+            delivered: 0,
+            prefix: Vec::new(),
+            prefix_dropped: 0,
+            last_produced: 0,
+            last_progress: now,
+            interrupted_since: None,
+            next_reopen: now,
+        })
+    }
+
+    fn drain(&mut self) -> (Vec<i16>, u64) {
+        let mut samples = std::mem::take(&mut self.prefix);
+        let from_ring = samples.len();
+        let dropped = self.reader.drain_into(&mut samples);
+        self.delivered += (samples.len() - from_ring) as u64 + dropped;
+        self.follow_session_clock(&mut samples, from_ring);
+        (samples, dropped + std::mem::take(&mut self.prefix_dropped))
+    }
+
+    /// Keep the source on the session clock so both sources' sample
+    /// positions line up. Loopback sends nothing while nothing plays on
+    /// some backends, and device clocks drift apart; a source that falls
+    /// behind gets silence ahead of the audio just drained, and one that
+    /// runs ahead loses the newest excess.
+    fn follow_session_clock(&mut self, samples: &mut Vec<i16>, from_ring: usize) {
+        let expected = (self.started.elapsed().as_secs_f64() * TARGET_SAMPLE_RATE as f64) as u64;
+        if self.delivered + CLOCK_LAG_SLACK_SAMPLES < expected {
+            let missing = expected - CLOCK_LAG_SLACK_SAMPLES - self.delivered;
+            samples.splice(
+                from_ring..from_ring,
+                std::iter::repeat(0).take(missing as usize),
+            );
+            self.delivered += missing;
+        } else if self.delivered > expected + CLOCK_LEAD_SLACK_SAMPLES {
+            let excess = (self.delivered - expected - CLOCK_LEAD_SLACK_SAMPLES)
+                .min((samples.len() - from_ring) as u64);
+            samples.truncate(samples.len() - excess as usize);
+            self.delivered -= excess;
+        }
+    }
+
+    /// Why the stream should be rebuilt, if it should.
+    fn failure(&mut self, now: Instant) -> Option<String> {
+        if self.reader.meter.failed.load(Ordering::Relaxed) {
+            return Some("the audio device reported an error".to_string());
+        }
+        let produced = self.reader.meter.produced.load(Ordering::Relaxed);
+        if produced != self.last_produced {
+            self.last_produced = produced;
+            self.last_progress = now;
+            return None;
+        }
+        if self.role == SourceRole::Microphone
+            && now.duration_since(self.last_progress) >= STALL_TIMEOUT
+        {
+            return Some("the device stopped delivering audio".to_string());
+        }
+        None
+    }
+
+    /// Replace the stream with `opened`. Audio still queued in the old ring
+    /// is kept, and the time the source was down is filled with silence
+    /// because downstream timestamps are derived from sample positions.
+    /// Returns the silence inserted, in samples.
+    fn replace_stream(&mut self, opened: OpenedStream) -> u64 {
+        // Stop the old callback before taking over its leftovers.
+        self.stream = SendStream(None);
+        let before = self.prefix.len();
+        let dropped = self.reader.drain_into(&mut self.prefix);
+        self.delivered += (self.prefix.len() - before) as u64 + dropped;
+        self.prefix_dropped += dropped;
+        self.reader = opened.reader;
+
+        // The new stream has been running since it was opened; what it has
+        // captured already is not part of the gap.
+        let expected = (self.started.elapsed().as_secs_f64() * TARGET_SAMPLE_RATE as f64) as u64;
+        let queued = self.reader.consumer.occupied_len() as u64;
+        let gap = expected.saturating_sub(self.delivered + queued);
+        self.prefix.resize(self.prefix.len() + gap as usize, 0);
+        self.delivered += gap;
+
+        let now = Instant::now();
+        self.stream = opened.stream;
+        self.device_name = opened.device_name;
+        self.interrupted_since = None;
+        self.last_produced = 0;
+        self.last_progress = now;
+        gap
+    }
+}
+
+/// A running stream on a source's preferred device, not yet attached to
+/// the capture.
+struct OpenedStream {
+    stream: SendStream,
+    reader: SourceReader,
+    device_name: String,
+}
+
+impl OpenedStream {
+    fn open(
+        host: &cpal::Host,
+        role: SourceRole,
+        selection: &DeviceSelection,
+    ) -> Result<Self, String> {
+        let (device, device_name, fell_back) = resolve_source_device(host, role, selection)?;
+        if fell_back {
+            log::warn!(
+                "Saved {} device is not connected; using the system default {}",
+                role.label().to_lowercase(),
+                device_name
+            );
+        }
+        let (sink, reader) = capture_ring();
+        let stream = start_source_capture(device, sink, role == SourceRole::Loopback)?;
+        Ok(Self {
+            stream: SendStream(Some(stream)),
+            reader,
+            device_name,
+        })
+    }
+}
+
+/// The device work of one health check: looking for device changes and
+/// reopening sources. Enumerating devices can block for a while (and runs
+/// `pactl` on Linux), so it runs without the capture.
+pub struct HealthCheck {
+    selection: DeviceSelection,
+    poll_devices: bool,
+    /// Each source's role, current device and whether it is due a reopen.
+    sources: Vec<(SourceRole, String, bool)>,
+    notices: Vec<CaptureNotice>,
+}
+
+impl HealthCheck {
+    /// Open replacement streams for sources that are down or whose
+    /// preferred device changed.
+    pub fn run(self) -> HealthUpdate {
+        let host = cpal::default_host();
+        let mut opened = Vec::new();
+        for (role, device_name, due) in &self.sources {
+            let mut reopen = *due;
+            if !reopen && self.poll_devices {
+                let preferred =
+                    resolve_source_device(&host, *role, &self.selection).map(|(_, name, _)| name);
+                if matches!(&preferred, Ok(name) if name != device_name) {
+                    log::info!("{} device changed from {}", role.label(), device_name);
+                    reopen = true;
+                }
+            }
+            if reopen {
+                opened.push((*role, OpenedStream::open(&host, *role, &self.selection)));
+            }
+        }
+        HealthUpdate {
+            opened,
+            notices: self.notices,
+        }
+    }
+}
+
+/// Result of `HealthCheck::run`, to hand back to `AudioCapture`.
+pub struct HealthUpdate {
+    opened: Vec<(SourceRole, Result<OpenedStream, String>)>,
+    notices: Vec<CaptureNotice>,
+}
+
+/// The device a source should capture from: the saved choice when it is
+/// connected, otherwise the system default. The flag is set when a saved
+/// device had to be replaced by the default.
+fn resolve_source_device(
+    host: &cpal::Host,
+    role: SourceRole,
+    selection: &DeviceSelection,
+) -> Result<(SourceDevice, String, bool), String> {
+    // ALSA cannot record an output; use the sink's monitor source instead.
+    #[cfg(target_os = "linux")]
+    if role == SourceRole::Loopback {
+        return resolve_monitor_source(host, selection.loopback_id.as_deref());
+    }
+
+    let (saved, default) = match role {
+        SourceRole::Microphone => (
+            selection.microphone_id.as_deref(),
+            host.default_input_device(),
+        ),
+        SourceRole::Loopback => (
+            selection.loopback_id.as_deref(),
+            host.default_output_device(),
+        ),
+    };
+    let selected = saved.and_then(|id| devices::find_device(host, id));
+    let fell_back = saved.is_some() && selected.is_none();
+    let device = selected.or(default).ok_or_else(|| match role {
+        SourceRole::Microphone => "No input device available".to_string(),
+        SourceRole::Loopback => "No default output device found for loopback capture".to_string(),
+    })?;
+    let name = device
+        .name()
+        .unwrap_or_else(|_| "Unknown device".to_string());
+    Ok((SourceDevice::Cpal(device), name, fell_back))
+}
+
+/// Linux loopback: a saved monitor source or cpal device when connected,
+/// otherwise the monitor of the default sink.
+#[cfg(target_os = "linux")]
+fn resolve_monitor_source(
+    host: &cpal::Host,
+    saved: Option<&str>,
+) -> Result<(SourceDevice, String, bool), String> {
+    if let Some(id) = saved {
+        if let Some(source) = id.strip_prefix(devices::MONITOR_ID_PREFIX) {
+            if monitor::list_monitor_sources()?.iter().any(|s| s == source) {
+                return Ok((
+                    SourceDevice::Monitor(source.to_string()),
+                    source.to_string(),
+                    false,
+                ));
+            }
+        } else if let Some(device) = devices::find_device(host, id) {
+            let name = device
+                .name()
+                .unwrap_or_else(|_| "Unknown device".to_string());
+            return Ok((SourceDevice::Cpal(device), name, false));
+        }
+    }
+
+    let source = monitor::default_monitor_source()?;
+    Ok((
+        SourceDevice::Monitor(source.clone()),
+        source,
+        saved.is_some(),
+    ))
 }
 
 /// Cross-platform audio capture using cpal.
 /// Captures 16kHz mono s16le PCM from the default microphone,
-/// and best-effort system loopback on Windows via default output device.
+/// and best-effort system loopback on Windows via default output device,
+/// or on Linux via the default sink's PulseAudio/PipeWire monitor source.
+///
+/// Each source writes into its own pre-allocated SPSC ring; the callbacks
+/// neither lock nor allocate. Sources whose stream fails, stalls or whose
+/// device is replaced are reopened by the health check.
 pub struct AudioCapture {
-    mic_stream: SendStream,
-    system_stream: SendStream,
-    mic_buffer: Arc<Mutex<Vec<i16>>>,
-    system_buffer: Arc<Mutex<Vec<i16>>>,
-    mic_level: Arc<Mutex<f32>>,
-    system_level: Arc<Mutex<f32>>,
+    mic: Option<SourceCapture>,
+    system: Option<SourceCapture>,
+    selection: DeviceSelection,
     system_capture_enabled: bool,
+    /// Why system audio is not being captured despite being requested.
+    loopback_error: Option<String>,
+    next_device_poll: Instant,
 }
 
 impl AudioCapture {
     pub fn new() -> Self {
         Self {
-            mic_stream: SendStream(None),
-            system_stream: SendStream(None),
-            mic_buffer: Arc::new(Mutex::new(Vec::new())),
-            system_buffer: Arc::new(Mutex::new(Vec::new())),
-            mic_level: Arc::new(Mutex::new(0.0)),
-            system_level: Arc::new(Mutex::new(0.0)),
+            mic: None,
+            system: None,
+            selection: DeviceSelection::default(),
             system_capture_enabled: false,
+            loopback_error: None,
+            next_device_poll: Instant::now(),
         }
     }
 
-    pub fn start(&mut self, enable_system_audio: bool) -> Result<(), String> {
+    pub fn start(
+        &mut self,
+        enable_system_audio: bool,
+        selection: &DeviceSelection,
+    ) -> Result<(), String> {
         self.stop();
         self.system_capture_enabled = enable_system_audio;
+        self.selection = selection.clone();
+        self.next_device_poll = Instant::now() + DEVICE_POLL_INTERVAL;
 
         let host = cpal::default_host();
-        let mic_device = host
-            .default_input_device()
-            .ok_or("No input device available")?;
-        self.mic_stream = SendStream(Some(start_device_capture(
-            mic_device,
-            self.mic_buffer.clone(),
-            self.mic_level.clone(),
-            false,
-        )?));
+        let started = Instant::now();
+        self.mic = Some(SourceCapture::open(
+            &host,
+            SourceRole::Microphone,
+            selection,
+            started,
+        )?);
 
         if enable_system_audio {
-            if let Some(output_device) = host.default_output_device() {
-                match start_device_capture(
-                    output_device,
-                    self.system_buffer.clone(),
-                    self.system_level.clone(),
-                    true,
-                ) {
-                    Ok(stream) => {
-                        self.system_stream = SendStream(Some(stream));
-                    }
-                    Err(error) => {
-                        // Keep mic capture running even if loopback is unavailable.
-                        log::warn!(
-                            "System loopback unavailable; continuing mic-only: {}",
-                            error
-                        );
-                    }
+            match SourceCapture::open(&host, SourceRole::Loopback, selection, started) {
+                Ok(system) => {
+                    self.system = Some(system);
+                }
+                Err(error) => {
+                    // Keep mic capture running even if loopback is unavailable.
+                    log::warn!(
+                        "System loopback unavailable; continuing mic-only: {}",
+                        error
+                    );
+                    self.loopback_error = Some(error);
                 }
-            } else {
-                log::warn!("No default output device found for loopback capture");
             }
         }
 
@@ -88,45 +519,271 @@ impl AudioCapture {
     }
 
     pub fn stop(&mut self) {
-        self.mic_stream = SendStream(None);
-        self.system_stream = SendStream(None);
+        // Dropping a source stops its stream before its ring is freed.
+        self.mic = None;
+        self.system = None;
         self.system_capture_enabled = false;
-        if let Ok(mut mic) = self.mic_buffer.lock() {
-            mic.clear();
-        }
-        if let Ok(mut system) = self.system_buffer.lock() {
-            system.clear();
+        self.loopback_error = None;
+    }
+
+    /// Stop capture and return whatever was still buffered, so the end of a
+    /// session can be transcribed.
+    pub fn finish(&mut self) -> AudioDrain {
+        // Stop the callbacks first so nothing lands in the rings after the
+        // last drain.
+        for source in [self.mic.as_mut(), self.system.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            source.stream = SendStream(None);
         }
+        let drained = self.drain_buffers();
+        self.stop();
+        drained
     }
 
-    /// Drain both buffers and return accumulated samples by source.
-    pub fn drain_buffers(&self) -> AudioDrain {
-        let mut mic = self.mic_buffer.lock().unwrap_or_else(|e| e.into_inner());
-        let mut system = self.system_buffer.lock().unwrap_or_else(|e| e.into_inner());
+    /// Set when system audio was requested but could not be opened.
+    pub fn loopback_error(&self) -> Option<&str> {
+        self.loopback_error.as_deref()
+    }
 
-        let microphone_samples = mic.clone();
-        let system_samples = system.clone();
-        mic.clear();
-        system.clear();
+    /// Drain both rings and return accumulated samples by source.
+    pub fn drain_buffers(&mut self) -> AudioDrain {
+        let (microphone_samples, microphone_dropped) = self
+            .mic
+            .as_mut()
+            .map(SourceCapture::drain)
+            .unwrap_or_default();
+        let (system_samples, system_dropped) = self
+            .system
+            .as_mut()
+            .map(SourceCapture::drain)
+            .unwrap_or_default();
 
         AudioDrain {
             microphone_samples,
             system_samples,
+            microphone_dropped,
+            system_dropped,
+        }
+    }
+
+    /// Detect failed or stalled streams and decide what device work is
+    /// due. Cheap; run the returned check without holding the capture and
+    /// pass its result to `finish_health_check`. Call regularly while
+    /// recording.
+    pub fn begin_health_check(&mut self) -> HealthCheck {
+        let now = Instant::now();
+        let mut notices = Vec::new();
+
+        // Device changes: the default moved, or a saved device came back.
+        let poll_devices = now >= self.next_device_poll;
+        if poll_devices {
+            self.next_device_poll = now + DEVICE_POLL_INTERVAL;
+        }
+
+        let mut sources = Vec::new();
+        for source in [self.mic.as_mut(), self.system.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            if source.interrupted_since.is_none() {
+                if let Some(reason) = source.failure(now) {
+                    log::warn!("{} capture interrupted: {}", source.role.label(), reason);
+                    source.stream = SendStream(None);
+                    source.interrupted_since = Some(now);
+                    source.next_reopen = now;
+                    notices.push(CaptureNotice::Interrupted {
+                        role: source.role,
+                        reason,
+                    });
+                }
+            }
+            let due = source.interrupted_since.is_some() && now >= source.next_reopen;
+            sources.push((source.role, source.device_name.clone(), due));
+        }
+
+        HealthCheck {
+            selection: self.selection.clone(),
+            poll_devices,
+            sources,
+            notices,
+        }
+    }
+
+    /// Attach the streams a health check reopened. Returns what the UI
+    /// should be told.
+    pub fn finish_health_check(&mut self, update: HealthUpdate) -> Vec<CaptureNotice> {
+        let now = Instant::now();
+        let mut notices = update.notices;
+        for (role, opened) in update.opened {
+            let source = match role {
+                SourceRole::Microphone => self.mic.as_mut(),
+                SourceRole::Loopback => self.system.as_mut(),
+            };
+            // Capture stopped meanwhile; the new stream is dropped.
+            let Some(source) = source else {
+                continue;
+            };
+            match opened {
+                Ok(opened) => {
+                    let down_for = source
+                        .interrupted_since
+                        .map(|since| since.elapsed())
+                        .unwrap_or_default();
+                    let gap = source.replace_stream(opened);
+                    log::info!(
+                        "{} capture resumed on {} after {:?}",
+                        source.role.label(),
+                        source.device_name,
+                        down_for
+                    );
+                    notices.push(CaptureNotice::Recovered {
+                        role: source.role,
+                        device: source.device_name.clone(),
+                        gap_ms: gap as i64 * 1000 / TARGET_SAMPLE_RATE as i64,
+                    });
+                }
+                Err(error) => {
+                    log::warn!(
+                        "Reopening {} capture failed: {}",
+                        source.role.label().to_lowercase(),
+                        error
+                    );
+                    // A source that changed device keeps its old stream
+                    // until the next poll.
+                    if source.interrupted_since.is_some() {
+                        source.next_reopen = now + REOPEN_RETRY_INTERVAL;
+                    }
+                }
+            }
         }
+        notices
     }
 
     /// Get the current active level (0-1), max of mic/system.
     pub fn get_level(&self) -> f32 {
-        let mic = *self.mic_level.lock().unwrap_or_else(|e| e.into_inner());
-        let system = *self.system_level.lock().unwrap_or_else(|e| e.into_inner());
-        mic.max(system)
+        let level = |source: &Option<SourceCapture>| {
+            source
+                .as_ref()
+                .filter(|s| s.interrupted_since.is_none())
+                .map_or(0.0, |s| s.reader.meter.level())
+        };
+        level(&self.mic).max(level(&self.system))
     }
 }
 
+fn start_source_capture(
+    device: SourceDevice,
+    sink: CaptureSink,
+    allow_output_fallback: bool,
+) -> Result<StreamHandle, String> {
+    match device {
+        SourceDevice::Cpal(device) => {
+            start_device_capture(device, sink, allow_output_fallback).map(StreamHandle::Cpal)
+        }
+        #[cfg(target_os = "linux")]
+        SourceDevice::Monitor(source) => {
+            start_monitor_capture(&source, sink).map(StreamHandle::Monitor)
+        }
+    }
+}
+
+/// A `parec` process recording a monitor source, and the thread moving its
+/// output into the source's ring.
+#[cfg(target_os = "linux")]
+struct MonitorStream {
+    child: std::process::Child,
+    reader: Option<std::thread::JoinHandle<()>>,
+    stopping: Arc<AtomicBool>,
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for MonitorStream {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn start_monitor_capture(source: &str, mut sink: CaptureSink) -> Result<MonitorStream, String> {
+    use std::io::Read;
+
+    let mut child = monitor::spawn_recorder(source)?;
+    let mut stdout = child.stdout.take().ok_or("Failed to read parec output")?;
+    let source = source.to_string();
+    let stopping = Arc::new(AtomicBool::new(false));
+    let reader_stopping = stopping.clone();
+
+    let reader = std::thread::spawn(move || {
+        // parec already delivers 16kHz mono s16le; 100ms per read.
+        let mut bytes = [0u8; 3200];
+        let mut filled = 0;
+        let mut samples: Vec<i16> = Vec::with_capacity(bytes.len() / 2);
+        loop {
+            let read = match stdout.read(&mut bytes[filled..]) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            filled += read;
+            let whole = filled - filled % 2;
+
+            samples.clear();
+            samples.extend(
+                bytes[..whole]
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]])),
+            );
+            bytes.copy_within(whole..filled, 0);
+            filled -= whole;
+            if samples.is_empty() {
+                continue;
+            }
+
+            let sum_sq: f32 = samples
+                .iter()
+                .map(|&sample| {
+                    let value = sample as f32 / 32768.0;
+                    value * value
+                })
+                .sum();
+            let rms = (sum_sq / samples.len() as f32).sqrt();
+            let level = (rms.min(1.0) * 3.0).min(1.0);
+            sink.meter.level.store(level.to_bits(), Ordering::Relaxed);
+
+            sink.meter
+                .produced
+                .fetch_add(samples.len() as u64, Ordering::Relaxed);
+            let pushed = sink.producer.push_slice(&samples);
+            if pushed < samples.len() {
+                sink.meter
+                    .dropped
+                    .fetch_add((samples.len() - pushed) as u64, Ordering::Relaxed);
+            }
+        }
+
+        // Unless we stopped it, the server went away or the source vanished.
+        if !reader_stopping.load(Ordering::Relaxed) {
+            log::warn!("Monitor capture of {} ended unexpectedly", source);
+            sink.meter.failed.store(true, Ordering::Relaxed);
+        }
+    });
+
+    Ok(MonitorStream {
+        child,
+        reader: Some(reader),
+        stopping,
+    })
+}
+
 fn start_device_capture(
     device: cpal::Device,
-    target_buffer: Arc<Mutex<Vec<i16>>>,
-    target_level: Arc<Mutex<f32>>,
+    sink: CaptureSink,
     allow_output_fallback: bool,
 ) -> Result<cpal::Stream, String> {
     let (config, sample_format) = resolve_device_config(&device, allow_output_fallback)?;
@@ -134,54 +791,24 @@ fn start_device_capture(
     let channels = config.channels as usize;
 
     let stream = match sample_format {
-        SampleFormat::F32 => build_input_stream::<f32>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
-        SampleFormat::I16 => build_input_stream::<i16>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
-        SampleFormat::U16 => build_input_stream::<u16>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
-        SampleFormat::I32 => build_input_stream::<i32>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
-        SampleFormat::U32 => build_input_stream::<u32>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
-        SampleFormat::F64 => build_input_stream::<f64>(
-            &device,
-            &config,
-            sample_rate_hz,
-            channels,
-            target_buffer,
-            target_level,
-        ),
+        SampleFormat::F32 => {
+            build_input_stream::<f32>(&device, &config, sample_rate_hz, channels, sink)
+        }
+        SampleFormat::I16 => {
+            build_input_stream::<i16>(&device, &config, sample_rate_hz, channels, sink)
+        }
+        SampleFormat::U16 => {
+            build_input_stream::<u16>(&device, &config, sample_rate_hz, channels, sink)
+        }
+        SampleFormat::I32 => {
+            build_input_stream::<i32>(&device, &config, sample_rate_hz, channels, sink)
+        }
+        SampleFormat::U32 => {
+            build_input_stream::<u32>(&device, &config, sample_rate_hz, channels, sink)
+        }
+        SampleFormat::F64 => {
+            build_input_stream::<f64>(&device, &config, sample_rate_hz, channels, sink)
+        }
         other => Err(format!("Unsupported input sample format: {:?}", other)),
     }?;
 
@@ -214,74 +841,1871 @@ fn build_input_stream<T>(
     config: &StreamConfig,
     source_sample_rate: u32,
     source_channels: usize,
-    target_buffer: Arc<Mutex<Vec<i16>>>,
-    target_level: Arc<Mutex<f32>>,
+    mut sink: CaptureSink,
 ) -> Result<cpal::Stream, String>
 where
     T: Sample + cpal::SizedSample,
     f32: FromSample<T>,
 {
+    // All scratch space is sized up front; the callback only reuses it.
+    let mut resampler = Resampler::new(source_sample_rate, TARGET_SAMPLE_RATE);
+    resampler.reserve(CALLBACK_BLOCK_FRAMES);
+    let mut mono: Vec<f32> = Vec::with_capacity(CALLBACK_BLOCK_FRAMES);
+    let mut resampled: Vec<f32> =
+        Vec::with_capacity(resampler.max_output_len(CALLBACK_BLOCK_FRAMES));
+    let mut samples_i16: Vec<i16> = Vec::with_capacity(resampled.capacity());
+    let channels = source_channels.max(1);
+    let error_meter = sink.meter.clone();
+
     device
         .build_input_stream(
             config,
             move |data: &[T], _: &cpal::InputCallbackInfo| {
-                if data.is_empty() {
+                if data.len() < channels {
                     return;
                 }
 
-                // Mix down to mono first.
-                let mono: Vec<f32> = if source_channels > 1 {
-                    data.chunks(source_channels)
-                        .map(|frame| {
-                            let sum: f32 =
-                                frame.iter().map(|sample| f32::from_sample(*sample)).sum();
-                            sum / source_channels as f32
-                        })
-                        .collect()
-                } else {
-                    data.iter()
-                        .map(|sample| f32::from_sample(*sample))
-                        .collect()
-                };
+                let mut sum_sq = 0.0_f32;
+                let mut frames = 0_usize;
 
-                if mono.is_empty() {
-                    return;
-                }
+                for block in data.chunks(CALLBACK_BLOCK_FRAMES * channels) {
+                    // Mix down to mono first.
+                    mono.clear();
+                    mono.extend(block.chunks_exact(channels).map(|frame| {
+                        let sum: f32 = frame.iter().map(|sample| f32::from_sample(*sample)).sum();
+                        sum / channels as f32
+                    }));
+                    sum_sq += mono.iter().map(|sample| sample * sample).sum::<f32>();
+                    frames += mono.len();
 
-                // RMS for live meter.
-                let sum_sq: f32 = mono.iter().map(|sample| sample * sample).sum();
-                let rms = (sum_sq / mono.len() as f32).sqrt();
-
-                // Resample to 16kHz via simple decimation/interleaved pick.
-                let resampled: Vec<f32> = if source_sample_rate != 16_000 {
-                    let ratio = source_sample_rate as f64 / 16_000.0;
-                    let out_len = (mono.len() as f64 / ratio).ceil().max(1.0) as usize;
-                    (0..out_len)
-                        .map(|index| {
-                            let source_index = ((index as f64) * ratio) as usize;
-                            mono[source_index.min(mono.len() - 1)]
-                        })
-                        .collect()
-                } else {
-                    mono
-                };
+                    // Band-limited resample to 16kHz; filter state carries over
+                    // between callbacks so block edges do not click.
+                    resampled.clear();
+                    resampler.process(&mono, &mut resampled);
 
-                let samples_i16: Vec<i16> = resampled
-                    .iter()
-                    .map(|sample| (sample * 32767.0).clamp(-32768.0, 32767.0) as i16)
-                    .collect();
+                    samples_i16.clear();
+                    samples_i16.extend(
+                        resampled
+                            .iter()
+                            .map(|sample| (sample * 32767.0).clamp(-32768.0, 32767.0) as i16),
+                    );
 
-                if let Ok(mut buffer) = target_buffer.lock() {
-                    buffer.extend_from_slice(&samples_i16);
+                    // A full ring means the loop has fallen behind; drop the
+                    // newest audio and count it rather than grow.
+                    sink.meter
+                        .produced
+                        .fetch_add(samples_i16.len() as u64, Ordering::Relaxed);
+                    let pushed = sink.producer.push_slice(&samples_i16);
+                    if pushed < samples_i16.len() {
+                        sink.meter
+                            .dropped
+                            .fetch_add((samples_i16.len() - pushed) as u64, Ordering::Relaxed);
+                    }
                 }
-                if let Ok(mut level) = target_level.lock() {
-                    *level = (rms.min(1.0) * 3.0).min(1.0);
+
+                if frames > 0 {
+                    // RMS for live meter.
+                    let rms = (sum_sq / frames as f32).sqrt();
+                    let level = (rms.min(1.0) * 3.0).min(1.0);
+                    sink.meter.level.store(level.to_bits(), Ordering::Relaxed);
                 }
             },
-            |err| {
+            move |err| {
+                // Device removal and similar failures end the stream; let
+                // the health check rebuild it.
                 log::error!("Audio capture stream error: {}", err);
+                error_meter.failed.store(true, Ordering::Relaxed);
             },
             None,
         )
         .map_err(|e| format!("Failed to build input stream: {}", e))
 }
+
+/// Sample rate everything downstream of capture runs at.
+const TARGET_SAMPLE_RATE: u32 = 16_000;
+/// Passband edge as a fraction of the lower Nyquist frequency.
+const RESAMPLER_ROLLOFF: f64 = 0.9;
+/// Sinc zero crossings on each side of the kernel centre.
+const RESAMPLER_ZERO_CROSSINGS: f64 = 24.0;
+/// Kaiser window shape; ~80 dB stopband attenuation.
+const RESAMPLER_KAISER_BETA: f64 = 8.0;
+
+/// Streaming polyphase windowed-sinc resampler.
+///
+/// The rate change is reduced to a rational `up / down`, with one filter
+/// phase per distinct output position. Input history is kept between calls so
+/// block boundaries are seamless, and after `N` input samples exactly
+/// `ceil(N * up / down)` samples have been produced, at the cost of a fixed
+/// delay of half the kernel length.
+pub struct Resampler {
+    up: u64,
+    down: u64,
+    taps: usize,
+    /// `up` phases of `taps` coefficients each.
+    bank: Vec<f32>,
+    /// Recent input; `history[0]` is absolute input index `history_start`.
+    history: Vec<f32>,
+    history_start: i64,
+    received: u64,
+    produced: u64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let divisor = gcd(input_rate as u64, output_rate as u64).max(1);
+        let up = output_rate as u64 / divisor;
+        let down = input_rate as u64 / divisor;
+
+        if up == down {
+            return Self {
+                up,
+                down,
+                taps: 0,
+                bank: Vec::new(),
+                history: Vec::new(),
+                history_start: 0,
+                received: 0,
+                produced: 0,
+            };
+        }
+
+        // Cutoff in cycles per input sample, below the lower of the two Nyquists.
+        let cutoff = 0.5 * (up as f64 / down as f64).min(1.0) * RESAMPLER_ROLLOFF;
+        let half_width = (RESAMPLER_ZERO_CROSSINGS / (2.0 * cutoff)).ceil() as usize;
+        let taps = half_width * 2;
+
+        let mut bank = Vec::with_capacity(up as usize * taps);
+        for phase in 0..up as usize {
+            let fraction = phase as f64 / up as f64;
+            let start = bank.len();
+            for tap in 0..taps {
+                // Distance of this tap from the output instant, in input samples.
+                let x = tap as f64 - half_width as f64 + 1.0 - fraction;
+                let window = kaiser(x / half_width as f64, RESAMPLER_KAISER_BETA);
+                bank.push((2.0 * cutoff * sinc(2.0 * cutoff * x) * window) as f32);
+            }
+            // Unity DC gain for every phase.
+            let sum: f32 = bank[start..].iter().sum();
+            for coefficient in &mut bank[start..] {
+                *coefficient /= sum;
+            }
+        }
+
+        Self {
+            up,
+            down,
+            taps,
+            bank,
+            history: vec![0.0; taps - 1],
+            history_start: -(taps as i64 - 1),
+            received: 0,
+            produced: 0,
+        }
+    }
+
+    /// Pre-allocate history so `process` never reallocates for blocks of up
+    /// to `max_input` samples.
+    pub fn reserve(&mut self, max_input: usize) {
+        if self.taps > 0 {
+            self.history
+                .reserve((self.taps + max_input).saturating_sub(self.history.len()));
+        }
+    }
+
+    /// Upper bound on the samples one `process` call appends for `input_len`
+    /// input samples.
+    pub fn max_output_len(&self, input_len: usize) -> usize {
+        (input_len as u64 * self.up).div_ceil(self.down) as usize + 1
+    }
+
+    /// Resample `input` and append the result to `output`.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.taps == 0 {
+            output.extend_from_slice(input);
+            return;
+        }
+
+        self.history.extend_from_slice(input);
+        self.received += input.len() as u64;
+
+        loop {
+            let position = self.produced * self.down;
+            let newest = position / self.up;
+            if newest >= self.received {
+                break;
+            }
+            let phase = (position % self.up) as usize;
+            let first = (newest as i64 - self.taps as i64 + 1 - self.history_start) as usize;
+
+            let coefficients = &self.bank[phase * self.taps..(phase + 1) * self.taps];
+            let samples = &self.history[first..first + self.taps];
+            output.push(
+                samples
+                    .iter()
+                    .zip(coefficients)
+                    .map(|(sample, coefficient)| sample * coefficient)
+                    .sum(),
+            );
+            self.produced += 1;
+        }
+
+        // Drop input no future output sample can reach.
+        let next_newest = (self.produced * self.down / self.up) as i64;
+        let consumed = next_newest - self.taps as i64 + 1 - self.history_start;
+        if consumed > 0 {
+            let consumed = (consumed as usize).min(self.history.len());
+            self.history.drain(..consumed);
+            self.history_start += consumed as i64;
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+/// Kaiser window at `x` in [-1, 1].
+fn kaiser(x: f64, beta: f64) -> f64 {
+    if x.abs() > 1.0 {
+        return 0.0;
+    }
+    bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+/// Zeroth-order modified Bessel function of the first kind (power series).
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half_x = x / 2.0;
+    for k in 1..50 {
+        term *= (half_x / k as f64) * (half_x / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+/// Amplitude (fraction of full scale) above which a sample counts as voiced.
+/// Shared with `compute_prosody` so the meters and the VAD agree.
+pub const VOICED_THRESHOLD: f32 = 0.02;
+
+const VAD_SAMPLE_RATE: usize = 16_000;
+/// Highest background RMS the VAD adapts to; its gate never rises above
+/// three times this, well below normal speech.
+const MAX_NOISE_FLOOR: f32 = VOICED_THRESHOLD * 2.0;
+
+/// Tuning for `VoiceActivitySegmenter`. Durations are in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct VadConfig {
+    /// Analysis frame length.
+    pub frame_ms: usize,
+    /// Utterances with less voiced audio than this are dropped as noise.
+    pub min_utterance_ms: usize,
+    /// Utterances are split at the quietest recent frame once this long.
+    pub max_utterance_ms: usize,
+    /// Trailing silence that ends an utterance.
+    pub end_silence_ms: usize,
+    /// Audio kept from before speech onset so the first word is not clipped.
+    pub pre_roll_ms: usize,
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            frame_ms: 30,
+            min_utterance_ms: 300,
+            max_utterance_ms: 15_000,
+            end_silence_ms: 600,
+            pre_roll_ms: 200,
+        }
+    }
+}
+
+/// A complete utterance ready for the ASR.
+pub struct Utterance {
+    pub samples: Vec<i16>,
+    /// Offset of `samples[0]` from the start of the source's capture.
+    pub start_sample: i64,
+}
+
+/// Energy + zero-crossing voice activity detector that cuts a 16kHz source
+/// into utterances at natural pauses.
+pub struct VoiceActivitySegmenter {
+    config: VadConfig,
+    frame_len: usize,
+    /// Samples not yet making up a full frame.
+    carry: Vec<i16>,
+    /// Frames before speech onset, capped at `pre_roll_ms`.
+    pre_roll: Vec<i16>,
+    utterance: Vec<i16>,
+    /// RMS of each frame in `utterance`, used to pick split points.
+    frame_energies: Vec<f32>,
+    utterance_start: i64,
+    in_speech: bool,
+    voiced_frames: usize,
+    silent_run_frames: usize,
+    /// Adaptive estimate of background RMS.
+    noise_floor: f32,
+    /// Samples consumed so far, i.e. the position of `carry[0]`.
+    position: i64,
+}
+
+impl VoiceActivitySegmenter {
+    pub fn new(config: VadConfig) -> Self {
+        Self {
+            config,
+            frame_len: VAD_SAMPLE_RATE * config.frame_ms / 1000,
+            carry: Vec::new(),
+            pre_roll: Vec::new(),
+            utterance: Vec::new(),
+            frame_energies: Vec::new(),
+            utterance_start: 0,
+            in_speech: false,
+            voiced_frames: 0,
+            silent_run_frames: 0,
+            noise_floor: VOICED_THRESHOLD / 4.0,
+            position: 0,
+        }
+    }
+
+    /// Change the tuning of a running segmenter. The frame length is fixed
+    /// at construction and is kept.
+    pub fn set_config(&mut self, config: VadConfig) {
+        self.config = VadConfig {
+            frame_ms: self.config.frame_ms,
+            ..config
+        };
+    }
+
+    /// Place the next pushed sample at `sample` on the source timeline, e.g.
+    /// when a resumed session continues where it left off.
+    pub fn start_at(&mut self, sample: i64) {
+        self.position = sample;
+    }
+
+    /// End the utterance in progress, e.g. when the source stops.
+    pub fn flush(&mut self) -> Option<Utterance> {
+        let carry = std::mem::take(&mut self.carry);
+        self.position += carry.len() as i64;
+        if !self.in_speech {
+            return None;
+        }
+        self.utterance.extend_from_slice(&carry);
+        self.finish_utterance()
+    }
+
+    /// `dropped` samples after those pushed were lost: end the utterance in
+    /// progress and place the next pushed sample after the gap.
+    pub fn skip(&mut self, dropped: usize) -> Option<Utterance> {
+        let utterance = self.flush();
+        self.position += dropped as i64;
+        utterance
+    }
+
+    /// Feed one source's share of a drain: its audio, then the `dropped`
+    /// samples lost after it.
+    pub fn push_drained(&mut self, samples: &[i16], dropped: u64) -> Vec<Utterance> {
+        let mut completed = self.push(samples);
+        if dropped > 0 {
+            completed.extend(self.skip(dropped as usize));
+        }
+        completed
+    }
+
+    /// Feed captured audio; returns any utterances completed by it.
+    pub fn push(&mut self, samples: &[i16]) -> Vec<Utterance> {
+        self.carry.extend_from_slice(samples);
+
+        let mut completed = Vec::new();
+        let whole_frames = self.carry.len() / self.frame_len;
+        let frames: Vec<i16> = self.carry.drain(..whole_frames * self.frame_len).collect();
+        for frame in frames.chunks(self.frame_len) {
+            if let Some(utterance) = self.process_frame(frame) {
+                completed.push(utterance);
+            }
+        }
+
+        completed
+    }
+
+    fn process_frame(&mut self, frame: &[i16]) -> Option<Utterance> {
+        let frame_start = self.position;
+        self.position += frame.len() as i64;
+        let (rms, voiced) = self.classify(frame);
+
+        if !self.in_speech {
+            if !voiced {
+                self.pre_roll.extend_from_slice(frame);
+                // Whole frames only, so frame_energies stays aligned with samples.
+                let max_pre_roll = self.config.pre_roll_ms / self.config.frame_ms * self.frame_len;
+                if self.pre_roll.len() > max_pre_roll {
+                    let excess = self.pre_roll.len() - max_pre_roll;
+                    self.pre_roll.drain(..excess);
+                }
+                return None;
+            }
+
+            self.in_speech = true;
+            self.utterance_start = frame_start - self.pre_roll.len() as i64;
+            self.utterance = std::mem::take(&mut self.pre_roll);
+            self.frame_energies = vec![0.0; self.utterance.len() / self.frame_len];
+        }
+
+        self.utterance.extend_from_slice(frame);
+        self.frame_energies.push(rms);
+        if voiced {
+            self.voiced_frames += 1;
+            self.silent_run_frames = 0;
+        } else {
+            self.silent_run_frames += 1;
+        }
+
+        if self.silent_run_frames * self.config.frame_ms >= self.config.end_silence_ms {
+            return self.finish_utterance();
+        }
+
+        if self.utterance.len() >= VAD_SAMPLE_RATE * self.config.max_utterance_ms / 1000 {
+            return self.split_utterance();
+        }
+
+        None
+    }
+
+    /// Returns the frame RMS and whether the frame looks like speech.
+    fn classify(&mut self, frame: &[i16]) -> (f32, bool) {
+        let mut sum_sq = 0.0_f32;
+        let mut crossings = 0usize;
+        let mut previous = 0i16;
+        for &sample in frame {
+            let value = sample as f32 / 32768.0;
+            sum_sq += value * value;
+            if (sample >= 0) != (previous >= 0) {
+                crossings += 1;
+            }
+            previous = sample;
+        }
+        let rms = (sum_sq / frame.len() as f32).sqrt();
+        let zero_crossing_rate = crossings as f32 / frame.len() as f32;
+
+        // Speech must clear both the fixed voiced threshold and the adaptive
+        // noise floor. Broadband hiss crosses zero far more often than voiced
+        // speech, so very high zero-crossing rates need extra energy.
+        let energy_gate = VOICED_THRESHOLD.max(self.noise_floor * 3.0);
+        let voiced = rms >= energy_gate && (zero_crossing_rate < 0.35 || rms >= energy_gate * 2.0);
+
+        // Only frames below the gate are background. Louder frames rejected
+        // as hiss would otherwise pull the floor up until the gate sits
+        // above speech, and the cap keeps even steady loud noise from doing so.
+        if rms < energy_gate {
+            self.noise_floor = (self.noise_floor * 0.95 + rms * 0.05).min(MAX_NOISE_FLOOR);
+        }
+
+        (rms, voiced)
+    }
+
+    fn finish_utterance(&mut self) -> Option<Utterance> {
+        let voiced_ms = self.voiced_frames * self.config.frame_ms;
+        let samples = std::mem::take(&mut self.utterance);
+        let start_sample = self.utterance_start;
+        self.reset_utterance();
+
+        if voiced_ms < self.config.min_utterance_ms {
+            return None;
+        }
+
+        Some(Utterance {
+            samples,
+            start_sample,
+        })
+    }
+
+    /// Cut an over-long utterance at the quietest frame of its last third and
+    /// keep the remainder as the start of the next one.
+    fn split_utterance(&mut self) -> Option<Utterance> {
+        let frame_count = self.frame_energies.len();
+        let search_from = frame_count * 2 / 3;
+        let split_frame = (search_from..frame_count)
+            .min_by(|a, b| self.frame_energies[*a].total_cmp(&self.frame_energies[*b]))
+            .map(|index| index + 1)
+            .unwrap_or(frame_count);
+        let split_at = (split_frame * self.frame_len).min(self.utterance.len());
+
+        let remainder = self.utterance.split_off(split_at);
+        let remainder_energies = self.frame_energies.split_off(split_frame.min(frame_count));
+        let samples = std::mem::take(&mut self.utterance);
+        let start_sample = self.utterance_start;
+
+        self.utterance_start += samples.len() as i64;
+        self.utterance = remainder;
+        self.voiced_frames = remainder_energies
+            .iter()
+            .filter(|rms| **rms >= VOICED_THRESHOLD)
+            .count();
+        self.frame_energies = remainder_energies;
+        self.silent_run_frames = 0;
+
+        Some(Utterance {
+            samples,
+            start_sample,
+        })
+    }
+
+    fn reset_utterance(&mut self) {
+        self.in_speech = false;
+        self.frame_energies.clear();
+        self.voiced_frames = 0;
+        self.silent_run_frames = 0;
+    }
+}
+
+/// True if any full frame of `samples` clears the voiced threshold.
+pub fn contains_speech(samples: &[i16]) -> bool {
+    let frame_len = VAD_SAMPLE_RATE * VadConfig::default().frame_ms / 1000;
+    samples.chunks(frame_len).any(|frame| {
+        let sum_sq: f32 = frame
+            .iter()
+            .map(|sample| {
+                let value = *sample as f32 / 32768.0;
+                value * value
+            })
+            .sum();
+        (sum_sq / frame.len() as f32).sqrt() >= VOICED_THRESHOLD
+    })
+}
+
+/// Adaptive filter length modelling the echo path after the bulk delay (32ms).
+const AEC_FILTER_TAPS: usize = 512;
+/// Taps placed ahead of the estimated delay to absorb estimation error.
+const AEC_DELAY_MARGIN: usize = 128;
+/// Longest mic-behind-reference delay searched for.
+const AEC_MAX_DELAY_MS: usize = 500;
+/// Frame of the energy envelopes correlated to find the delay (4ms).
+const AEC_ENVELOPE_FRAME: usize = 64;
+/// Audio correlated per delay estimate.
+const AEC_ESTIMATE_WINDOW_MS: usize = 4_000;
+/// Mic audio between delay estimates.
+const AEC_ESTIMATE_INTERVAL_MS: usize = 1_000;
+/// Minimum envelope correlation for a delay estimate to be trusted.
+const AEC_MIN_CORRELATION: f32 = 0.3;
+/// NLMS step size.
+const AEC_STEP_SIZE: f32 = 0.3;
+/// Geigel double-talk detector: a mic block whose peak exceeds this fraction
+/// of the recent reference peak contains near-end speech. Raised to twice the
+/// measured echo-path gain when the echo itself is louder.
+const AEC_DOUBLE_TALK_RATIO: f32 = 0.5;
+/// Samples per double-talk decision (4ms).
+const AEC_BLOCK: usize = 64;
+/// Adaptation stays frozen this long after double talk ends (30ms).
+const AEC_DOUBLE_TALK_HANGOVER: usize = 480;
+/// Reference RMS below which the far end counts as silent.
+const AEC_FAR_END_THRESHOLD: f32 = 0.005;
+
+/// How much echo the canceller is removing.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EchoMetrics {
+    /// Estimated delay of the echo behind the system-audio reference.
+    pub delay_ms: Option<f64>,
+    /// Echo return loss enhancement: mic power over residual power while
+    /// only the far end is talking.
+    pub erle_db: Option<f64>,
+    /// Mic audio during which the far end was active.
+    pub far_end_ms: i64,
+    /// Far-end audio during which the near end also spoke (not adapted on).
+    pub double_talk_ms: i64,
+}
+
+/// Removes system audio picked up by the microphone (e.g. laptop speakers)
+/// using the system-audio stream as the far-end reference.
+///
+/// `AudioCapture` keeps both sources on the session clock, filling loopback
+/// gaps with silence and absorbing device drift, so sample `n` of one lines
+/// up with sample `n` of the other to within the delay search range. Audio
+/// the rings dropped is accounted for with `skip`. The bulk delay of the
+/// acoustic path is found by correlating energy envelopes; an NLMS filter
+/// models the rest of the path and adapts only while the far end talks
+/// alone.
+pub struct EchoCanceller {
+    weights: Vec<f32>,
+    /// Recent reference; `reference[0]` is absolute sample `reference_start`.
+    reference: Vec<f32>,
+    reference_start: i64,
+    /// Recent mic input before cancellation, for delay estimation.
+    mic: Vec<f32>,
+    mic_start: i64,
+    /// Estimated delay in samples, once found.
+    delay: Option<usize>,
+    samples_until_estimate: usize,
+    /// Samples left before adaptation resumes after double talk.
+    hangover: usize,
+    /// Echo level relative to the reference, from the delay estimate.
+    echo_gain: f32,
+    echo_power: f64,
+    residual_power: f64,
+    far_end_samples: u64,
+    double_talk_samples: u64,
+}
+
+impl EchoCanceller {
+    pub fn new() -> Self {
+        Self {
+            weights: vec![0.0; AEC_FILTER_TAPS],
+            reference: Vec::new(),
+            reference_start: 0,
+            mic: Vec::new(),
+            mic_start: 0,
+            delay: None,
+            samples_until_estimate: ms_to_aec_samples(AEC_ESTIMATE_WINDOW_MS),
+            hangover: 0,
+            echo_gain: 0.0,
+            echo_power: 0.0,
+            residual_power: 0.0,
+            far_end_samples: 0,
+            double_talk_samples: 0,
+        }
+    }
+
+    /// Cancel echo of `reference` from `mic` in place. Both are the next
+    /// samples of their source since capture start.
+    pub fn process(&mut self, mic: &mut [i16], reference: &[i16]) {
+        self.reference
+            .extend(reference.iter().map(|&sample| sample as f32 / 32768.0));
+        let mic_offset = self.mic.len();
+        self.mic
+            .extend(mic.iter().map(|&sample| sample as f32 / 32768.0));
+
+        if self.samples_until_estimate <= mic.len() {
+            self.estimate_delay();
+            self.samples_until_estimate = ms_to_aec_samples(AEC_ESTIMATE_INTERVAL_MS);
+        } else {
+            self.samples_until_estimate -= mic.len();
+        }
+
+        if let Some(delay) = self.delay {
+            let bulk = delay.saturating_sub(AEC_DELAY_MARGIN) as i64;
+            for (block_index, block) in mic.chunks_mut(AEC_BLOCK).enumerate() {
+                let offset = mic_offset + block_index * AEC_BLOCK;
+                let position = self.mic_start + offset as i64;
+                let input = &self.mic[offset..offset + block.len()];
+
+                if self.near_end_talking(input, position, bulk) {
+                    self.hangover = AEC_DOUBLE_TALK_HANGOVER;
+                }
+                for (index, sample) in block.iter_mut().enumerate() {
+                    let adapt = self.hangover == 0;
+                    self.hangover = self.hangover.saturating_sub(1);
+                    let input = self.mic[offset + index];
+                    if let Some(output) =
+                        self.cancel_sample(position + index as i64, bulk, input, adapt)
+                    {
+                        *sample = (output * 32767.0).clamp(-32768.0, 32767.0) as i16;
+                    }
+                }
+            }
+        }
+
+        self.trim_history();
+    }
+
+    /// `mic` and `reference` samples following those processed were lost;
+    /// keep later audio at its position on the shared clock.
+    pub fn skip(&mut self, mic: usize, reference: usize) {
+        self.mic.resize(self.mic.len() + mic, 0.0);
+        self.reference.resize(self.reference.len() + reference, 0.0);
+        self.trim_history();
+    }
+
+    /// Geigel test: whether the mic block is louder than echo of the
+    /// reference that could have produced it.
+    fn near_end_talking(&self, input: &[f32], position: i64, bulk: i64) -> bool {
+        let newest = position + input.len() as i64 - 1 - bulk - self.reference_start;
+        let oldest = (position - bulk - AEC_FILTER_TAPS as i64 + 1 - self.reference_start).max(0);
+        let newest = newest.min(self.reference.len() as i64 - 1);
+        if newest < oldest {
+            return false;
+        }
+        let reference_peak = self.reference[oldest as usize..=newest as usize]
+            .iter()
+            .fold(0.0_f32, |peak, x| peak.max(x.abs()));
+        let input_peak = input.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+        let threshold = AEC_DOUBLE_TALK_RATIO.max(2.0 * self.echo_gain);
+        input_peak > threshold * reference_peak
+    }
+
+    /// Echo-cancel one mic sample at absolute `position`, adapting the filter
+    /// when `adapt` is set. `None` when the reference it needs has not
+    /// arrived.
+    fn cancel_sample(&mut self, position: i64, bulk: i64, input: f32, adapt: bool) -> Option<f32> {
+        let newest = position - bulk - self.reference_start;
+        let oldest = newest - AEC_FILTER_TAPS as i64 + 1;
+        if oldest < 0 || newest >= self.reference.len() as i64 {
+            return None;
+        }
+        // Newest reference sample first, matching `weights`.
+        let taps = &self.reference[oldest as usize..=newest as usize];
+
+        let mut estimate = 0.0_f32;
+        let mut power = 0.0_f32;
+        for (weight, &x) in self.weights.iter().zip(taps.iter().rev()) {
+            estimate += weight * x;
+            power += x * x;
+        }
+        let error = input - estimate;
+
+        let far_end_active =
+            power / AEC_FILTER_TAPS as f32 >= AEC_FAR_END_THRESHOLD * AEC_FAR_END_THRESHOLD;
+        if far_end_active {
+            self.far_end_samples += 1;
+            if !adapt {
+                self.double_talk_samples += 1;
+            } else {
+                let step = AEC_STEP_SIZE * error / (power + 1e-6);
+                for (weight, &x) in self.weights.iter_mut().zip(taps.iter().rev()) {
+                    *weight += step * x;
+                }
+                self.echo_power += (input * input) as f64;
+                self.residual_power += (error * error) as f64;
+            }
+        }
+
+        if !error.is_finite() {
+            self.weights.iter_mut().for_each(|weight| *weight = 0.0);
+            return Some(input);
+        }
+        Some(error)
+    }
+
+    /// Correlate mic and reference energy envelopes over the last window
+    /// and adopt the best lag when it is clear enough.
+    fn estimate_delay(&mut self) {
+        let window = ms_to_aec_samples(AEC_ESTIMATE_WINDOW_MS);
+        let max_lag_frames = ms_to_aec_samples(AEC_MAX_DELAY_MS) / AEC_ENVELOPE_FRAME;
+        let mic_end = self.mic_start + self.mic.len() as i64;
+        let reference_end = self.reference_start + self.reference.len() as i64;
+        // Align frames to absolute positions both histories cover.
+        let end =
+            mic_end.min(reference_end) / AEC_ENVELOPE_FRAME as i64 * AEC_ENVELOPE_FRAME as i64;
+        let start = end - window as i64;
+        let lookback = (max_lag_frames * AEC_ENVELOPE_FRAME) as i64;
+        if start < self.mic_start || start - lookback < self.reference_start {
+            return;
+        }
+
+        let envelope = |samples: &[f32], from: i64, base: i64, frames: usize| -> Vec<f32> {
+            let offset = (from - base) as usize;
+            samples[offset..offset + frames * AEC_ENVELOPE_FRAME]
+                .chunks_exact(AEC_ENVELOPE_FRAME)
+                .map(|frame| frame.iter().map(|s| s.abs()).sum::<f32>() / AEC_ENVELOPE_FRAME as f32)
+                .collect()
+        };
+        let frames = window / AEC_ENVELOPE_FRAME;
+        let mic_envelope = envelope(&self.mic, start, self.mic_start, frames);
+        let reference_envelope = envelope(
+            &self.reference,
+            start - lookback,
+            self.reference_start,
+            frames + max_lag_frames,
+        );
+
+        let mut best: Option<(usize, f32, f32)> = None;
+        for lag in 0..=max_lag_frames {
+            // Reference frames `lag` frames before each mic frame.
+            let aligned = &reference_envelope[max_lag_frames - lag..max_lag_frames - lag + frames];
+            let (correlation, gain) = regression(aligned, &mic_envelope);
+            if best.map_or(true, |(_, c, _)| correlation > c) {
+                best = Some((lag, correlation, gain));
+            }
+        }
+
+        let Some((lag, correlation, gain)) = best else {
+            return;
+        };
+        if correlation < AEC_MIN_CORRELATION {
+            return;
+        }
+        // Near-end speech is uncorrelated with the reference, so the slope
+        // measures the echo path alone.
+        self.echo_gain = gain.max(0.0);
+        let delay = lag * AEC_ENVELOPE_FRAME;
+        let moved = self.delay.map_or(true, |current| {
+            current.abs_diff(delay) > AEC_DELAY_MARGIN / 2
+        });
+        if moved {
+            log::info!(
+                "Echo delay estimate {}ms (correlation {:.2})",
+                delay * 1000 / VAD_SAMPLE_RATE,
+                correlation
+            );
+            self.delay = Some(delay);
+            self.weights.iter_mut().for_each(|weight| *weight = 0.0);
+        }
+    }
+
+    /// Keep enough history for the filter and the next delay estimate.
+    fn trim_history(&mut self) {
+        let keep =
+            ms_to_aec_samples(AEC_ESTIMATE_WINDOW_MS + AEC_MAX_DELAY_MS) + AEC_FILTER_TAPS * 2;
+        if self.reference.len() > keep * 2 {
+            let excess = self.reference.len() - keep;
+            self.reference.drain(..excess);
+            self.reference_start += excess as i64;
+        }
+        if self.mic.len() > keep * 2 {
+            let excess = self.mic.len() - keep;
+            self.mic.drain(..excess);
+            self.mic_start += excess as i64;
+        }
+    }
+
+    pub fn metrics(&self) -> EchoMetrics {
+        let erle_db = (self.residual_power > 0.0 && self.echo_power > 0.0)
+            .then(|| 10.0 * (self.echo_power / self.residual_power).log10());
+        EchoMetrics {
+            delay_ms: self
+                .delay
+                .map(|delay| delay as f64 * 1000.0 / VAD_SAMPLE_RATE as f64),
+            erle_db,
+            far_end_ms: (self.far_end_samples * 1000 / VAD_SAMPLE_RATE as u64) as i64,
+            double_talk_ms: (self.double_talk_samples * 1000 / VAD_SAMPLE_RATE as u64) as i64,
+        }
+    }
+}
+
+fn ms_to_aec_samples(ms: usize) -> usize {
+    ms * VAD_SAMPLE_RATE / 1000
+}
+
+/// Pearson correlation of two equally long series and the least-squares
+/// slope of `y` on `x`; zeros when either is flat.
+fn regression(x: &[f32], y: &[f32]) -> (f32, f32) {
+    let n = x.len() as f32;
+    let mean_x = x.iter().sum::<f32>() / n;
+    let mean_y = y.iter().sum::<f32>() / n;
+    let mut covariance = 0.0;
+    let mut variance_x = 0.0;
+    let mut variance_y = 0.0;
+    for (&a, &b) in x.iter().zip(y) {
+        covariance += (a - mean_x) * (b - mean_y);
+        variance_x += (a - mean_x) * (a - mean_x);
+        variance_y += (b - mean_y) * (b - mean_y);
+    }
+    if variance_x <= f32::EPSILON || variance_y <= f32::EPSILON {
+        return (0.0, 0.0);
+    }
+    (
+        covariance / (variance_x * variance_y).sqrt(),
+        covariance / variance_x,
+    )
+}
+
+/// Cleanup stages applied to each source before the ASR. Deserialized from
+/// the `processing` argument of `start_transcription`.
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProcessingConfig {
+    /// Remove speaker playback from the mic (needs system audio).
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub high_pass_filter: bool,
+    pub automatic_gain_control: bool,
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        Self {
+            echo_cancellation: true,
+            noise_suppression: false,
+            high_pass_filter: false,
+            automatic_gain_control: false,
+        }
+    }
+}
+
+impl ProcessingConfig {
+    /// Whether any per-source stage (everything but echo cancellation) runs.
+    pub fn cleans_sources(&self) -> bool {
+        self.noise_suppression || self.high_pass_filter || self.automatic_gain_control
+    }
+}
+
+/// Corner frequency of the high-pass filter; removes rumble, handling noise
+/// and DC below the voice band.
+const HIGH_PASS_HZ: f32 = 100.0;
+/// Noise suppression FFT size (32ms) and hop (50% overlap).
+const NS_FRAME: usize = 512;
+const NS_HOP: usize = NS_FRAME / 2;
+/// Frames averaged for the initial noise estimate.
+const NS_NOISE_INIT_FRAMES: u32 = 8;
+/// Bins this far above the noise estimate are taken as speech.
+const NS_SPEECH_RATIO: f32 = 4.0;
+/// Smoothing of the noise estimate on bins that are not speech.
+const NS_NOISE_SMOOTHING: f32 = 0.05;
+/// Per-frame growth allowed for the noise estimate on speech bins (about
+/// 1.3 dB/s), so a louder background is still learnt eventually.
+const NS_NOISE_RISE: f32 = 1.005;
+/// Smoothing of the decision-directed a-priori SNR.
+const NS_PRIORI_SMOOTHING: f32 = 0.98;
+/// Lowest gain applied to a bin (-20 dB); deeper cuts sound watery.
+const NS_GAIN_FLOOR: f32 = 0.1;
+/// AGC analysis block (10ms).
+const AGC_BLOCK: usize = 160;
+/// Speech RMS the AGC steers towards (-20 dBFS).
+const AGC_TARGET_RMS: f32 = 0.1;
+/// Gain limits (+24 dB / -6 dB).
+const AGC_MAX_GAIN: f32 = 16.0;
+const AGC_MIN_GAIN: f32 = 0.5;
+/// Blocks below this RMS (-60 dBFS) never count as speech.
+const AGC_MIN_SPEECH_RMS: f32 = 0.001;
+/// Peak the gained signal may reach before the gain is cut immediately.
+const AGC_LIMIT: f32 = 0.9;
+/// Per-block gain change towards the target when raising (about 6 dB/s)
+/// and lowering.
+const AGC_RISE: f32 = 1.007;
+const AGC_FALL: f32 = 0.97;
+/// Unprocessed audio kept per source for before/after comparisons.
+const RAW_HISTORY_MS: usize = 60_000;
+
+/// Per-source cleanup: high-pass filter, spectral noise suppression and
+/// automatic gain control, in that order. Keeps the unprocessed input so
+/// the benefit can be measured on the same audio.
+///
+/// Output has the same length as the input so both sources stay on the
+/// capture clock; noise suppression delays the signal by one frame (32ms).
+pub struct SourceProcessor {
+    high_pass: Option<HighPassFilter>,
+    noise: Option<NoiseSuppressor>,
+    gain: Option<AutomaticGain>,
+    scratch: Vec<f32>,
+    /// Unprocessed input; `raw[0]` is sample `raw_start` of the source.
+    raw: Vec<i16>,
+    raw_start: i64,
+}
+
+impl SourceProcessor {
+    pub fn new(config: ProcessingConfig) -> Self {
+        Self {
+            high_pass: config.high_pass_filter.then(HighPassFilter::new),
+            noise: config.noise_suppression.then(NoiseSuppressor::new),
+            gain: config.automatic_gain_control.then(AutomaticGain::new),
+            scratch: Vec::new(),
+            raw: Vec::new(),
+            raw_start: 0,
+        }
+    }
+
+    /// Place the next processed sample at `sample` on the source timeline.
+    pub fn start_at(&mut self, sample: i64) {
+        self.raw_start = sample - self.raw.len() as i64;
+    }
+
+    /// Clean the next `samples` of the source in place.
+    pub fn process(&mut self, samples: &mut [i16]) {
+        self.raw.extend_from_slice(samples);
+        self.trim_raw();
+
+        self.scratch.clear();
+        self.scratch
+            .extend(samples.iter().map(|&sample| sample as f32 / 32768.0));
+        if let Some(filter) = self.high_pass.as_mut() {
+            filter.process(&mut self.scratch);
+        }
+        if let Some(noise) = self.noise.as_mut() {
+            noise.process(&mut self.scratch);
+        }
+        if let Some(gain) = self.gain.as_mut() {
+            gain.process(&mut self.scratch);
+        }
+        for (sample, value) in samples.iter_mut().zip(&self.scratch) {
+            *sample = (value * 32768.0).clamp(-32768.0, 32767.0) as i16;
+        }
+    }
+
+    /// `dropped` samples following those processed were lost; silence
+    /// stands in for them so `raw` stays on the source timeline.
+    pub fn skip(&mut self, dropped: usize) {
+        let keep = RAW_HISTORY_MS * VAD_SAMPLE_RATE / 1000;
+        if dropped >= keep {
+            // Nothing kept so far would survive the silence.
+            self.raw_start += (self.raw.len() + dropped - keep) as i64;
+            self.raw.clear();
+            self.raw.resize(keep, 0);
+        } else {
+            self.raw.resize(self.raw.len() + dropped, 0);
+            self.trim_raw();
+        }
+    }
+
+    fn trim_raw(&mut self) {
+        let keep = RAW_HISTORY_MS * VAD_SAMPLE_RATE / 1000;
+        if self.raw.len() > keep * 2 {
+            let excess = self.raw.len() - keep;
+            self.raw.drain(..excess);
+            self.raw_start += excess as i64;
+        }
+    }
+
+    /// Unprocessed audio for `len` samples from `start_sample`, as far as it
+    /// is still kept.
+    pub fn raw(&self, start_sample: i64, len: usize) -> &[i16] {
+        let start = (start_sample - self.raw_start).clamp(0, self.raw.len() as i64) as usize;
+        let end = (start + len).min(self.raw.len());
+        &self.raw[start..end]
+    }
+}
+
+/// Everything done to captured audio before the ASR: echo cancellation on
+/// the mic, then each source's `SourceProcessor`. Owned by the session loop,
+/// which runs it on a blocking thread because the NLMS filter costs too much
+/// for the async runtime.
+pub struct CaptureProcessing {
+    echo: Option<EchoCanceller>,
+    microphone: Option<SourceProcessor>,
+    system: Option<SourceProcessor>,
+}
+
+impl CaptureProcessing {
+    /// Echo cancellation needs the system audio as its reference, so it
+    /// only runs with `system_audio`.
+    pub fn new(config: ProcessingConfig, system_audio: bool) -> Self {
+        let processor = || {
+            config
+                .cleans_sources()
+                .then(|| SourceProcessor::new(config))
+        };
+        Self {
+            echo: (system_audio && config.echo_cancellation).then(EchoCanceller::new),
+            microphone: processor(),
+            system: processor(),
+        }
+    }
+
+    /// Place the next processed sample of both sources at `sample`.
+    pub fn start_at(&mut self, sample: i64) {
+        for processor in [self.microphone.as_mut(), self.system.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            processor.start_at(sample);
+        }
+    }
+
+    /// Clean a drain of both sources in place.
+    pub fn process(&mut self, drained: &mut AudioDrain) {
+        // Remove speaker playback from the mic before it can be transcribed
+        // a second time as the microphone source.
+        if let Some(canceller) = self.echo.as_mut() {
+            canceller.process(&mut drained.microphone_samples, &drained.system_samples);
+        }
+        if let Some(processor) = self.microphone.as_mut() {
+            processor.process(&mut drained.microphone_samples);
+        }
+        if let Some(processor) = self.system.as_mut() {
+            processor.process(&mut drained.system_samples);
+        }
+        self.skip(
+            drained.microphone_dropped as usize,
+            drained.system_dropped as usize,
+        );
+    }
+
+    /// `microphone` and `system` samples following those processed were
+    /// lost; keep every stage on the source timelines.
+    pub fn skip(&mut self, microphone: usize, system: usize) {
+        if let Some(canceller) = self.echo.as_mut() {
+            canceller.skip(microphone, system);
+        }
+        if let Some(processor) = self.microphone.as_mut() {
+            processor.skip(microphone);
+        }
+        if let Some(processor) = self.system.as_mut() {
+            processor.skip(system);
+        }
+    }
+
+    pub fn processor(&self, role: SourceRole) -> Option<&SourceProcessor> {
+        match role {
+            SourceRole::Microphone => self.microphone.as_ref(),
+            SourceRole::Loopback => self.system.as_ref(),
+        }
+    }
+
+    /// `None` when echo cancellation is off.
+    pub fn echo_metrics(&self) -> Option<EchoMetrics> {
+        self.echo.as_ref().map(EchoCanceller::metrics)
+    }
+}
+
+/// Second-order Butterworth high-pass (RBJ biquad, transposed direct form II).
+struct HighPassFilter {
+    b: [f32; 3],
+    a: [f32; 2],
+    state: [f32; 2],
+}
+
+impl HighPassFilter {
+    fn new() -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * HIGH_PASS_HZ / VAD_SAMPLE_RATE as f32;
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha;
+        Self {
+            b: [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            state: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let input = *sample;
+            let output = self.b[0] * input + self.state[0];
+            self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+            self.state[1] = self.b[2] * input - self.a[1] * output;
+            *sample = output;
+        }
+    }
+}
+
+/// Short-time spectral noise suppression: a Wiener gain per frequency bin
+/// from a tracked noise spectrum and a decision-directed SNR estimate,
+/// resynthesised by weighted overlap-add.
+struct NoiseSuppressor {
+    /// Square-root Hann, applied on analysis and synthesis.
+    window: Vec<f32>,
+    /// Last `NS_FRAME` input samples.
+    frame: Vec<f32>,
+    /// Input collected towards the next hop.
+    hop: Vec<f32>,
+    overlap: Vec<f32>,
+    output: VecDeque<f32>,
+    noise: Vec<f32>,
+    previous_gain: Vec<f32>,
+    previous_snr: Vec<f32>,
+    frames: u32,
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl NoiseSuppressor {
+    fn new() -> Self {
+        let bins = NS_FRAME / 2 + 1;
+        let window = (0..NS_FRAME)
+            .map(|n| {
+                let phase = std::f32::consts::PI * n as f32 / NS_FRAME as f32;
+                phase.sin()
+            })
+            .collect();
+        Self {
+            window,
+            frame: vec![0.0; NS_FRAME],
+            hop: Vec::with_capacity(NS_HOP),
+            overlap: vec![0.0; NS_FRAME],
+            output: std::iter::repeat(0.0).take(NS_HOP).collect(),
+            noise: vec![0.0; bins],
+            previous_gain: vec![1.0; bins],
+            previous_snr: vec![1.0; bins],
+            frames: 0,
+            re: vec![0.0; NS_FRAME],
+            im: vec![0.0; NS_FRAME],
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.hop.push(*sample);
+            if self.hop.len() == NS_HOP {
+                self.process_hop();
+            }
+            *sample = self.output.pop_front().unwrap_or(0.0);
+        }
+    }
+
+    fn process_hop(&mut self) {
+        self.frame.copy_within(NS_HOP.., 0);
+        self.frame[NS_FRAME - NS_HOP..].copy_from_slice(&self.hop);
+        self.hop.clear();
+
+        for (n, value) in self.re.iter_mut().enumerate() {
+            *value = self.frame[n] * self.window[n];
+        }
+        self.im.fill(0.0);
+        fft(&mut self.re, &mut self.im, false);
+
+        self.frames += 1;
+        for k in 0..=NS_FRAME / 2 {
+            let power = self.re[k] * self.re[k] + self.im[k] * self.im[k];
+            let noise = &mut self.noise[k];
+            if self.frames <= NS_NOISE_INIT_FRAMES {
+                *noise += (power - *noise) / self.frames as f32;
+            } else if power < *noise * NS_SPEECH_RATIO {
+                *noise += NS_NOISE_SMOOTHING * (power - *noise);
+            } else {
+                *noise *= NS_NOISE_RISE;
+            }
+
+            let snr = power / noise.max(1e-10);
+            let priori = NS_PRIORI_SMOOTHING
+                * self.previous_gain[k]
+                * self.previous_gain[k]
+                * self.previous_snr[k]
+                + (1.0 - NS_PRIORI_SMOOTHING) * (snr - 1.0).max(0.0);
+            let gain = (priori / (1.0 + priori)).max(NS_GAIN_FLOOR);
+            self.previous_gain[k] = gain;
+            self.previous_snr[k] = snr;
+
+            self.re[k] *= gain;
+            self.im[k] *= gain;
+            if k > 0 && k < NS_FRAME / 2 {
+                self.re[NS_FRAME - k] *= gain;
+                self.im[NS_FRAME - k] *= gain;
+            }
+        }
+
+        fft(&mut self.re, &mut self.im, true);
+        for n in 0..NS_FRAME {
+            self.overlap[n] += self.re[n] / NS_FRAME as f32 * self.window[n];
+        }
+        self.output.extend(&self.overlap[..NS_HOP]);
+        self.overlap.copy_within(NS_HOP.., 0);
+        self.overlap[NS_FRAME - NS_HOP..].fill(0.0);
+    }
+}
+
+/// In-place radix-2 FFT; `re.len()` must be a power of two. The inverse is
+/// unscaled.
+fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Slow automatic gain control towards a target speech level, with an
+/// instant limiter so boosted peaks never clip.
+struct AutomaticGain {
+    gain: f32,
+    /// Running minimum of block RMS, taken as the background level.
+    floor: f32,
+    block_sum_sq: f32,
+    block_len: usize,
+}
+
+impl AutomaticGain {
+    fn new() -> Self {
+        Self {
+            gain: 1.0,
+            floor: AGC_MIN_SPEECH_RMS,
+            block_sum_sq: 0.0,
+            block_len: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let input = *sample;
+            self.block_sum_sq += input * input;
+            self.block_len += 1;
+            if self.block_len == AGC_BLOCK {
+                let rms = (self.block_sum_sq / AGC_BLOCK as f32).sqrt();
+                self.update(rms);
+                self.block_sum_sq = 0.0;
+                self.block_len = 0;
+            }
+
+            if (input * self.gain).abs() > AGC_LIMIT {
+                self.gain = AGC_LIMIT / input.abs();
+            }
+            *sample = input * self.gain;
+        }
+    }
+
+    fn update(&mut self, rms: f32) {
+        // The floor drops at once and creeps up, like the VAD's.
+        self.floor = if rms < self.floor {
+            rms.max(1e-5)
+        } else {
+            self.floor * 1.002
+        };
+
+        // Only speech moves the gain; boosting pauses would pump the noise.
+        if rms < AGC_MIN_SPEECH_RMS.max(self.floor * 3.0) {
+            return;
+        }
+        let wanted = (AGC_TARGET_RMS / rms).clamp(AGC_MIN_GAIN, AGC_MAX_GAIN);
+        self.gain = if wanted > self.gain {
+            (self.gain * AGC_RISE).min(wanted)
+        } else {
+            (self.gain * AGC_FALL).max(wanted)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(rate: u32, frequency: f64, seconds: f64) -> Vec<f32> {
+        let len = (rate as f64 * seconds) as usize;
+        (0..len)
+            .map(|n| {
+                (0.5 * (2.0 * std::f64::consts::PI * frequency * n as f64 / rate as f64).sin())
+                    as f32
+            })
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f64 {
+        let sum: f64 = samples.iter().map(|s| (*s as f64) * (*s as f64)).sum();
+        (sum / samples.len() as f64).sqrt()
+    }
+
+    /// Resample in uneven blocks, as cpal delivers them.
+    fn resample_in_blocks(rate: u32, input: &[f32]) -> Vec<f32> {
+        let mut resampler = Resampler::new(rate, TARGET_SAMPLE_RATE);
+        let mut output = Vec::new();
+        for block in input.chunks(441).flat_map(|chunk| chunk.chunks(173)) {
+            resampler.process(block, &mut output);
+        }
+        output
+    }
+
+    /// Gain in dB of a tone through the resampler, ignoring the start-up transient.
+    fn gain_db(rate: u32, frequency: f64) -> f64 {
+        let input = sine(rate, frequency, 1.0);
+        let output = resample_in_blocks(rate, &input);
+        let settled = &output[output.len() / 4..];
+        20.0 * (rms(settled) / rms(&input)).log10()
+    }
+
+    #[test]
+    fn output_length_is_exact_for_common_device_rates() {
+        for rate in [
+            8_000, 16_000, 22_050, 32_000, 44_100, 48_000, 88_200, 96_000,
+        ] {
+            for seconds in [1.0, 2.5] {
+                let input = vec![0.0_f32; (rate as f64 * seconds) as usize];
+                let output = resample_in_blocks(rate, &input);
+                let expected =
+                    (input.len() as u64 * TARGET_SAMPLE_RATE as u64).div_ceil(rate as u64) as usize;
+                assert_eq!(output.len(), expected, "rate {} for {}s", rate, seconds);
+            }
+        }
+    }
+
+    #[test]
+    fn block_size_does_not_change_output() {
+        let input = sine(44_100, 440.0, 0.5);
+        let mut whole = Vec::new();
+        Resampler::new(44_100, TARGET_SAMPLE_RATE).process(&input, &mut whole);
+        let split = resample_in_blocks(44_100, &input);
+
+        assert_eq!(whole.len(), split.len());
+        for (a, b) in whole.iter().zip(&split) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn passband_is_flat() {
+        for rate in [44_100, 48_000] {
+            for frequency in [200.0, 1_000.0, 3_000.0, 6_000.0] {
+                let gain = gain_db(rate, frequency);
+                assert!(
+                    gain.abs() < 0.1,
+                    "{} Hz at {} Hz: {:.3} dB",
+                    frequency,
+                    rate,
+                    gain
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn content_above_output_nyquist_is_rejected() {
+        for rate in [44_100, 48_000] {
+            for frequency in [9_000.0, 12_000.0, 15_000.0, 20_000.0] {
+                let gain = gain_db(rate, frequency);
+                assert!(
+                    gain < -60.0,
+                    "{} Hz at {} Hz: {:.1} dB",
+                    frequency,
+                    rate,
+                    gain
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matching_rates_pass_through() {
+        let input = sine(16_000, 1_000.0, 0.1);
+        let mut output = Vec::new();
+        Resampler::new(16_000, TARGET_SAMPLE_RATE).process(&input, &mut output);
+        assert_eq!(output, input);
+    }
+
+    /// Noise bursts shaped like speech: on for 3 of every 5 `burst` periods.
+    fn bursts(seconds: f64, seed: u64, burst: usize, amplitude: f64) -> Vec<i16> {
+        let mut state = seed;
+        let len = (seconds * VAD_SAMPLE_RATE as f64) as usize;
+        (0..len)
+            .map(|n| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let noise = ((state >> 33) as f64 / (1u64 << 31) as f64) * 2.0 - 1.0;
+                if (n / burst) % 5 < 3 {
+                    (noise * amplitude) as i16
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    /// `reference` delayed and passed through a short room response.
+    fn echo_of(reference: &[i16], delay: usize, gain: f64) -> Vec<i16> {
+        let response = [1.0, 0.5, 0.25, -0.2, 0.1];
+        (0..reference.len())
+            .map(|n| {
+                let mut acc = 0.0;
+                for (k, tap) in response.iter().enumerate() {
+                    let index = n as i64 - delay as i64 - (k * 7) as i64;
+                    if index >= 0 {
+                        acc += tap * reference[index as usize] as f64;
+                    }
+                }
+                (acc * gain) as i16
+            })
+            .collect()
+    }
+
+    fn power(samples: &[i16]) -> f64 {
+        samples.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / samples.len() as f64
+    }
+
+    fn cancel_in_chunks(mic: &mut [i16], reference: &[i16]) -> EchoCanceller {
+        let mut canceller = EchoCanceller::new();
+        for (mic, far) in mic.chunks_mut(4_000).zip(reference.chunks(4_000)) {
+            canceller.process(mic, far);
+        }
+        canceller
+    }
+
+    #[test]
+    fn echo_canceller_finds_delay_and_removes_echo() {
+        let reference = bursts(20.0, 7, 1_600, 8_000.0);
+        let echo = echo_of(&reference, 1_600, 0.4);
+        let mut mic = echo.clone();
+        let metrics = cancel_in_chunks(&mut mic, &reference).metrics();
+
+        let delay_ms = metrics.delay_ms.expect("delay estimated");
+        assert!((delay_ms - 100.0).abs() <= 8.0, "delay {}ms", delay_ms);
+        let tail = mic.len() - 5 * VAD_SAMPLE_RATE;
+        let removed_db = 10.0 * (power(&echo[tail..]) / power(&mic[tail..])).log10();
+        assert!(removed_db > 20.0, "only {:.1} dB removed", removed_db);
+        assert!(metrics.erle_db.unwrap() > 10.0);
+    }
+
+    #[test]
+    fn echo_canceller_stays_aligned_across_dropped_audio() {
+        let reference = bursts(20.0, 7, 1_600, 8_000.0);
+        let echo = echo_of(&reference, 1_600, 0.4);
+        let mut mic = echo.clone();
+        let mut canceller = EchoCanceller::new();
+        // The rings lost the second half of every fifth chunk.
+        for (index, (mic, far)) in mic
+            .chunks_mut(4_000)
+            .zip(reference.chunks(4_000))
+            .enumerate()
+        {
+            if index % 5 == 4 {
+                canceller.process(&mut mic[..2_000], &far[..2_000]);
+                mic[2_000..].fill(0);
+                canceller.skip(2_000, 2_000);
+            } else {
+                canceller.process(mic, far);
+            }
+        }
+
+        let delay_ms = canceller.metrics().delay_ms.expect("delay estimated");
+        assert!((delay_ms - 100.0).abs() <= 8.0, "delay {}ms", delay_ms);
+        // Echo of the lost reference cannot be removed; judge the audio
+        // whose echo path lies wholly after each gap.
+        let settled = |n: &usize| n % 20_000 >= 2_400 && n % 20_000 < 16_000;
+        let tail = mic.len() - 5 * VAD_SAMPLE_RATE;
+        let pick = |samples: &[i16]| -> Vec<i16> {
+            (tail..samples.len())
+                .filter(settled)
+                .map(|n| samples[n])
+                .collect()
+        };
+        let removed_db = 10.0 * (power(&pick(&echo)) / power(&pick(&mic))).log10();
+        assert!(removed_db > 20.0, "only {:.1} dB removed", removed_db);
+    }
+
+    #[test]
+    fn echo_canceller_keeps_near_end_speech() {
+        let reference = bursts(12.0, 7, 1_600, 8_000.0);
+        let near = bursts(12.0, 99, 2_300, 4_000.0);
+        let echo = echo_of(&reference, 800, 0.3);
+        let mut mic: Vec<i16> = echo
+            .iter()
+            .zip(&near)
+            .map(|(e, n)| e.saturating_add(*n))
+            .collect();
+        cancel_in_chunks(&mut mic, &reference);
+
+        let tail = mic.len() - 4 * VAD_SAMPLE_RATE;
+        let residual: Vec<i16> = mic[tail..]
+            .iter()
+            .zip(&near[tail..])
+            .map(|(m, n)| m.saturating_sub(*n))
+            .collect();
+        assert!(power(&residual) < power(&echo[tail..]) * 0.01);
+    }
+
+    #[test]
+    fn echo_canceller_leaves_unrelated_mic_untouched() {
+        let reference = bursts(12.0, 7, 1_600, 8_000.0);
+        let near = bursts(12.0, 99, 2_300, 4_000.0);
+        let mut mic = near.clone();
+        let metrics = cancel_in_chunks(&mut mic, &reference).metrics();
+
+        assert!(metrics.delay_ms.is_none());
+        assert_eq!(mic, near);
+    }
+
+    /// Voiced harmonics with a gliding pitch, syllable-rate envelope and
+    /// pauses between words, after a second of silence.
+    fn speech_like(seconds: f64, amplitude: f32) -> Vec<f32> {
+        let mut phase = 0.0_f32;
+        (0..(seconds * VAD_SAMPLE_RATE as f64) as usize)
+            .map(|n| {
+                let t = n as f32 / VAD_SAMPLE_RATE as f32;
+                if t < 1.0 || ((t * 1.3) as usize) % 3 == 2 {
+                    return 0.0;
+                }
+                let pitch = 140.0 + 40.0 * (std::f32::consts::TAU * 0.7 * t).sin();
+                phase += std::f32::consts::TAU * pitch / VAD_SAMPLE_RATE as f32;
+                let envelope = (std::f32::consts::TAU * 3.0 * t).sin().max(0.0);
+                let voiced: f32 = (1..12).map(|h| (phase * h as f32).sin() / h as f32).sum();
+                amplitude * envelope * voiced
+            })
+            .collect()
+    }
+
+    fn to_pcm(samples: &[f32]) -> Vec<i16> {
+        samples
+            .iter()
+            .map(|&value| (value * 32768.0) as i16)
+            .collect()
+    }
+
+    #[test]
+    fn noise_suppression_raises_snr() {
+        let clean = speech_like(10.0, 0.1);
+        let noise: Vec<f32> = bursts(10.0, 3, usize::MAX, 1_000.0)
+            .iter()
+            .map(|&sample| sample as f32 / 32768.0)
+            .collect();
+        let mixed: Vec<f32> = clean.iter().zip(&noise).map(|(c, n)| c + n).collect();
+        let mut samples = to_pcm(&mixed);
+        let mut processor = SourceProcessor::new(ProcessingConfig {
+            noise_suppression: true,
+            ..ProcessingConfig::default()
+        });
+        for chunk in samples.chunks_mut(4_000) {
+            processor.process(chunk);
+        }
+
+        // Compare the last 5s, compensating the one-frame delay.
+        let tail = 5 * VAD_SAMPLE_RATE;
+        let reference = to_pcm(&clean[tail..clean.len() - NS_FRAME]);
+        let output = &samples[tail + NS_FRAME..];
+        let error: Vec<i16> = output
+            .iter()
+            .zip(&reference)
+            .map(|(o, r)| o.saturating_sub(*r))
+            .collect();
+        let snr_in = 10.0 * (power(&reference) / power(&to_pcm(&noise[tail..]))).log10();
+        let snr_out = 10.0 * (power(&reference) / power(&error)).log10();
+        assert!(
+            snr_out > snr_in + 6.0,
+            "{:.1} dB -> {:.1} dB",
+            snr_in,
+            snr_out
+        );
+        assert_eq!(processor.raw(0, mixed.len()), to_pcm(&mixed).as_slice());
+    }
+
+    #[test]
+    fn raw_audio_stays_on_the_timeline_across_dropped_audio() {
+        let mut processor = SourceProcessor::new(ProcessingConfig {
+            high_pass_filter: true,
+            ..ProcessingConfig::default()
+        });
+        processor.start_at(1_000);
+        let before = tone(0.5, 0.3);
+        processor.process(&mut before.clone());
+        processor.skip(4_000);
+        let after = tone(0.25, 0.6);
+        processor.process(&mut after.clone());
+
+        assert_eq!(processor.raw(1_000, before.len()), before.as_slice());
+        let gap_start = 1_000 + before.len() as i64;
+        assert!(processor.raw(gap_start, 4_000).iter().all(|&s| s == 0));
+        assert_eq!(
+            processor.raw(gap_start + 4_000, after.len()),
+            after.as_slice()
+        );
+
+        // A gap longer than the history leaves only silence before new audio.
+        let keep = RAW_HISTORY_MS * VAD_SAMPLE_RATE / 1000;
+        let long_gap_start = gap_start + 4_000 + after.len() as i64;
+        processor.skip(keep * 3);
+        processor.process(&mut after.clone());
+        assert!(processor.raw(gap_start, 1_000).iter().all(|&s| s == 0));
+        let resumed = long_gap_start + (keep * 3) as i64;
+        assert!(processor.raw(resumed - 10, 10).iter().all(|&s| s == 0));
+        assert_eq!(processor.raw(resumed, after.len()), after.as_slice());
+    }
+
+    #[test]
+    fn automatic_gain_raises_quiet_speech_without_clipping() {
+        let quiet = speech_like(10.0, 0.01);
+        let mut samples = to_pcm(&quiet);
+        let mut processor = SourceProcessor::new(ProcessingConfig {
+            high_pass_filter: true,
+            automatic_gain_control: true,
+            ..ProcessingConfig::default()
+        });
+        for chunk in samples.chunks_mut(1_000) {
+            processor.process(chunk);
+        }
+        let tail = 6 * VAD_SAMPLE_RATE;
+        let gain_db = 10.0 * (power(&samples[tail..]) / power(&to_pcm(&quiet[tail..]))).log10();
+        assert!(gain_db > 12.0, "gain {:.1} dB", gain_db);
+
+        let mut loud = to_pcm(&speech_like(3.0, 0.9));
+        processor.process(&mut loud);
+        let peak = loud
+            .iter()
+            .map(|sample| sample.unsigned_abs())
+            .max()
+            .unwrap();
+        assert!(peak < 32_000, "peak {}", peak);
+    }
+
+    /// A low voiced tone of `seconds`, peak `amplitude` of full scale.
+    fn tone(seconds: f64, amplitude: f64) -> Vec<i16> {
+        (0..(seconds * VAD_SAMPLE_RATE as f64) as usize)
+            .map(|n| {
+                let t = n as f64 / VAD_SAMPLE_RATE as f64;
+                (amplitude * 32_767.0 * (std::f64::consts::TAU * 180.0 * t).sin()) as i16
+            })
+            .collect()
+    }
+
+    fn segment(vad: &mut VoiceActivitySegmenter, samples: &[i16]) -> Vec<Utterance> {
+        let mut utterances = Vec::new();
+        for block in samples.chunks(1_000) {
+            utterances.extend(vad.push(block));
+        }
+        utterances.extend(vad.flush());
+        utterances
+    }
+
+    fn ms(samples: i64) -> i64 {
+        samples * 1000 / VAD_SAMPLE_RATE as i64
+    }
+
+    #[test]
+    fn vad_cuts_utterances_at_pauses_with_pre_roll() {
+        let mut audio = vec![0; VAD_SAMPLE_RATE];
+        audio.extend(tone(2.0, 0.3));
+        audio.extend(vec![0; VAD_SAMPLE_RATE]);
+        audio.extend(tone(1.0, 0.3));
+        audio.extend(vec![0; VAD_SAMPLE_RATE]);
+
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let utterances = segment(&mut vad, &audio);
+
+        assert_eq!(utterances.len(), 2);
+        // Onset at 1s, less about 200ms of pre-roll; ends 600ms into the pause.
+        let first = &utterances[0];
+        assert!((ms(first.start_sample) - 800).abs() <= 40);
+        assert!((ms(first.start_sample + first.samples.len() as i64) - 3_600).abs() <= 40);
+        let second = &utterances[1];
+        assert!((ms(second.start_sample) - 3_800).abs() <= 40);
+        assert!((second.samples.len() as i64 - 1_800 * 16).abs() <= 40 * 16);
+    }
+
+    #[test]
+    fn vad_drops_blips_shorter_than_an_utterance() {
+        let mut audio = vec![0; VAD_SAMPLE_RATE];
+        audio.extend(tone(0.1, 0.3));
+        audio.extend(vec![0; VAD_SAMPLE_RATE]);
+
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        assert!(segment(&mut vad, &audio).is_empty());
+    }
+
+    #[test]
+    fn vad_splits_long_speech_and_keeps_every_sample() {
+        let audio = tone(20.0, 0.3);
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let utterances = segment(&mut vad, &audio);
+
+        assert_eq!(utterances.len(), 2);
+        assert!(utterances[0].samples.len() <= 15 * VAD_SAMPLE_RATE);
+        assert_eq!(
+            utterances[1].start_sample,
+            utterances[0].start_sample + utterances[0].samples.len() as i64
+        );
+        let total: usize = utterances.iter().map(|u| u.samples.len()).sum();
+        assert_eq!(total, audio.len());
+    }
+
+    #[test]
+    fn vad_noise_floor_does_not_lock_out_speech_after_loud_hiss() {
+        // Hiss loud enough to clear the fixed threshold but rejected for its
+        // zero-crossing rate, then moderate speech.
+        let mut audio = bursts(10.0, 5, usize::MAX, 1_700.0);
+        audio.extend(tone(2.0, 0.1));
+        audio.extend(vec![0; VAD_SAMPLE_RATE]);
+
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let utterances = segment(&mut vad, &audio);
+
+        assert_eq!(utterances.len(), 1);
+        let start_ms = ms(utterances[0].start_sample);
+        assert!(
+            (9_700..=10_000).contains(&start_ms),
+            "starts at {}ms",
+            start_ms
+        );
+    }
+
+    #[test]
+    fn vad_start_at_moves_the_timeline() {
+        let mut audio = vec![0; VAD_SAMPLE_RATE];
+        audio.extend(tone(1.0, 0.3));
+        audio.extend(vec![0; VAD_SAMPLE_RATE]);
+
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        vad.start_at(5 * VAD_SAMPLE_RATE as i64);
+        let utterances = segment(&mut vad, &audio);
+        assert!((ms(utterances[0].start_sample) - 5_800).abs() <= 40);
+    }
+
+    #[test]
+    fn paused_audio_never_reaches_an_utterance() {
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let mut before = vec![0; VAD_SAMPLE_RATE];
+        before.extend(tone(1.0, 0.3));
+        let mut utterances = Vec::new();
+        for block in before.chunks(1_000) {
+            utterances.extend(vad.push_drained(block, 0));
+        }
+
+        // Two seconds of speech while paused, drained tick by tick.
+        for block in tone(2.0, 0.3).chunks(1_000) {
+            let mut drained = AudioDrain {
+                microphone_samples: block.to_vec(),
+                system_samples: Vec::new(),
+                microphone_dropped: 0,
+                system_dropped: 0,
+            };
+            drained.discard();
+            assert!(drained.microphone_samples.is_empty());
+            assert_eq!(drained.microphone_dropped, block.len() as u64);
+            utterances
+                .extend(vad.push_drained(&drained.microphone_samples, drained.microphone_dropped));
+        }
+
+        let mut after = vec![0; VAD_SAMPLE_RATE];
+        after.extend(tone(1.0, 0.3));
+        after.extend(vec![0; VAD_SAMPLE_RATE]);
+        utterances.extend(segment(&mut vad, &after));
+
+        assert_eq!(utterances.len(), 2);
+        for utterance in &utterances {
+            let start = ms(utterance.start_sample);
+            let end = ms(utterance.start_sample + utterance.samples.len() as i64);
+            assert!(end <= 2_000 || start >= 4_000, "{}..{}ms", start, end);
+        }
+        // The tone starts at 5s; pre-roll reaches back into the silence before it.
+        assert!((ms(utterances[1].start_sample) - 4_800).abs() <= 40);
+    }
+
+    #[test]
+    fn vad_skip_ends_speech_and_leaves_a_gap() {
+        let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let mut audio = vec![0; VAD_SAMPLE_RATE];
+        audio.extend(tone(1.5, 0.3));
+        let mut utterances = Vec::new();
+        for block in audio.chunks(1_000) {
+            utterances.extend(vad.push(block));
+        }
+        assert!(utterances.is_empty());
+
+        let cut = vad.skip(2 * VAD_SAMPLE_RATE).unwrap();
+        assert_eq!(ms(cut.start_sample + cut.samples.len() as i64), 2_500);
+
+        let mut later = tone(1.0, 0.3);
+        later.extend(vec![0; VAD_SAMPLE_RATE]);
+        let utterances = segment(&mut vad, &later);
+        assert!((ms(utterances[0].start_sample) - 4_500).abs() <= 40);
+    }
+}
@@ -0,0 +1,391 @@
+use crate::asr::AsrSegment;
+use crate::audio::SourceRole;
+use crate::streaming::words;
+
+/// Capture time a final waits for a duplicate from the other source.
+pub const HOLD_MS: i64 = 2_000;
+/// Released finals remembered for duplicates that arrive after the hold.
+const RELEASED_MEMORY_MS: i64 = 10_000;
+/// Share of the shorter segment's span the two must overlap.
+const MIN_TIME_OVERLAP: f64 = 0.5;
+/// Share of the longer text's words the two must have in common, in order.
+const MIN_TEXT_SIMILARITY: f64 = 0.6;
+/// Slack for the two sources' independently cut segment boundaries.
+const TIMESTAMP_TOLERANCE_MS: i64 = 300;
+
+/// A final from one source waiting to be emitted.
+pub struct StagedFinal<T> {
+    pub role: SourceRole,
+    pub segment: AsrSegment,
+    /// Loudness of the audio the segment came from, used to decide which
+    /// copy of a duplicate is the real one and which is bleed.
+    pub energy: f64,
+    /// Passed through untouched for the caller.
+    pub extra: T,
+    staged_at_ms: i64,
+}
+
+struct Released {
+    role: SourceRole,
+    words: Vec<String>,
+    t_start_ms: i64,
+    t_end_ms: i64,
+    released_at_ms: i64,
+}
+
+/// Holds finals briefly so the same speech transcribed from both the
+/// microphone and system audio (speaker bleed the echo canceller missed) is
+/// emitted once, from the source where it was loudest.
+///
+/// Times are milliseconds on the shared capture clock.
+pub struct DuplicateSuppressor<T> {
+    pending: Vec<StagedFinal<T>>,
+    released: Vec<Released>,
+    suppressed: u64,
+}
+
+impl<T> DuplicateSuppressor<T> {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            released: Vec::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// Finals dropped as duplicates so far.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Stage a final from `role`. A duplicate of a staged final from the other
+    /// source is merged into whichever copy is louder; a duplicate of one
+    /// already emitted is dropped. Returns the copy that was dropped.
+    pub fn push(
+        &mut self,
+        role: SourceRole,
+        segment: AsrSegment,
+        energy: f64,
+        extra: T,
+        now_ms: i64,
+    ) -> Option<StagedFinal<T>> {
+        let segment_words: Vec<String> = words(&segment.text).collect();
+
+        let already_emitted = self.released.iter().any(|released| {
+            released.role != role
+                && is_duplicate(
+                    (released.t_start_ms, released.t_end_ms, &released.words),
+                    (segment.t_start_ms, segment.t_end_ms, &segment_words),
+                )
+        });
+        if already_emitted {
+            self.log_suppressed(role, &segment.text, "already emitted");
+            return Some(StagedFinal {
+                role,
+                segment,
+                energy,
+                extra,
+                staged_at_ms: now_ms,
+            });
+        }
+
+        let duplicate = self.pending.iter().position(|staged| {
+            let staged_words: Vec<String> = words(&staged.segment.text).collect();
+            staged.role != role
+                && is_duplicate(
+                    (
+                        staged.segment.t_start_ms,
+                        staged.segment.t_end_ms,
+                        &staged_words,
+                    ),
+                    (segment.t_start_ms, segment.t_end_ms, &segment_words),
+                )
+        });
+        let Some(index) = duplicate else {
+            self.pending.push(StagedFinal {
+                role,
+                segment,
+                energy,
+                extra,
+                staged_at_ms: now_ms,
+            });
+            return None;
+        };
+
+        // Keep the louder copy, covering the span of both.
+        let staged = &mut self.pending[index];
+        let t_start_ms = staged.segment.t_start_ms.min(segment.t_start_ms);
+        let t_end_ms = staged.segment.t_end_ms.max(segment.t_end_ms);
+        let incoming = StagedFinal {
+            role,
+            segment,
+            energy,
+            extra,
+            staged_at_ms: staged.staged_at_ms,
+        };
+        let weaker = if energy > staged.energy {
+            std::mem::replace(staged, incoming)
+        } else {
+            incoming
+        };
+        staged.segment.t_start_ms = t_start_ms;
+        staged.segment.t_end_ms = t_end_ms;
+        self.log_suppressed(weaker.role, &weaker.segment.text, "quieter duplicate");
+        Some(weaker)
+    }
+
+    /// Finals whose hold has expired, oldest first.
+    pub fn release(&mut self, now_ms: i64) -> Vec<StagedFinal<T>> {
+        self.release_where(now_ms, |staged| staged.staged_at_ms + HOLD_MS <= now_ms)
+    }
+
+    /// Every staged final, for when capture ends.
+    pub fn flush(&mut self, now_ms: i64) -> Vec<StagedFinal<T>> {
+        self.release_where(now_ms, |_| true)
+    }
+
+    fn release_where(
+        &mut self,
+        now_ms: i64,
+        ready: impl Fn(&StagedFinal<T>) -> bool,
+    ) -> Vec<StagedFinal<T>> {
+        self.released
+            .retain(|released| released.released_at_ms + RELEASED_MEMORY_MS > now_ms);
+
+        let (mut ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(ready);
+        self.pending = pending;
+        ready.sort_by_key(|staged| staged.segment.t_start_ms);
+
+        for staged in &ready {
+            self.released.push(Released {
+                role: staged.role,
+                words: words(&staged.segment.text).collect(),
+                t_start_ms: staged.segment.t_start_ms,
+                t_end_ms: staged.segment.t_end_ms,
+                released_at_ms: now_ms,
+            });
+        }
+        ready
+    }
+
+    fn log_suppressed(&mut self, role: SourceRole, text: &str, reason: &str) {
+        self.suppressed += 1;
+        log::debug!(
+            "Suppressed cross-source duplicate from {} ({}): {}",
+            role.label(),
+            reason,
+            text
+        );
+    }
+}
+
+/// Whether two segments from different sources cover the same speech: they
+/// overlap in time and mostly say the same words.
+fn is_duplicate(a: (i64, i64, &[String]), b: (i64, i64, &[String])) -> bool {
+    let (a_start, a_end, a_words) = a;
+    let (b_start, b_end, b_words) = b;
+    if a_words.is_empty() || b_words.is_empty() {
+        return false;
+    }
+
+    let overlap = a_end.min(b_end) - a_start.max(b_start) + TIMESTAMP_TOLERANCE_MS;
+    let shorter = (a_end - a_start).min(b_end - b_start).max(1);
+    if (overlap as f64) < shorter as f64 * MIN_TIME_OVERLAP {
+        return false;
+    }
+
+    let common = common_subsequence_len(a_words, b_words);
+    common as f64 >= a_words.len().max(b_words.len()) as f64 * MIN_TEXT_SIMILARITY
+}
+
+/// Length of the longest common subsequence of two word lists.
+fn common_subsequence_len(a: &[String], b: &[String]) -> usize {
+    let mut previous = vec![0; b.len() + 1];
+    let mut current = vec![0; b.len() + 1];
+    for word in a {
+        for (j, other) in b.iter().enumerate() {
+            current[j + 1] = if word == other {
+                previous[j] + 1
+            } else {
+                previous[j + 1].max(current[j])
+            };
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::segment;
+
+    fn texts(finals: &[StagedFinal<u32>]) -> Vec<(SourceRole, &str)> {
+        finals
+            .iter()
+            .map(|staged| (staged.role, staged.segment.text.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn finals_wait_out_the_hold() {
+        let mut suppressor = DuplicateSuppressor::new();
+        let dropped = suppressor.push(
+            SourceRole::Microphone,
+            segment("hello there", 0, 1_000),
+            0.2,
+            1,
+            1_000,
+        );
+        assert!(dropped.is_none());
+
+        assert!(suppressor.release(1_000 + HOLD_MS - 1).is_empty());
+        let released = suppressor.release(1_000 + HOLD_MS);
+        assert_eq!(texts(&released), [(SourceRole::Microphone, "hello there")]);
+    }
+
+    #[test]
+    fn the_louder_copy_of_a_duplicate_is_kept() {
+        let mut suppressor = DuplicateSuppressor::new();
+        suppressor.push(
+            SourceRole::Microphone,
+            segment("we ship on friday", 1_000, 2_500),
+            0.05,
+            1,
+            2_500,
+        );
+        let dropped = suppressor
+            .push(
+                SourceRole::Loopback,
+                segment("We ship on Friday.", 1_100, 2_800),
+                0.3,
+                2,
+                2_800,
+            )
+            .unwrap();
+
+        assert_eq!((dropped.role, dropped.extra), (SourceRole::Microphone, 1));
+        let released = suppressor.flush(3_000);
+        assert_eq!(
+            texts(&released),
+            [(SourceRole::Loopback, "We ship on Friday.")]
+        );
+        assert_eq!(released[0].extra, 2);
+        assert_eq!(
+            (released[0].segment.t_start_ms, released[0].segment.t_end_ms),
+            (1_000, 2_800)
+        );
+        assert_eq!(suppressor.suppressed(), 1);
+    }
+
+    #[test]
+    fn a_quieter_late_copy_is_dropped() {
+        let mut suppressor = DuplicateSuppressor::new();
+        suppressor.push(
+            SourceRole::Loopback,
+            segment("see you tomorrow", 0, 1_500),
+            0.3,
+            1,
+            1_500,
+        );
+        let dropped = suppressor
+            .push(
+                SourceRole::Microphone,
+                segment("see you tomorrow", 0, 1_400),
+                0.1,
+                2,
+                1_600,
+            )
+            .unwrap();
+
+        assert_eq!(dropped.extra, 2);
+        assert_eq!(
+            texts(&suppressor.flush(2_000)),
+            [(SourceRole::Loopback, "see you tomorrow")]
+        );
+    }
+
+    #[test]
+    fn a_copy_arriving_after_release_is_dropped() {
+        let mut suppressor = DuplicateSuppressor::new();
+        suppressor.push(
+            SourceRole::Microphone,
+            segment("let's get started", 0, 1_200),
+            0.3,
+            1,
+            1_200,
+        );
+        assert_eq!(suppressor.release(1_200 + HOLD_MS).len(), 1);
+
+        let dropped = suppressor.push(
+            SourceRole::Loopback,
+            segment("let's get started", 100, 1_300),
+            0.5,
+            2,
+            4_000,
+        );
+        assert!(dropped.is_some());
+        assert!(suppressor.flush(4_000).is_empty());
+    }
+
+    #[test]
+    fn the_same_source_is_never_its_own_duplicate() {
+        let mut suppressor = DuplicateSuppressor::new();
+        for (start, extra) in [(0, 1), (200, 2)] {
+            let dropped = suppressor.push(
+                SourceRole::Microphone,
+                segment("yes", start, start + 500),
+                0.2,
+                extra,
+                1_000,
+            );
+            assert!(dropped.is_none());
+        }
+        assert_eq!(suppressor.flush(1_000).len(), 2);
+    }
+
+    #[test]
+    fn different_words_or_times_are_not_duplicates() {
+        let mut suppressor = DuplicateSuppressor::new();
+        suppressor.push(
+            SourceRole::Microphone,
+            segment("the budget is approved", 0, 2_000),
+            0.2,
+            1,
+            2_000,
+        );
+        let other_words = suppressor.push(
+            SourceRole::Loopback,
+            segment("can you hear me now", 0, 2_000),
+            0.2,
+            2,
+            2_000,
+        );
+        let other_time = suppressor.push(
+            SourceRole::Loopback,
+            segment("the budget is approved", 6_000, 8_000),
+            0.2,
+            3,
+            8_000,
+        );
+        assert!(other_words.is_none() && other_time.is_none());
+
+        let released = suppressor.flush(8_000);
+        let starts: Vec<i64> = released.iter().map(|s| s.segment.t_start_ms).collect();
+        assert_eq!(starts, [0, 0, 6_000]);
+    }
+
+    #[test]
+    fn released_memory_expires() {
+        let mut suppressor = DuplicateSuppressor::new();
+        suppressor.push(SourceRole::Microphone, segment("okay", 0, 500), 0.2, 1, 500);
+        suppressor.flush(500);
+
+        let later = 500 + RELEASED_MEMORY_MS;
+        suppressor.release(later);
+        let dropped = suppressor.push(SourceRole::Loopback, segment("okay", 0, 500), 0.2, 2, later);
+        assert!(dropped.is_none());
+    }
+}
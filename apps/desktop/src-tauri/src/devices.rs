@@ -0,0 +1,233 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+const SELECTION_FILE: &str = "audio-devices.json";
+/// ID prefix for PulseAudio/PipeWire monitor sources (Linux loopback).
+pub const MONITOR_ID_PREFIX: &str = "monitor:";
+
+/// One stream configuration range a device supports.
+#[derive(Debug, Serialize, Clone)]
+#[allow(non_snake_case)]
+pub struct DeviceConfigInfo {
+    channels: u16,
+    minSampleRate: u32,
+    maxSampleRate: u32,
+    sampleFormat: String,
+}
+
+/// A capture-capable device as shown in the device picker.
+#[derive(Debug, Serialize, Clone)]
+#[allow(non_snake_case)]
+pub struct AudioDeviceInfo {
+    /// Stable across restarts as long as the device keeps its name.
+    id: String,
+    name: String,
+    /// "input", "output" or "monitor"; outputs and monitors are captured
+    /// as loopback.
+    kind: String,
+    isDefault: bool,
+    defaultSampleRate: Option<u32>,
+    defaultChannels: Option<u16>,
+    supportedConfigs: Vec<DeviceConfigInfo>,
+}
+
+/// Devices chosen by the user. `None` means follow the system default.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceSelection {
+    pub microphone_id: Option<String>,
+    pub loopback_id: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DeviceKind {
+    Input,
+    Output,
+}
+
+impl DeviceKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            DeviceKind::Input => "input",
+            DeviceKind::Output => "output",
+        }
+    }
+}
+
+/// Devices of one kind paired with their IDs. Identical names get a `#n`
+/// suffix in enumeration order so two of the same headset stay distinct.
+fn devices_with_ids(host: &cpal::Host, kind: DeviceKind) -> Vec<(String, cpal::Device)> {
+    let devices = match kind {
+        DeviceKind::Input => host.input_devices().map(|d| d.collect::<Vec<_>>()),
+        DeviceKind::Output => host.output_devices().map(|d| d.collect::<Vec<_>>()),
+    };
+    let devices = match devices {
+        Ok(devices) => devices,
+        Err(error) => {
+            log::warn!("Failed to enumerate {} devices: {}", kind.as_str(), error);
+            return Vec::new();
+        }
+    };
+
+    let mut seen: Vec<String> = Vec::new();
+    devices
+        .into_iter()
+        .filter_map(|device| {
+            let name = device.name().ok()?;
+            let duplicates = seen.iter().filter(|n| **n == name).count();
+            seen.push(name.clone());
+            let id = if duplicates == 0 {
+                format!("{}:{}", kind.as_str(), name)
+            } else {
+                format!("{}:{}#{}", kind.as_str(), name, duplicates + 1)
+            };
+            Some((id, device))
+        })
+        .collect()
+}
+
+fn describe(
+    id: String,
+    device: &cpal::Device,
+    kind: DeviceKind,
+    default_name: &Option<String>,
+) -> AudioDeviceInfo {
+    let name = device.name().unwrap_or_default();
+    let default_config = match kind {
+        DeviceKind::Input => device.default_input_config(),
+        DeviceKind::Output => device.default_output_config(),
+    }
+    .ok();
+    let supported: Vec<DeviceConfigInfo> = match kind {
+        DeviceKind::Input => device
+            .supported_input_configs()
+            .map(|configs| configs.collect::<Vec<_>>()),
+        DeviceKind::Output => device
+            .supported_output_configs()
+            .map(|configs| configs.collect::<Vec<_>>()),
+    }
+    .unwrap_or_default()
+    .into_iter()
+    .map(|range| DeviceConfigInfo {
+        channels: range.channels(),
+        minSampleRate: range.min_sample_rate().0,
+        maxSampleRate: range.max_sample_rate().0,
+        sampleFormat: range.sample_format().to_string(),
+    })
+    .collect();
+
+    AudioDeviceInfo {
+        isDefault: default_name.as_deref() == Some(name.as_str()),
+        id,
+        name,
+        kind: kind.as_str().to_string(),
+        defaultSampleRate: default_config.as_ref().map(|c| c.sample_rate().0),
+        defaultChannels: default_config.as_ref().map(|c| c.channels()),
+        supportedConfigs: supported,
+    }
+}
+
+/// All input and output devices on the default host.
+pub fn list_devices() -> Vec<AudioDeviceInfo> {
+    let host = cpal::default_host();
+    let default_input = host.default_input_device().and_then(|d| d.name().ok());
+    let default_output = host.default_output_device().and_then(|d| d.name().ok());
+
+    let mut devices = Vec::new();
+    for (kind, default_name) in [
+        (DeviceKind::Input, &default_input),
+        (DeviceKind::Output, &default_output),
+    ] {
+        for (id, device) in devices_with_ids(&host, kind) {
+            devices.push(describe(id, &device, kind, default_name));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    devices.extend(list_monitor_devices());
+
+    devices
+}
+
+/// Monitor sources stand in for output devices as loopback on Linux.
+#[cfg(target_os = "linux")]
+fn list_monitor_devices() -> Vec<AudioDeviceInfo> {
+    let sources = match crate::monitor::list_monitor_sources() {
+        Ok(sources) => sources,
+        Err(error) => {
+            log::warn!("Failed to list monitor sources: {}", error);
+            return Vec::new();
+        }
+    };
+    let default_source = crate::monitor::default_monitor_source().ok();
+
+    sources
+        .into_iter()
+        .map(|source| AudioDeviceInfo {
+            id: format!("{}{}", MONITOR_ID_PREFIX, source),
+            isDefault: default_source.as_deref() == Some(source.as_str()),
+            name: source,
+            kind: "monitor".to_string(),
+            defaultSampleRate: Some(16_000),
+            defaultChannels: Some(1),
+            supportedConfigs: Vec::new(),
+        })
+        .collect()
+}
+
+/// Whether `id` names a device that is currently connected.
+pub fn device_exists(host: &cpal::Host, id: &str) -> bool {
+    #[cfg(target_os = "linux")]
+    if let Some(source) = id.strip_prefix(MONITOR_ID_PREFIX) {
+        return crate::monitor::list_monitor_sources()
+            .map(|sources| sources.iter().any(|s| s == source))
+            .unwrap_or(false);
+    }
+
+    find_device(host, id).is_some()
+}
+
+/// Look up a device by the ID `list_devices` reported for it.
+pub fn find_device(host: &cpal::Host, id: &str) -> Option<cpal::Device> {
+    let kind = if id.starts_with("output:") {
+        DeviceKind::Output
+    } else {
+        DeviceKind::Input
+    };
+    devices_with_ids(host, kind)
+        .into_iter()
+        .find(|(device_id, _)| device_id == id)
+        .map(|(_, device)| device)
+}
+
+fn selection_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(SELECTION_FILE))
+        .map_err(|e| e.to_string())
+}
+
+/// Saved device choice, or defaults when nothing was saved or it is unreadable.
+pub fn load_selection(app: &AppHandle) -> DeviceSelection {
+    let Ok(path) = selection_path(app) else {
+        return DeviceSelection::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+            log::warn!("Ignoring unreadable {}: {}", path.display(), error);
+            DeviceSelection::default()
+        }),
+        Err(_) => DeviceSelection::default(),
+    }
+}
+
+pub fn save_selection(app: &AppHandle, selection: &DeviceSelection) -> Result<(), String> {
+    let path = selection_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(selection).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
@@ -0,0 +1,135 @@
+//! Errors returned by Tauri commands and carried by ASR_ERROR events.
+//!
+//! Both serialize as `{ code, message, recovery }` so the webview can show
+//! the message and offer the action that is most likely to fix it.
+
+use crate::session::SessionError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// A capture device is missing, busy or failed to open.
+    DeviceUnavailable(String),
+    /// The OS refused access to the microphone or system audio.
+    PermissionDenied(String),
+    /// The model file does not exist.
+    ModelMissing(String),
+    /// The model file exists but could not be loaded.
+    ModelLoadFailed(String),
+    /// A model download was interrupted or did not match its checksum.
+    DownloadFailed(String),
+    /// The whisper sidecar could not be run or kept crashing.
+    SidecarCrashed(String),
+    Timeout(String),
+    /// The command is not allowed in the session's current phase.
+    InvalidState(String),
+    /// Decoding a chunk failed; later chunks may still succeed.
+    TranscriptionFailed(String),
+    InvalidInput(String),
+    Internal(String),
+}
+
+/// What the user can do about an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Recovery {
+    ChooseDevice,
+    GrantPermission,
+    DownloadModel,
+    /// Start transcription again.
+    Restart,
+    /// Repeat the command.
+    Retry,
+    None,
+}
+
+impl AppError {
+    /// Classify a capture failure; platforms only report a denied microphone
+    /// permission through the error text.
+    pub fn capture(message: String) -> Self {
+        let lower = message.to_lowercase();
+        let denied = ["permission", "denied", "not authorized", "not permitted"]
+            .iter()
+            .any(|needle| lower.contains(needle));
+        if denied {
+            AppError::PermissionDenied(message)
+        } else {
+            AppError::DeviceUnavailable(message)
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::DeviceUnavailable(_) => "deviceUnavailable",
+            AppError::PermissionDenied(_) => "permissionDenied",
+            AppError::ModelMissing(_) => "modelMissing",
+            AppError::ModelLoadFailed(_) => "modelLoadFailed",
+            AppError::DownloadFailed(_) => "downloadFailed",
+            AppError::SidecarCrashed(_) => "sidecarCrashed",
+            AppError::Timeout(_) => "timeout",
+            AppError::InvalidState(_) => "invalidState",
+            AppError::TranscriptionFailed(_) => "transcriptionFailed",
+            AppError::InvalidInput(_) => "invalidInput",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::DeviceUnavailable(message)
+            | AppError::PermissionDenied(message)
+            | AppError::ModelMissing(message)
+            | AppError::ModelLoadFailed(message)
+            | AppError::DownloadFailed(message)
+            | AppError::SidecarCrashed(message)
+            | AppError::Timeout(message)
+            | AppError::InvalidState(message)
+            | AppError::TranscriptionFailed(message)
+            | AppError::InvalidInput(message)
+            | AppError::Internal(message) => message,
+        }
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            AppError::DeviceUnavailable(_) => Recovery::ChooseDevice,
+            AppError::PermissionDenied(_) => Recovery::GrantPermission,
+            AppError::ModelMissing(_) | AppError::ModelLoadFailed(_) => Recovery::DownloadModel,
+            AppError::SidecarCrashed(_) | AppError::Internal(_) => Recovery::Restart,
+            AppError::DownloadFailed(_)
+            | AppError::Timeout(_)
+            | AppError::TranscriptionFailed(_) => Recovery::Retry,
+            AppError::InvalidState(_) | AppError::InvalidInput(_) => Recovery::None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("AppError", 3)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", self.message())?;
+        error.serialize_field("recovery", &self.recovery())?;
+        error.end()
+    }
+}
+
+/// Untyped errors from helpers (locks, files, events) are internal.
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Internal(message)
+    }
+}
+
+impl From<SessionError> for AppError {
+    fn from(error: SessionError) -> Self {
+        AppError::InvalidState(error.to_string())
+    }
+}
@@ -0,0 +1,451 @@
+//! Minimal streaming FLAC encoder for 16kHz mono 16-bit PCM: fixed-predictor
+//! subframes with Rice-coded residuals. Enough to keep recordings lossless at
+//! roughly half the size of WAV without a native codec dependency.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const SAMPLE_RATE: u32 = 16_000;
+const BLOCK_SIZE: usize = 4_096;
+/// Offset of the byte holding the top of the 36-bit total sample count:
+/// "fLaC", the metadata block header, then 13 bytes into STREAMINFO.
+const TOTAL_SAMPLES_OFFSET: u64 = 4 + 4 + 13;
+/// Largest Rice parameter for the 4-bit encoding.
+const MAX_RICE_PARAMETER: u32 = 14;
+
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    pending: Vec<i16>,
+    frame_number: u64,
+    total_samples: u64,
+}
+
+impl FlacWriter {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut writer = Self {
+            file: BufWriter::new(file),
+            pending: Vec::with_capacity(BLOCK_SIZE),
+            frame_number: 0,
+            total_samples: 0,
+        };
+        writer.write_header().map_err(|e| e.to_string())?;
+        Ok(writer)
+    }
+
+    /// Samples accepted so far, including any not yet encoded.
+    pub fn samples(&self) -> u64 {
+        self.total_samples
+    }
+
+    pub fn write(&mut self, samples: &[i16]) -> Result<(), String> {
+        self.total_samples += samples.len() as u64;
+        let mut rest = samples;
+        while !rest.is_empty() {
+            let take = (BLOCK_SIZE - self.pending.len()).min(rest.len());
+            self.pending.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            if self.pending.len() == BLOCK_SIZE {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode the final partial block and record the stream length. Files
+    /// never finished (e.g. after a crash) stay decodable, with the length
+    /// left as unknown.
+    pub fn finish(mut self) -> Result<u64, String> {
+        if !self.pending.is_empty() {
+            self.flush_block()?;
+        }
+        self.patch_total_samples().map_err(|e| e.to_string())?;
+        Ok(self.total_samples)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let mut streaminfo = BitWriter::new();
+        streaminfo.put(BLOCK_SIZE as u64, 16); // min block size
+        streaminfo.put(BLOCK_SIZE as u64, 16); // max block size
+        streaminfo.put(0, 24); // min frame size: unknown
+        streaminfo.put(0, 24); // max frame size: unknown
+        streaminfo.put(SAMPLE_RATE as u64, 20);
+        streaminfo.put(0, 3); // channels - 1
+        streaminfo.put(15, 5); // bits per sample - 1
+        streaminfo.put(0, 36); // total samples: patched by `finish`
+        streaminfo.put(0, 64); // MD5: not computed
+        streaminfo.put(0, 64);
+
+        self.file.write_all(b"fLaC")?;
+        // Last metadata block, type STREAMINFO, 34 bytes.
+        self.file.write_all(&[0x80, 0, 0, 34])?;
+        self.file.write_all(&streaminfo.into_bytes())
+    }
+
+    fn patch_total_samples(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let file = self.file.get_mut();
+        // The count's first byte starts with the low four bits of the bit
+        // depth field (all ones for 16-bit).
+        let mut bytes = [0_u8; 5];
+        bytes[0] = 0xF0 | ((self.total_samples >> 32) & 0x0F) as u8;
+        bytes[1..].copy_from_slice(&(self.total_samples as u32).to_be_bytes());
+        file.seek(SeekFrom::Start(TOTAL_SAMPLES_OFFSET))?;
+        file.write_all(&bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        file.flush()
+    }
+
+    fn flush_block(&mut self) -> Result<(), String> {
+        let frame = encode_frame(&self.pending, self.frame_number);
+        self.pending.clear();
+        self.frame_number += 1;
+        self.file.write_all(&frame).map_err(|e| e.to_string())
+    }
+}
+
+fn encode_frame(samples: &[i16], frame_number: u64) -> Vec<u8> {
+    let mut bits = BitWriter::new();
+    bits.put(0b1111_1111_1111_1000, 16); // sync, fixed block size
+    let block_size_code = if samples.len() == BLOCK_SIZE {
+        0b1100 // 4096
+    } else {
+        0b0111 // 16-bit (size - 1) after the header
+    };
+    bits.put(block_size_code, 4);
+    bits.put(0b0101, 4); // 16kHz
+    bits.put(0b0000, 4); // mono
+    bits.put(0b100, 3); // 16 bits per sample
+    bits.put(0, 1);
+    for byte in utf8_number(frame_number) {
+        bits.put(byte as u64, 8);
+    }
+    if block_size_code == 0b0111 {
+        bits.put(samples.len() as u64 - 1, 16);
+    }
+    let crc = crc8(&bits.bytes);
+    bits.put(crc as u64, 8);
+
+    encode_subframe(&mut bits, samples);
+    bits.align();
+    let crc = crc16(&bits.bytes);
+    bits.put(crc as u64, 16);
+    bits.into_bytes()
+}
+
+fn encode_subframe(bits: &mut BitWriter, samples: &[i16]) {
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        bits.put(0b0000_0000, 8); // CONSTANT
+        bits.put(samples[0] as u16 as u64, 16);
+        return;
+    }
+
+    // Pick the fixed predictor order with the smallest residual.
+    let (order, residuals) = (0..=4.min(samples.len() - 1))
+        .map(|order| (order, fixed_residuals(samples, order)))
+        .min_by_key(|(_, residuals)| {
+            residuals
+                .iter()
+                .map(|r| r.unsigned_abs() as u64)
+                .sum::<u64>()
+        })
+        .expect("at least order 0");
+
+    bits.put(0b0001_0000 | (order as u64) << 1, 8); // FIXED, no wasted bits
+    for &sample in &samples[..order] {
+        bits.put(sample as u16 as u64, 16);
+    }
+
+    let parameter = rice_parameter(&residuals);
+    bits.put(0b00, 2); // Rice, 4-bit parameters
+    bits.put(0, 4); // partition order 0
+    bits.put(parameter as u64, 4);
+    for &residual in &residuals {
+        let folded = ((residual << 1) ^ (residual >> 31)) as u32;
+        bits.put_unary(folded >> parameter);
+        bits.put((folded & ((1 << parameter) - 1)) as u64, parameter);
+    }
+}
+
+fn fixed_residuals(samples: &[i16], order: usize) -> Vec<i32> {
+    let x = |i: usize| samples[i] as i32;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => x(i),
+            1 => x(i) - x(i - 1),
+            2 => x(i) - 2 * x(i - 1) + x(i - 2),
+            3 => x(i) - 3 * x(i - 1) + 3 * x(i - 2) - x(i - 3),
+            _ => x(i) - 4 * x(i - 1) + 6 * x(i - 2) - 4 * x(i - 3) + x(i - 4),
+        })
+        .collect()
+}
+
+/// Rice parameter close to optimal for the mean folded residual.
+fn rice_parameter(residuals: &[i32]) -> u32 {
+    if residuals.is_empty() {
+        return 0;
+    }
+    let sum: u64 = residuals
+        .iter()
+        .map(|&r| ((r << 1) ^ (r >> 31)) as u32 as u64)
+        .sum();
+    let mean = sum / residuals.len() as u64;
+    (64 - mean.leading_zeros())
+        .saturating_sub(1)
+        .min(MAX_RICE_PARAMETER)
+}
+
+/// FLAC's UTF-8-style variable-length frame number.
+fn utf8_number(value: u64) -> Vec<u8> {
+    if value < 0x80 {
+        return vec![value as u8];
+    }
+    let mut continuation = Vec::new();
+    let mut rest = value;
+    let mut first_bits = 6;
+    loop {
+        continuation.push(0x80 | (rest & 0x3F) as u8);
+        rest >>= 6;
+        first_bits -= 1;
+        if rest < (1 << first_bits) {
+            break;
+        }
+    }
+    let length = continuation.len() + 1;
+    let prefix = !(0xFF_u8 >> length);
+    let mut bytes = vec![prefix | rest as u8];
+    bytes.extend(continuation.into_iter().rev());
+    bytes
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0_u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0_u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// MSB-first bit packer.
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u64,
+    filled: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            current: 0,
+            filled: 0,
+        }
+    }
+
+    fn put(&mut self, value: u64, bits: u32) {
+        for shift in (0..bits).rev() {
+            self.current = (self.current << 1) | ((value >> shift) & 1);
+            self.filled += 1;
+            if self.filled == 8 {
+                self.bytes.push(self.current as u8);
+                self.current = 0;
+                self.filled = 0;
+            }
+        }
+    }
+
+    /// `value` zeros followed by a one.
+    fn put_unary(&mut self, value: u32) {
+        for _ in 0..value {
+            self.put(0, 1);
+        }
+        self.put(1, 1);
+    }
+
+    fn align(&mut self) {
+        if self.filled > 0 {
+            self.put(0, 8 - self.filled);
+        }
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MSB-first bit reader for decoding what `FlacWriter` produced.
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn get(&mut self, bits: u32) -> u64 {
+            let mut value = 0;
+            for _ in 0..bits {
+                let bit = self.bytes[self.position / 8] >> (7 - self.position % 8) & 1;
+                value = (value << 1) | bit as u64;
+                self.position += 1;
+            }
+            value
+        }
+
+        fn get_unary(&mut self) -> u32 {
+            let mut zeros = 0;
+            while self.get(1) == 0 {
+                zeros += 1;
+            }
+            zeros
+        }
+
+        fn align(&mut self) {
+            self.position = self.position.div_ceil(8) * 8;
+        }
+
+        fn byte_position(&self) -> usize {
+            self.position / 8
+        }
+    }
+
+    /// Decode one frame at the start of `bytes`, checking both CRCs.
+    /// Returns the frame number, the samples and the frame length in bytes.
+    fn decode_frame(bytes: &[u8]) -> (u64, Vec<i16>, usize) {
+        let mut reader = BitReader { bytes, position: 0 };
+        assert_eq!(reader.get(16), 0xFFF8, "frame sync");
+        let block_size_code = reader.get(4);
+        assert_eq!(reader.get(4), 0b0101, "16kHz");
+        assert_eq!(reader.get(4), 0, "mono");
+        assert_eq!(reader.get(3), 0b100, "16-bit");
+        assert_eq!(reader.get(1), 0);
+
+        let first = reader.get(8) as u8;
+        let length = first.leading_ones();
+        let mut frame_number = (first & (0xFF >> (length + 1))) as u64;
+        for _ in 1..length {
+            frame_number = (frame_number << 6) | (reader.get(8) & 0x3F);
+        }
+
+        let block_size = match block_size_code {
+            0b1100 => BLOCK_SIZE,
+            0b0111 => reader.get(16) as usize + 1,
+            code => panic!("unexpected block size code {code:#b}"),
+        };
+        let header_crc = crc8(&bytes[..reader.byte_position()]);
+        assert_eq!(reader.get(8) as u8, header_crc, "header CRC");
+
+        let subframe_type = reader.get(8);
+        let samples = if subframe_type == 0 {
+            vec![reader.get(16) as u16 as i16; block_size]
+        } else {
+            assert_eq!(subframe_type & 0b0111_0001, 0b0001_0000, "FIXED subframe");
+            let order = ((subframe_type >> 1) & 0b111) as usize;
+            let mut samples: Vec<i32> = (0..order)
+                .map(|_| reader.get(16) as u16 as i16 as i32)
+                .collect();
+            assert_eq!(reader.get(2), 0, "4-bit Rice parameters");
+            assert_eq!(reader.get(4), 0, "partition order");
+            let parameter = reader.get(4) as u32;
+            for i in order..block_size {
+                let folded = (reader.get_unary() << parameter) | reader.get(parameter) as u32;
+                let residual = (folded >> 1) as i32 ^ -((folded & 1) as i32);
+                let x = |back: usize| samples[i - back];
+                let prediction = match order {
+                    0 => 0,
+                    1 => x(1),
+                    2 => 2 * x(1) - x(2),
+                    3 => 3 * x(1) - 3 * x(2) + x(3),
+                    _ => 4 * x(1) - 6 * x(2) + 4 * x(3) - x(4),
+                };
+                samples.push(prediction + residual);
+            }
+            samples.into_iter().map(|sample| sample as i16).collect()
+        };
+
+        reader.align();
+        let frame_crc = crc16(&bytes[..reader.byte_position()]);
+        assert_eq!(reader.get(16) as u16, frame_crc, "frame CRC");
+        (frame_number, samples, reader.byte_position())
+    }
+
+    /// Check the header of a finished file and decode all of its frames.
+    fn decode(bytes: &[u8]) -> (u64, Vec<i16>) {
+        assert_eq!(&bytes[..4], b"fLaC");
+        assert_eq!(&bytes[4..8], &[0x80, 0, 0, 34]);
+        let offset = TOTAL_SAMPLES_OFFSET as usize;
+        let total_samples = ((bytes[offset] & 0x0F) as u64) << 32
+            | u32::from_be_bytes(bytes[offset + 1..offset + 5].try_into().unwrap()) as u64;
+
+        let mut samples = Vec::new();
+        let mut position = 8 + 34;
+        let mut expected_frame = 0;
+        while position < bytes.len() {
+            let (frame_number, frame, length) = decode_frame(&bytes[position..]);
+            assert_eq!(frame_number, expected_frame);
+            samples.extend(frame);
+            position += length;
+            expected_frame += 1;
+        }
+        (total_samples, samples)
+    }
+
+    #[test]
+    fn recordings_decode_to_the_written_samples() {
+        let path = std::env::temp_dir().join(format!("flac-{}.flac", std::process::id()));
+        // A tone, a stretch of silence (CONSTANT) and full-scale noise, over
+        // several blocks and ending in a partial one.
+        let mut written: Vec<i16> = (0..10_000)
+            .map(|n| ((n as f32 * 0.05).sin() * 8_000.0) as i16)
+            .collect();
+        written.extend(std::iter::repeat_n(0, BLOCK_SIZE));
+        written.extend((0..3_000_u32).map(|n| (n.wrapping_mul(2_654_435_761) >> 16) as i16));
+
+        let mut writer = FlacWriter::create(&path).unwrap();
+        for chunk in written.chunks(1_000) {
+            writer.write(chunk).unwrap();
+        }
+        assert_eq!(writer.samples(), written.len() as u64);
+        assert_eq!(writer.finish().unwrap(), written.len() as u64);
+
+        let bytes = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let (total_samples, decoded) = decode(&bytes);
+        assert_eq!(total_samples, written.len() as u64);
+        assert_eq!(decoded, written);
+    }
+
+    #[test]
+    fn frame_numbers_use_the_utf8_coding() {
+        assert_eq!(utf8_number(0x7F), [0x7F]);
+        assert_eq!(utf8_number(0x80), [0xC2, 0x80]);
+        assert_eq!(utf8_number(0x800), [0xE0, 0xA0, 0x80]);
+    }
+}
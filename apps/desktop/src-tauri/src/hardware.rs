@@ -0,0 +1,337 @@
+//! What the machine can run: CPU cores, memory and SIMD support decide the
+//! whisper thread count and model size, and a short benchmark per model
+//! checks that the choice keeps up with real time.
+//!
+//! Benchmark results are cached in `<app config>/calibration.json` so each
+//! model is only measured once per thread count and engine.
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use sysinfo::{CpuRefreshKind, System};
+use tauri::{AppHandle, Manager};
+
+const CALIBRATION_FILE: &str = "calibration.json";
+/// whisper.cpp stops getting faster beyond about this many threads.
+const MAX_THREADS: usize = 8;
+/// Highest benchmark real-time factor accepted for a model. Microphone and
+/// system audio share the engine, so each may use half of real time.
+pub const MAX_CALIBRATED_RTF: f64 = 0.5;
+/// Length of the timed benchmark chunk, about one utterance.
+const BENCHMARK_MS: usize = 5_000;
+/// Approximate memory whisper.cpp needs for each model size, in MiB.
+const MODEL_MEMORY_MIB: [(&str, u64); 5] = [
+    ("tiny", 300),
+    ("base", 400),
+    ("small", 900),
+    ("medium", 2_200),
+    ("large", 4_000),
+];
+
+/// CPU and memory as seen when the profile was built.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardwareInfo {
+    pub cpu_brand: String,
+    pub arch: String,
+    pub physical_cores: usize,
+    pub logical_cores: usize,
+    pub total_memory_bytes: u64,
+    pub available_memory_bytes: u64,
+    /// SIMD extensions whisper.cpp uses, e.g. "avx2", "fma", "neon".
+    pub cpu_features: Vec<String>,
+}
+
+impl HardwareInfo {
+    pub fn detect() -> Self {
+        let mut system = System::new();
+        system.refresh_memory();
+        system.refresh_cpu_list(CpuRefreshKind::new());
+        let logical_cores = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or_else(|_| system.cpus().len().max(1));
+
+        Self {
+            cpu_brand: system
+                .cpus()
+                .first()
+                .map(|cpu| cpu.brand().trim().to_string())
+                .unwrap_or_default(),
+            arch: std::env::consts::ARCH.to_string(),
+            physical_cores: system.physical_core_count().unwrap_or(logical_cores),
+            logical_cores,
+            total_memory_bytes: system.total_memory(),
+            available_memory_bytes: system.available_memory(),
+            cpu_features: cpu_features(),
+        }
+    }
+
+    /// Whether the CPU has the vector instructions whisper.cpp needs to run
+    /// anything but the smallest model in real time.
+    fn has_fast_simd(&self) -> bool {
+        self.cpu_features
+            .iter()
+            .any(|feature| feature == "avx2" || feature == "neon")
+    }
+
+    /// Decoding threads: one per physical core, leaving one for capture and
+    /// the UI on larger machines.
+    pub fn recommended_threads(&self) -> usize {
+        let cores = self.physical_cores.max(1);
+        let threads = if cores > 4 { cores - 1 } else { cores };
+        threads.clamp(1, MAX_THREADS)
+    }
+
+    /// Largest model size the CPU can be expected to run in real time and
+    /// that fits in half the available memory. `large` is never recommended
+    /// for live transcription.
+    pub fn recommended_model(&self) -> &'static str {
+        let by_cpu = if !self.has_fast_simd() {
+            "tiny"
+        } else if self.physical_cores < 4 {
+            "base"
+        } else if self.physical_cores < 8 {
+            "small"
+        } else {
+            "medium"
+        };
+
+        let budget_mib = self.available_memory_bytes / 2 / (1024 * 1024);
+        MODEL_MEMORY_MIB
+            .iter()
+            .take_while(|(size, _)| *size != "large")
+            .filter(|(_, mib)| *mib <= budget_mib)
+            .map(|(size, _)| *size)
+            .take_while(|size| model_rank(size) <= model_rank(by_cpu))
+            .last()
+            .unwrap_or("tiny")
+    }
+}
+
+fn model_rank(size: &str) -> usize {
+    MODEL_MEMORY_MIB
+        .iter()
+        .position(|(s, _)| *s == size)
+        .unwrap_or(0)
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cpu_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if std::arch::is_x86_feature_detected!("sse3") {
+        features.push("sse3");
+    }
+    if std::arch::is_x86_feature_detected!("avx") {
+        features.push("avx");
+    }
+    if std::arch::is_x86_feature_detected!("avx2") {
+        features.push("avx2");
+    }
+    if std::arch::is_x86_feature_detected!("fma") {
+        features.push("fma");
+    }
+    if std::arch::is_x86_feature_detected!("f16c") {
+        features.push("f16c");
+    }
+    if std::arch::is_x86_feature_detected!("avx512f") {
+        features.push("avx512f");
+    }
+    features.into_iter().map(str::to_string).collect()
+}
+
+#[cfg(target_arch = "aarch64")]
+fn cpu_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        features.push("neon");
+    }
+    if std::arch::is_aarch64_feature_detected!("fp16") {
+        features.push("fp16");
+    }
+    if std::arch::is_aarch64_feature_detected!("dotprod") {
+        features.push("dotprod");
+    }
+    features.into_iter().map(str::to_string).collect()
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+fn cpu_features() -> Vec<String> {
+    Vec::new()
+}
+
+/// One benchmark of a model on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calibration {
+    pub model_path: String,
+    pub engine: String,
+    pub threads: usize,
+    /// Decode time divided by audio duration for the benchmark chunk.
+    pub realtime_factor: f64,
+    pub measured_at_ms: i64,
+}
+
+impl Calibration {
+    pub fn new(model_path: &str, engine: &str, threads: usize, elapsed: Duration) -> Self {
+        Self {
+            model_path: model_path.to_string(),
+            engine: engine.to_string(),
+            threads,
+            realtime_factor: elapsed.as_secs_f64() / (BENCHMARK_MS as f64 / 1000.0),
+            measured_at_ms: crate::chrono_like_timestamp(),
+        }
+    }
+
+    pub fn keeps_up(&self) -> bool {
+        self.realtime_factor <= MAX_CALIBRATED_RTF
+    }
+}
+
+/// What `get_hardware_profile` reports: the machine, the choices made from
+/// it, and the last session's model and benchmark.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardwareProfile {
+    pub hardware: HardwareInfo,
+    pub threads: usize,
+    pub recommended_model: &'static str,
+    /// Model the last session ran with, after calibration.
+    pub selected_model: Option<String>,
+    pub calibration: Option<Calibration>,
+}
+
+impl HardwareProfile {
+    pub fn detect() -> Self {
+        let hardware = HardwareInfo::detect();
+        Self {
+            threads: hardware.recommended_threads(),
+            recommended_model: hardware.recommended_model(),
+            hardware,
+            selected_model: None,
+            calibration: None,
+        }
+    }
+}
+
+/// Speech-like benchmark audio: a voiced tone with harmonics, pulsed at
+/// syllable rate so the decoder has something to work on.
+pub fn benchmark_audio() -> Vec<i16> {
+    let rate = 16_000.0;
+    (0..BENCHMARK_MS * 16)
+        .map(|n| {
+            let t = n as f64 / rate;
+            let pitch = 140.0 + 20.0 * (2.0 * std::f64::consts::PI * 0.7 * t).sin();
+            let voice: f64 = (1..=4)
+                .map(|h| (2.0 * std::f64::consts::PI * pitch * h as f64 * t).sin() / h as f64)
+                .sum();
+            let envelope = (2.0 * std::f64::consts::PI * 4.0 * t).sin().max(0.0);
+            (voice * envelope * 6_000.0) as i16
+        })
+        .collect()
+}
+
+fn calibration_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(CALIBRATION_FILE))
+        .map_err(|e| e.to_string())
+}
+
+fn load_calibrations(app: &AppHandle) -> Vec<Calibration> {
+    let Ok(path) = calibration_path(app) else {
+        return Vec::new();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+            log::warn!("Ignoring unreadable {}: {}", path.display(), error);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// A previous benchmark of this model, engine and thread count.
+pub fn cached_calibration(
+    app: &AppHandle,
+    model_path: &str,
+    engine: &str,
+    threads: usize,
+) -> Option<Calibration> {
+    load_calibrations(app).into_iter().find(|calibration| {
+        calibration.model_path == model_path
+            && calibration.engine == engine
+            && calibration.threads == threads
+    })
+}
+
+/// Remember a benchmark, replacing any earlier one of the same setup.
+pub fn save_calibration(app: &AppHandle, calibration: &Calibration) -> Result<(), String> {
+    let mut calibrations = load_calibrations(app);
+    calibrations.retain(|c| {
+        !(c.model_path == calibration.model_path
+            && c.engine == calibration.engine
+            && c.threads == calibration.threads)
+    });
+    calibrations.push(calibration.clone());
+
+    let path = calibration_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(&calibrations).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn machine(physical_cores: usize, available_mib: u64, features: &[&str]) -> HardwareInfo {
+        HardwareInfo {
+            cpu_brand: String::new(),
+            arch: String::new(),
+            physical_cores,
+            logical_cores: physical_cores * 2,
+            total_memory_bytes: available_mib * MIB,
+            available_memory_bytes: available_mib * MIB,
+            cpu_features: features.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn threads_follow_physical_cores() {
+        for (cores, threads) in [(0, 1), (1, 1), (2, 2), (4, 4), (6, 5), (9, 8), (16, 8)] {
+            assert_eq!(
+                machine(cores, 16_384, &["avx2"]).recommended_threads(),
+                threads,
+                "{} cores",
+                cores
+            );
+        }
+    }
+
+    #[test]
+    fn model_fits_cpu_and_memory() {
+        for (cores, available_mib, features, model) in [
+            (8, 16_384, &["sse3", "avx"][..], "tiny"),
+            (2, 16_384, &["avx2"][..], "base"),
+            (4, 16_384, &["avx2", "fma"][..], "small"),
+            (8, 16_384, &["neon"][..], "medium"),
+            (32, 65_536, &["avx2"][..], "medium"),
+            (8, 2_048, &["avx2"][..], "small"),
+            (8, 900, &["avx2"][..], "base"),
+            (8, 256, &["avx2"][..], "tiny"),
+        ] {
+            assert_eq!(
+                machine(cores, available_mib, features).recommended_model(),
+                model,
+                "{} cores, {} MiB, {:?}",
+                cores,
+                available_mib,
+                features
+            );
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Append-only journal of a meeting session, so a crash or forced quit loses
+//! neither the finals already emitted nor audio that was never transcribed.
+//!
+//! `<app data>/journal/<meeting session>/` holds `session.json`,
+//! `journal.jsonl` (one ASR_FINAL or progress entry per line) and, when
+//! audio is kept, one s16le 16kHz PCM file per source. All positions are on
+//! the session timeline, which continues across resumes. A session that
+//! stops normally deletes its journal; one still on disk is unfinished.
+
+use crate::audio::SourceRole;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+const JOURNAL_DIR: &str = "journal";
+const SESSION_FILE: &str = "session.json";
+const ENTRIES_FILE: &str = "journal.jsonl";
+const SAMPLE_RATE: i64 = 16_000;
+
+/// `journal` argument of `start_transcription`. Starting a session whose
+/// journal is unfinished resumes it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalOptions {
+    pub meeting_session_id: String,
+    /// Also journal the audio, so untranscribed audio survives a crash.
+    #[serde(default)]
+    pub keep_audio: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionInfo {
+    meeting_session_id: String,
+    started_at_ms: i64,
+    updated_at_ms: i64,
+    resumes: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum Entry {
+    /// An emitted ASR_FINAL, as sent to the webview.
+    Final { event: serde_json::Value },
+    /// Audio of `audioSource` before `untilSample` has been transcribed.
+    #[serde(rename_all = "camelCase")]
+    Progress {
+        audio_source: String,
+        until_sample: i64,
+    },
+}
+
+/// An unfinished session found on disk, for offering to resume it.
+#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+pub struct UnfinishedSession {
+    meetingSessionId: String,
+    startedAtMs: i64,
+    updatedAtMs: i64,
+    finals: usize,
+    /// Journaled audio that was never transcribed.
+    leftoverMs: i64,
+}
+
+/// Audio of one source captured before a crash but never transcribed.
+pub struct Leftover {
+    pub role: SourceRole,
+    pub start_sample: i64,
+    pub samples: Vec<i16>,
+}
+
+/// What an existing journal says about its session.
+#[derive(Default)]
+struct Replay {
+    finals: Vec<serde_json::Value>,
+    next_sequence: u32,
+    end_ms: i64,
+    /// Per source: transcribed until, in samples.
+    progress: [i64; 2],
+    /// Length of the valid prefix of the entries file.
+    valid_len: u64,
+}
+
+pub struct SessionJournal {
+    dir: PathBuf,
+    info: SessionInfo,
+    entries: File,
+    /// PCM per source, indexed by `role_index`, when audio is kept.
+    audio: [Option<File>; 2],
+    /// Session-timeline position of this run's first sample.
+    start_sample: i64,
+    next_sequence: u32,
+    leftover: Vec<Leftover>,
+}
+
+impl SessionJournal {
+    /// Start journaling `options.meeting_session_id`, resuming its journal
+    /// if one was left unfinished.
+    pub fn open(app: &AppHandle, options: &JournalOptions) -> Result<Self, String> {
+        Self::open_in(session_dir(app, &options.meeting_session_id)?, options)
+    }
+
+    fn open_in(dir: PathBuf, options: &JournalOptions) -> Result<Self, String> {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let now = crate::chrono_like_timestamp();
+
+        let info = match read_info(&dir) {
+            Some(info) => SessionInfo {
+                updated_at_ms: now,
+                resumes: info.resumes + 1,
+                ..info
+            },
+            None => SessionInfo {
+                meeting_session_id: options.meeting_session_id.clone(),
+                started_at_ms: now,
+                updated_at_ms: now,
+                resumes: 0,
+            },
+        };
+
+        let replay = replay(&dir);
+        let entries_path = dir.join(ENTRIES_FILE);
+        let mut entries = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&entries_path)
+            .map_err(|e| format!("Failed to open {}: {}", entries_path.display(), e))?;
+        // Drop a line half-written by a crash so new entries start cleanly.
+        // Not opened for appending: Windows refuses to truncate such handles.
+        entries
+            .set_len(replay.valid_len)
+            .and_then(|_| entries.seek(SeekFrom::End(0)))
+            .map_err(|e| e.to_string())?;
+
+        let mut start_sample = replay.end_ms * SAMPLE_RATE / 1000;
+        let mut leftover = Vec::new();
+        let mut audio: [Option<File>; 2] = [None, None];
+        for role in [SourceRole::Microphone, SourceRole::Loopback] {
+            let path = audio_path(&dir, role);
+            let Ok(samples) = read_pcm(&path) else {
+                continue;
+            };
+            start_sample = start_sample.max(samples.len() as i64);
+            let from = replay.progress[role_index(role)].clamp(0, samples.len() as i64);
+            if (from as usize) < samples.len() {
+                leftover.push(Leftover {
+                    role,
+                    start_sample: from,
+                    samples: samples[from as usize..].to_vec(),
+                });
+            }
+        }
+        if options.keep_audio {
+            for role in [SourceRole::Microphone, SourceRole::Loopback] {
+                audio[role_index(role)] = Some(open_pcm(&audio_path(&dir, role), start_sample)?);
+            }
+        }
+
+        let journal = Self {
+            dir,
+            info,
+            entries,
+            audio,
+            start_sample,
+            next_sequence: replay.next_sequence,
+            leftover,
+        };
+        journal.write_info()?;
+        if journal.info.resumes > 0 {
+            log::info!(
+                "Resuming session {} at {}ms after {} finals",
+                journal.info.meeting_session_id,
+                start_sample * 1000 / SAMPLE_RATE,
+                replay.finals.len()
+            );
+        }
+        Ok(journal)
+    }
+
+    /// Session-timeline position of this run's first captured sample.
+    pub fn start_sample(&self) -> i64 {
+        self.start_sample
+    }
+
+    /// First `sequence` this run may use.
+    pub fn next_sequence(&self) -> u32 {
+        self.next_sequence
+    }
+
+    /// Untranscribed audio from before the resume, to transcribe first.
+    pub fn take_leftover(&mut self) -> Vec<Leftover> {
+        std::mem::take(&mut self.leftover)
+    }
+
+    pub fn record_final(&mut self, event: &impl Serialize) -> Result<(), String> {
+        let event = serde_json::to_value(event).map_err(|e| e.to_string())?;
+        self.append(&Entry::Final { event })?;
+        // Finals are what the user cannot get back; make sure they hit disk.
+        self.entries.sync_data().map_err(|e| e.to_string())
+    }
+
+    /// Mark audio of `role` before `until_sample` as transcribed.
+    pub fn record_progress(&mut self, role: SourceRole, until_sample: i64) -> Result<(), String> {
+        self.append(&Entry::Progress {
+            audio_source: crate::source_labels(role).0.to_string(),
+            until_sample,
+        })
+    }
+
+    /// Append `samples` of `role`, then `dropped` samples of silence for
+    /// audio that was lost after them.
+    pub fn record_audio(
+        &mut self,
+        role: SourceRole,
+        samples: &[i16],
+        dropped: u64,
+    ) -> Result<(), String> {
+        let Some(file) = self.audio[role_index(role)].as_mut() else {
+            return Ok(());
+        };
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        file.write_all(&bytes).map_err(|e| e.to_string())?;
+        if dropped > 0 {
+            // Extending the file leaves a hole that reads back as silence.
+            let end = file.stream_position().map_err(|e| e.to_string())? + dropped * 2;
+            file.set_len(end)
+                .and_then(|_| file.seek(SeekFrom::Start(end)))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// The session ended normally; nothing is left to recover.
+    pub fn finish(self) -> Result<(), String> {
+        drop(self.entries);
+        std::fs::remove_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to remove {}: {}", self.dir.display(), e))
+    }
+
+    fn append(&mut self, entry: &Entry) -> Result<(), String> {
+        let mut line = serde_json::to_vec(entry).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        self.entries.write_all(&line).map_err(|e| e.to_string())
+    }
+
+    fn write_info(&self) -> Result<(), String> {
+        let path = self.dir.join(SESSION_FILE);
+        let contents = serde_json::to_string_pretty(&self.info).map_err(|e| e.to_string())?;
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+    }
+}
+
+fn role_index(role: SourceRole) -> usize {
+    match role {
+        SourceRole::Microphone => 0,
+        SourceRole::Loopback => 1,
+    }
+}
+
+fn journal_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(JOURNAL_DIR))
+        .map_err(|e| e.to_string())
+}
+
+fn session_dir(app: &AppHandle, meeting_session_id: &str) -> Result<PathBuf, String> {
+    crate::recording::check_session_id(meeting_session_id)?;
+    Ok(journal_dir(app)?.join(meeting_session_id))
+}
+
+fn audio_path(dir: &Path, role: SourceRole) -> PathBuf {
+    dir.join(format!("{}.pcm", crate::source_labels(role).0))
+}
+
+fn read_info(dir: &Path) -> Option<SessionInfo> {
+    let contents = std::fs::read_to_string(dir.join(SESSION_FILE)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn replay(dir: &Path) -> Replay {
+    let mut replay = Replay::default();
+    let Ok(contents) = std::fs::read(dir.join(ENTRIES_FILE)) else {
+        return replay;
+    };
+
+    let mut offset = 0;
+    for line in contents.split_inclusive(|byte| *byte == b'\n') {
+        // A crash can leave the last line incomplete; it and anything after
+        // it are ignored.
+        let Ok(entry) = serde_json::from_slice::<Entry>(line) else {
+            break;
+        };
+        if !line.ends_with(b"\n") {
+            break;
+        }
+        offset += line.len() as u64;
+        match entry {
+            Entry::Final { event } => {
+                if let Some(sequence) = event.get("sequence").and_then(|v| v.as_u64()) {
+                    replay.next_sequence = replay.next_sequence.max(sequence as u32 + 1);
+                }
+                if let Some(end) = event.get("tEndMs").and_then(|v| v.as_i64()) {
+                    replay.end_ms = replay.end_ms.max(end);
+                }
+                replay.finals.push(event);
+            }
+            Entry::Progress {
+                audio_source,
+                until_sample,
+            } => {
+                for role in [SourceRole::Microphone, SourceRole::Loopback] {
+                    if crate::source_labels(role).0 == audio_source {
+                        let progress = &mut replay.progress[role_index(role)];
+                        *progress = (*progress).max(until_sample);
+                    }
+                }
+            }
+        }
+    }
+    replay.valid_len = offset;
+    replay
+}
+
+fn read_pcm(path: &Path) -> Result<Vec<i16>, String> {
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| e.to_string())?;
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+/// Open a source's PCM for appending at `start_sample`, padding with silence
+/// (or dropping a torn trailing byte) so file offset and timeline agree.
+fn open_pcm(path: &Path, start_sample: i64) -> Result<File, String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    file.set_len(start_sample as u64 * 2)
+        .and_then(|_| file.seek(SeekFrom::End(0)))
+        .map_err(|e| e.to_string())?;
+    Ok(file)
+}
+
+/// Sessions whose journal was never finished, most recent first.
+pub fn unfinished_sessions(app: &AppHandle) -> Vec<UnfinishedSession> {
+    let Ok(root) = journal_dir(app) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(&root) else {
+        return Vec::new();
+    };
+
+    let mut sessions: Vec<UnfinishedSession> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|dir| {
+            let info = read_info(&dir)?;
+            let replay = replay(&dir);
+            let leftover_samples: i64 = [SourceRole::Microphone, SourceRole::Loopback]
+                .into_iter()
+                .map(|role| {
+                    let len = std::fs::metadata(audio_path(&dir, role))
+                        .map(|m| m.len() as i64 / 2)
+                        .unwrap_or(0);
+                    (len - replay.progress[role_index(role)]).max(0)
+                })
+                .max()
+                .unwrap_or(0);
+            Some(UnfinishedSession {
+                meetingSessionId: info.meeting_session_id,
+                startedAtMs: info.started_at_ms,
+                updatedAtMs: info.updated_at_ms,
+                finals: replay.finals.len(),
+                leftoverMs: leftover_samples * 1000 / SAMPLE_RATE,
+            })
+        })
+        .collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.updatedAtMs));
+    sessions
+}
+
+/// ASR_FINAL events journaled for a session, to rebuild its transcript.
+pub fn journaled_finals(
+    app: &AppHandle,
+    meeting_session_id: &str,
+) -> Result<Vec<serde_json::Value>, String> {
+    Ok(replay(&session_dir(app, meeting_session_id)?).finals)
+}
+
+/// Forget an unfinished session instead of resuming it.
+pub fn discard(app: &AppHandle, meeting_session_id: &str) -> Result<(), String> {
+    let dir = session_dir(app, meeting_session_id)?;
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(format!("Failed to remove {}: {}", dir.display(), error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(keep_audio: bool) -> JournalOptions {
+        JournalOptions {
+            meeting_session_id: "meeting".to_string(),
+            keep_audio,
+        }
+    }
+
+    fn write_pcm(path: &Path, samples: usize) {
+        let bytes: Vec<u8> = (0..samples)
+            .flat_map(|n| (n as i16).to_le_bytes())
+            .collect();
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn resume_ignores_a_torn_last_line() {
+        let dir = temp_dir("torn");
+        let valid = concat!(
+            r#"{"kind":"final","event":{"sequence":0,"tStartMs":0,"tEndMs":1000}}"#,
+            "\n",
+            r#"{"kind":"final","event":{"sequence":4,"tStartMs":1000,"tEndMs":2000}}"#,
+            "\n",
+            r#"{"kind":"progress","audioSource":"microphone","untilSample":16000}"#,
+            "\n",
+        );
+        let torn = r#"{"kind":"final","event":{"sequ"#;
+        std::fs::write(dir.join(ENTRIES_FILE), format!("{valid}{torn}")).unwrap();
+        write_pcm(&audio_path(&dir, SourceRole::Microphone), 48_000);
+
+        let mut journal = SessionJournal::open_in(dir.clone(), &options(true)).unwrap();
+        assert_eq!(journal.start_sample(), 48_000);
+        assert_eq!(journal.next_sequence(), 5);
+
+        let leftover = journal.take_leftover();
+        assert_eq!(leftover.len(), 1);
+        assert_eq!(leftover[0].role, SourceRole::Microphone);
+        assert_eq!(leftover[0].start_sample, 16_000);
+        assert_eq!(leftover[0].samples.len(), 32_000);
+        assert_eq!(leftover[0].samples[0], 16_000);
+        assert!(journal.take_leftover().is_empty());
+
+        // New entries follow the valid prefix, with the torn line gone.
+        journal
+            .record_progress(SourceRole::Loopback, 8_000)
+            .unwrap();
+        let contents = std::fs::read_to_string(dir.join(ENTRIES_FILE)).unwrap();
+        assert_eq!(
+            contents,
+            format!(
+                "{valid}{}\n",
+                r#"{"kind":"progress","audioSource":"systemAudio","untilSample":8000}"#
+            )
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resume_continues_after_the_last_final() {
+        let dir = temp_dir("finals");
+        std::fs::write(
+            dir.join(ENTRIES_FILE),
+            concat!(
+                r#"{"kind":"final","event":{"sequence":2,"tStartMs":0,"tEndMs":3000}}"#,
+                "\n"
+            ),
+        )
+        .unwrap();
+
+        let mut journal = SessionJournal::open_in(dir.clone(), &options(false)).unwrap();
+        assert_eq!(journal.start_sample(), 3 * SAMPLE_RATE);
+        assert_eq!(journal.next_sequence(), 3);
+        assert!(journal.take_leftover().is_empty());
+        assert_eq!(read_info(&dir).unwrap().resumes, 0);
+        drop(journal);
+
+        let journal = SessionJournal::open_in(dir.clone(), &options(false)).unwrap();
+        assert_eq!(read_info(&dir).unwrap().resumes, 1);
+        journal.finish().unwrap();
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn lost_audio_is_journaled_as_silence() {
+        let dir = temp_dir("dropped");
+        let mut journal = SessionJournal::open_in(dir.clone(), &options(true)).unwrap();
+        journal
+            .record_audio(SourceRole::Microphone, &[7; 100], 50)
+            .unwrap();
+        journal
+            .record_audio(SourceRole::Microphone, &[9; 10], 0)
+            .unwrap();
+        journal.record_audio(SourceRole::Loopback, &[], 30).unwrap();
+        drop(journal);
+
+        let microphone = read_pcm(&audio_path(&dir, SourceRole::Microphone)).unwrap();
+        let mut expected = vec![7; 100];
+        expected.extend([0; 50]);
+        expected.extend([9; 10]);
+        assert_eq!(microphone, expected);
+        let loopback = read_pcm(&audio_path(&dir, SourceRole::Loopback)).unwrap();
+        assert_eq!(loopback, [0; 30]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
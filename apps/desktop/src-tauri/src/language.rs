@@ -0,0 +1,358 @@
+//! Spoken language of a session transcribed with `language = "auto"`.
+//!
+//! whisper detects the language of every chunk on its own, so a bilingual
+//! meeting can swing between languages. A `LanguagePolicy` can lock the
+//! session to a language once it has been detected confidently enough times
+//! in a row, or limit it to a set of languages; chunks detected as anything
+//! else are decoded again in an allowed one.
+
+use serde::{Deserialize, Serialize};
+
+/// Language setting that lets whisper detect the language.
+pub const AUTO: &str = "auto";
+/// Consecutive confident detections needed to lock by default.
+const DEFAULT_LOCK_SEGMENTS: u32 = 3;
+
+/// `languagePolicy` in `start_transcription`'s capture options. Only applies
+/// when the language is "auto".
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LanguagePolicy {
+    /// Lock the session to a language once it is detected with at least this
+    /// probability (0..1) in `lock_segments` decodes in a row. Decodes
+    /// without a probability never count towards locking.
+    pub lock_probability: Option<f64>,
+    pub lock_segments: Option<u32>,
+    /// Language codes ("en", "de", ...) the session may use; empty allows all.
+    pub allowed: Vec<String>,
+}
+
+/// Attached to the ASR_STATUS emitted when the session's language changes or
+/// locks.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageReport {
+    pub language: String,
+    pub previous_language: Option<String>,
+    pub probability: Option<f64>,
+    /// Whether the session is now fixed to `language`.
+    pub locked: bool,
+}
+
+/// Follows the detected language of a session and applies its policy.
+pub struct LanguageTracker {
+    policy: LanguagePolicy,
+    detecting: bool,
+    current: Option<String>,
+    locked: bool,
+    /// Consecutive confident detections of `current`.
+    streak: u32,
+    report: Option<LanguageReport>,
+}
+
+impl LanguageTracker {
+    pub fn new(configured: &str, mut policy: LanguagePolicy) -> Self {
+        for language in &mut policy.allowed {
+            *language = language.to_lowercase();
+        }
+        Self {
+            policy,
+            detecting: configured == AUTO,
+            current: None,
+            locked: false,
+            streak: 0,
+            report: None,
+        }
+    }
+
+    /// Whether the session's language is detected rather than configured.
+    pub fn is_detecting(&self) -> bool {
+        self.detecting
+    }
+
+    /// Language the engine should decode in: the locked language, or "auto".
+    pub fn forced(&self) -> &str {
+        match &self.current {
+            Some(language) if self.locked => language,
+            _ => AUTO,
+        }
+    }
+
+    fn is_allowed(&self, language: &str) -> bool {
+        self.policy.allowed.is_empty() || self.policy.allowed.iter().any(|l| l == language)
+    }
+
+    /// Language to decode a chunk again in when it was detected as one the
+    /// policy does not allow: the current language if allowed, else the
+    /// first allowed one.
+    pub fn redirect(&self, detected: Option<&str>) -> Option<String> {
+        let detected = detected?;
+        if !self.detecting || self.locked || self.is_allowed(detected) {
+            return None;
+        }
+        self.current
+            .as_deref()
+            .filter(|current| self.is_allowed(current))
+            .or(self.policy.allowed.first().map(String::as_str))
+            .map(str::to_string)
+    }
+
+    /// Record the language of one decode. Returns the language to fix the
+    /// engine to when this detection locked the session.
+    pub fn observe(&mut self, language: Option<&str>, probability: Option<f64>) -> Option<String> {
+        let language = language?;
+        if !self.detecting || self.locked {
+            return None;
+        }
+
+        if self.current.as_deref() != Some(language) {
+            self.report = Some(LanguageReport {
+                language: language.to_string(),
+                previous_language: self.current.replace(language.to_string()),
+                probability,
+                locked: false,
+            });
+            self.streak = 0;
+        }
+
+        let min_probability = self.policy.lock_probability?;
+        if probability.is_some_and(|p| p >= min_probability) {
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+        if self.streak < self.policy.lock_segments.unwrap_or(DEFAULT_LOCK_SEGMENTS) {
+            return None;
+        }
+
+        self.locked = true;
+        let report = self.report.get_or_insert_with(|| LanguageReport {
+            language: language.to_string(),
+            previous_language: Some(language.to_string()),
+            probability,
+            locked: false,
+        });
+        report.probability = probability;
+        report.locked = true;
+        Some(language.to_string())
+    }
+
+    /// The change since the last call, if any.
+    pub fn take_report(&mut self) -> Option<LanguageReport> {
+        self.report.take()
+    }
+}
+
+/// whisper.cpp language names, as whisper-server reports them, and codes.
+const LANGUAGES: [(&str, &str); 100] = [
+    ("en", "english"),
+    ("zh", "chinese"),
+    ("de", "german"),
+    ("es", "spanish"),
+    ("ru", "russian"),
+    ("ko", "korean"),
+    ("fr", "french"),
+    ("ja", "japanese"),
+    ("pt", "portuguese"),
+    ("tr", "turkish"),
+    ("pl", "polish"),
+    ("ca", "catalan"),
+    ("nl", "dutch"),
+    ("ar", "arabic"),
+    ("sv", "swedish"),
+    ("it", "italian"),
+    ("id", "indonesian"),
+    ("hi", "hindi"),
+    ("fi", "finnish"),
+    ("vi", "vietnamese"),
+    ("he", "hebrew"),
+    ("uk", "ukrainian"),
+    ("el", "greek"),
+    ("ms", "malay"),
+    ("cs", "czech"),
+    ("ro", "romanian"),
+    ("da", "danish"),
+    ("hu", "hungarian"),
+    ("ta", "tamil"),
+    ("no", "norwegian"),
+    ("th", "thai"),
+    ("ur", "urdu"),
+    ("hr", "croatian"),
+    ("bg", "bulgarian"),
+    ("lt", "lithuanian"),
+    ("la", "latin"),
+    ("mi", "maori"),
+    ("ml", "malayalam"),
+    ("cy", "welsh"),
+    ("sk", "slovak"),
+    ("te", "telugu"),
+    ("fa", "persian"),
+    ("lv", "latvian"),
+    ("bn", "bengali"),
+    ("sr", "serbian"),
+    ("az", "azerbaijani"),
+    ("sl", "slovenian"),
+    ("kn", "kannada"),
+    ("et", "estonian"),
+    ("mk", "macedonian"),
+    ("br", "breton"),
+    ("eu", "basque"),
+    ("is", "icelandic"),
+    ("hy", "armenian"),
+    ("ne", "nepali"),
+    ("mn", "mongolian"),
+    ("bs", "bosnian"),
+    ("kk", "kazakh"),
+    ("sq", "albanian"),
+    ("sw", "swahili"),
+    ("gl", "galician"),
+    ("mr", "marathi"),
+    ("pa", "punjabi"),
+    ("si", "sinhala"),
+    ("km", "khmer"),
+    ("sn", "shona"),
+    ("yo", "yoruba"),
+    ("so", "somali"),
+    ("af", "afrikaans"),
+    ("oc", "occitan"),
+    ("ka", "georgian"),
+    ("be", "belarusian"),
+    ("tg", "tajik"),
+    ("sd", "sindhi"),
+    ("gu", "gujarati"),
+    ("am", "amharic"),
+    ("yi", "yiddish"),
+    ("lo", "lao"),
+    ("uz", "uzbek"),
+    ("fo", "faroese"),
+    ("ht", "haitian creole"),
+    ("ps", "pashto"),
+    ("tk", "turkmen"),
+    ("nn", "nynorsk"),
+    ("mt", "maltese"),
+    ("sa", "sanskrit"),
+    ("lb", "luxembourgish"),
+    ("my", "myanmar"),
+    ("bo", "tibetan"),
+    ("tl", "tagalog"),
+    ("mg", "malagasy"),
+    ("as", "assamese"),
+    ("tt", "tatar"),
+    ("haw", "hawaiian"),
+    ("ln", "lingala"),
+    ("ha", "hausa"),
+    ("ba", "bashkir"),
+    ("jw", "javanese"),
+    ("su", "sundanese"),
+    ("yue", "cantonese"),
+];
+
+/// Language code for a whisper language name or code ("german" or "de").
+pub fn to_code(language: &str) -> Option<String> {
+    let language = language.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, name)| *code == language || *name == language)
+        .map(|(code, _)| code.to_string())
+}
+
+/// Display name for a language code, e.g. "German" for "de".
+pub fn display_name(code: &str) -> Option<String> {
+    let (_, name) = LANGUAGES.iter().find(|(c, _)| *c == code)?;
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    Some(first.to_uppercase().chain(chars).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(lock_probability: Option<f64>, allowed: &[&str]) -> LanguagePolicy {
+        LanguagePolicy {
+            lock_probability,
+            lock_segments: Some(2),
+            allowed: allowed.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn language_changes_are_reported_without_locking() {
+        let mut tracker = LanguageTracker::new(AUTO, LanguagePolicy::default());
+        assert_eq!(tracker.observe(Some("en"), Some(0.99)), None);
+        let report = tracker.take_report().unwrap();
+        assert_eq!(report.language, "en");
+        assert_eq!(report.previous_language, None);
+        assert!(!report.locked);
+
+        for _ in 0..5 {
+            assert_eq!(tracker.observe(Some("en"), Some(0.99)), None);
+        }
+        assert!(tracker.take_report().is_none());
+
+        tracker.observe(Some("de"), Some(0.7));
+        let report = tracker.take_report().unwrap();
+        assert_eq!(report.previous_language.as_deref(), Some("en"));
+        assert_eq!(tracker.forced(), AUTO);
+    }
+
+    #[test]
+    fn confident_detections_in_a_row_lock() {
+        let mut tracker = LanguageTracker::new(AUTO, policy(Some(0.8), &[]));
+        assert_eq!(tracker.observe(Some("de"), Some(0.9)), None);
+        // An unsure decode starts the count again.
+        assert_eq!(tracker.observe(Some("de"), Some(0.5)), None);
+        assert_eq!(tracker.observe(Some("de"), None), None);
+        assert_eq!(tracker.observe(Some("de"), Some(0.9)), None);
+        assert_eq!(
+            tracker.observe(Some("de"), Some(0.85)).as_deref(),
+            Some("de")
+        );
+        assert_eq!(tracker.forced(), "de");
+
+        let report = tracker.take_report().unwrap();
+        assert_eq!(report.language, "de");
+        assert_eq!(report.probability, Some(0.85));
+        assert!(report.locked);
+
+        // Locked for the rest of the session.
+        assert_eq!(tracker.observe(Some("en"), Some(0.99)), None);
+        assert!(tracker.take_report().is_none());
+        assert_eq!(tracker.forced(), "de");
+        assert_eq!(tracker.redirect(Some("fr")), None);
+    }
+
+    #[test]
+    fn a_configured_language_is_left_alone() {
+        let mut tracker = LanguageTracker::new("de", policy(Some(0.5), &["en"]));
+        assert!(!tracker.is_detecting());
+        for _ in 0..3 {
+            assert_eq!(tracker.observe(Some("en"), Some(0.99)), None);
+        }
+        assert!(tracker.take_report().is_none());
+        assert_eq!(tracker.redirect(Some("fr")), None);
+    }
+
+    #[test]
+    fn disallowed_languages_are_redirected() {
+        let mut tracker = LanguageTracker::new(AUTO, policy(None, &["EN", "de"]));
+        assert_eq!(tracker.redirect(None), None);
+        assert_eq!(tracker.redirect(Some("en")), None);
+        assert_eq!(tracker.redirect(Some("fr")).as_deref(), Some("en"));
+
+        tracker.observe(Some("de"), Some(0.9));
+        assert_eq!(tracker.redirect(Some("fr")).as_deref(), Some("de"));
+
+        // A disallowed current language falls back to the first allowed.
+        tracker.observe(Some("nl"), Some(0.9));
+        assert_eq!(tracker.redirect(Some("fr")).as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn names_map_to_codes() {
+        assert_eq!(to_code(" German ").as_deref(), Some("de"));
+        assert_eq!(to_code("yue").as_deref(), Some("yue"));
+        assert_eq!(to_code("klingon"), None);
+        assert_eq!(display_name("ht").as_deref(), Some("Haitian creole"));
+    }
+}
@@ -1,21 +1,55 @@
+mod asr;
 mod audio;
+mod dedup;
+mod devices;
+mod error;
+mod flac;
+mod hardware;
+mod journal;
+mod language;
+mod models;
+#[cfg(target_os = "linux")]
+mod monitor;
+mod pacing;
+mod recording;
+mod session;
+mod stitching;
+mod streaming;
+mod translation;
 mod whisper;
 
-use audio::AudioCapture;
-use serde::Serialize;
+use asr::{AsrConfig, AsrEngine, AsrEngineKind, AsrSegment, AsrWord};
+use audio::{
+    AudioCapture, AudioDrain, CaptureNotice, CaptureProcessing, EchoMetrics, ProcessingConfig,
+    SourceProcessor, SourceRole, Utterance, VadConfig, VoiceActivitySegmenter,
+};
+use dedup::{DuplicateSuppressor, StagedFinal};
+use error::AppError;
+use journal::{JournalOptions, SessionJournal};
+use language::{LanguagePolicy, LanguageReport, LanguageTracker};
+use pacing::{PacingController, PacingStage};
+use recording::{RecordingOptions, SessionRecorder};
+use serde::{Deserialize, Serialize};
+use session::{SessionCommand, SessionController, SessionError, SessionInbox, SessionPhase};
 use std::collections::{HashMap, HashSet};
 use std::process::Command;
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use stitching::TranscriptStitcher;
+use streaming::StreamingWindow;
 use tauri::{Emitter, Manager, PhysicalPosition, State};
-use whisper::WhisperManager;
+use translation::{Translation, TranslationOptions};
 
 /// Global state for the audio/transcription pipeline.
 struct TranscriptionState {
     audio: Mutex<AudioCapture>,
-    whisper: Mutex<Option<WhisperManager>>,
-    is_recording: Mutex<bool>,
-    is_paused: Mutex<bool>,
+    session: Mutex<SessionController>,
+    /// Latest echo canceller metrics; `None` while echo cancellation is off.
+    echo_metrics: Mutex<Option<EchoMetrics>>,
+    /// Journal of the running session, if it asked for one.
+    journal: Mutex<Option<SessionJournal>>,
+    /// Hardware profile and calibration of the last session.
+    profile: Mutex<Option<hardware::HardwareProfile>>,
 }
 
 /// Tracks meeting providers currently detected so we do not spam notifications.
@@ -32,9 +66,26 @@ struct MeetingDetectorState {
 #[allow(non_snake_case, dead_code)]
 enum ASREvent {
     #[serde(rename = "ASR_STATUS")]
-    Status { state: String, message: String },
+    Status {
+        state: String,
+        message: String,
+        /// Present on warnings about transcription falling behind.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        lag: Option<LagReport>,
+        /// Present when an auto-detected session changes or locks its language.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        language: Option<LanguageReport>,
+    },
     #[serde(rename = "ASR_PARTIAL")]
-    Partial { text: String, tStartMs: i64 },
+    Partial {
+        text: String,
+        tStartMs: i64,
+        tEndMs: i64,
+        speakerRole: Option<String>,
+        audioSource: Option<String>,
+        /// Sequence the eventual ASR_FINAL for this utterance will carry.
+        sequence: u32,
+    },
     #[serde(rename = "ASR_FINAL")]
     Final {
         text: String,
@@ -47,9 +98,66 @@ enum ASREvent {
         prosodyPauseRatio: Option<f64>,
         prosodyVoicedMs: Option<f64>,
         prosodySnrDb: Option<f64>,
+        /// SNR of the same audio before noise suppression, filtering and
+        /// gain; only present when that processing is on.
+        prosodyRawSnrDb: Option<f64>,
+        /// Mean probability of `words`; `None` when the engine reports none.
         confidence: Option<f64>,
+        language: Option<String>,
+        /// How sure whisper was of a detected `language`, 0..1.
+        languageProbability: Option<f64>,
+        /// `text` in the session's translation target, when its source is
+        /// translated.
+        translation: Option<String>,
         sequence: u32,
+        /// Timed words with their probabilities, on the `tStartMs` clock.
+        words: Vec<AsrWord>,
     },
+    /// Something failed that the user should know about, with the action
+    /// most likely to fix it.
+    #[serde(rename = "ASR_ERROR")]
+    Error {
+        #[serde(flatten)]
+        error: AppError,
+        /// Source whose audio could not be transcribed, if any.
+        audioSource: Option<String>,
+    },
+    /// The session moved to another phase.
+    #[serde(rename = "ASR_SESSION")]
+    Session {
+        phase: SessionPhase,
+        previousPhase: SessionPhase,
+    },
+}
+
+/// How far transcription is behind real time and what was done about it.
+#[derive(Debug, Serialize, Clone)]
+#[allow(non_snake_case)]
+struct LagReport {
+    /// Smoothed decode time divided by audio duration.
+    realtimeFactor: f64,
+    /// Audio waiting for the ASR.
+    queuedMs: i64,
+    /// Audio discarded to keep the queue bounded.
+    droppedMs: i64,
+    /// Current adaptation, e.g. "normal", "reducedWindows", "smallerModel".
+    stage: String,
+}
+
+/// `capture` argument of `start_transcription`: what happens to captured
+/// audio besides transcribing it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CaptureOptions {
+    processing: ProcessingConfig,
+    /// Keep the audio on disk; off unless given.
+    recording: Option<RecordingOptions>,
+    /// Journal the session so it can be resumed after a crash.
+    journal: Option<JournalOptions>,
+    /// Locking or limiting the language when it is "auto".
+    language_policy: Option<LanguagePolicy>,
+    /// Translate finals of some or all sources; off unless given.
+    translation: Option<TranslationOptions>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -375,6 +483,17 @@ struct ProsodySnapshot {
     pause_ratio: f64,
     voiced_ms: f64,
     snr_db: f64,
+    /// `snr_db` of the unprocessed audio, when the source is processed.
+    raw_snr_db: Option<f64>,
+}
+
+/// What a final carries while it waits out the duplicate hold.
+#[derive(Clone, Copy)]
+struct FinalContext {
+    prosody: ProsodySnapshot,
+    /// Sequence the streaming window gave it; batch finals are numbered
+    /// when they are emitted.
+    sequence: Option<u32>,
 }
 
 fn clamp_f64(value: f64, min: f64, max: f64) -> f64 {
@@ -388,6 +507,7 @@ fn compute_prosody(samples: &[i16]) -> ProsodySnapshot {
             pause_ratio: 1.0,
             voiced_ms: 0.0,
             snr_db: 0.0,
+            raw_snr_db: None,
         };
     }
 
@@ -395,7 +515,7 @@ fn compute_prosody(samples: &[i16]) -> ProsodySnapshot {
     let mut voiced_count: usize = 0;
     let mut noise_sum_sq = 0.0_f64;
     let mut noise_count: usize = 0;
-    let voiced_threshold = 0.02_f64;
+    let voiced_threshold = audio::VOICED_THRESHOLD as f64;
 
     for sample in samples {
         let value = (*sample as f64) / 32768.0;
@@ -428,96 +548,970 @@ fn compute_prosody(samples: &[i16]) -> ProsodySnapshot {
         pause_ratio,
         voiced_ms,
         snr_db: clamp_f64(snr_db, -5.0, 45.0),
+        raw_snr_db: None,
     }
 }
 
-async fn transcribe_source_chunk(
+/// How often the batch loop feeds captured audio to the VAD.
+const VAD_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often streaming mode re-decodes the sliding window.
+const STREAMING_TICK_INTERVAL: Duration = Duration::from_millis(500);
+/// How often capture streams are checked for failures and device changes.
+const CAPTURE_HEALTH_INTERVAL: Duration = Duration::from_secs(2);
+/// The same transcription error is reported at most this often per source.
+const REPEATED_ERROR_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Check the capture streams, enumerating devices and reopening streams on
+/// a blocking thread so neither the capture lock nor the runtime waits on
+/// them.
+async fn check_capture_health(app: &tauri::AppHandle) -> Vec<CaptureNotice> {
+    let check = {
+        let state = app.state::<TranscriptionState>();
+        let Ok(mut audio) = state.audio.lock() else {
+            return Vec::new();
+        };
+        audio.begin_health_check()
+    };
+    let update = match tauri::async_runtime::spawn_blocking(move || check.run()).await {
+        Ok(update) => update,
+        Err(error) => {
+            log::error!("Capture health check failed: {}", error);
+            return Vec::new();
+        }
+    };
+    let state = app.state::<TranscriptionState>();
+    let Ok(mut audio) = state.audio.lock() else {
+        return Vec::new();
+    };
+    audio.finish_health_check(update)
+}
+
+/// How often recordings past their retention are looked for.
+const RECORDING_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete expired recordings now and then hourly, so retention holds while
+/// the app stays open through long sessions.
+fn start_recording_retention(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let handle = app.clone();
+            let sweep =
+                tauri::async_runtime::spawn_blocking(move || recording::remove_expired(&handle));
+            if let Err(error) = sweep.await {
+                log::error!("Removing expired recordings failed: {}", error);
+            }
+            tokio::time::sleep(RECORDING_SWEEP_INTERVAL).await;
+        }
+    });
+}
+
+/// Tell the frontend when a capture stream drops out or comes back.
+fn report_capture_notices(app: &tauri::AppHandle, notices: Vec<CaptureNotice>) {
+    for notice in notices {
+        let (state, message) = match notice {
+            CaptureNotice::Interrupted { role, reason } => (
+                "warning",
+                format!(
+                    "{} capture interrupted: {}. Reconnecting...",
+                    role.label(),
+                    reason
+                ),
+            ),
+            CaptureNotice::Recovered {
+                role,
+                device,
+                gap_ms,
+            } if gap_ms > 0 => (
+                "listening",
+                format!(
+                    "{} capture resumed on {} after a {:.1}s gap",
+                    role.label(),
+                    device,
+                    gap_ms as f64 / 1000.0
+                ),
+            ),
+            CaptureNotice::Recovered { role, device, .. } => (
+                "listening",
+                format!("{} capture switched to {}", role.label(), device),
+            ),
+        };
+        let _ = app.emit(
+            "asr-event",
+            ASREvent::Status {
+                state: state.to_string(),
+                message,
+                lag: None,
+                language: None,
+            },
+        );
+    }
+}
+
+/// Surface audio the capture rings had to drop since the last drain.
+fn report_capture_overflow(app: &tauri::AppHandle, drained: &AudioDrain) {
+    for (source, dropped) in [
+        ("microphone", drained.microphone_dropped),
+        ("system audio", drained.system_dropped),
+    ] {
+        if dropped == 0 {
+            continue;
+        }
+        let dropped_ms = dropped * 1000 / 16000;
+        log::warn!(
+            "Capture buffer overflow: dropped {}ms of {} audio",
+            dropped_ms,
+            source
+        );
+        let _ = app.emit(
+            "asr-event",
+            ASREvent::Status {
+                state: "warning".to_string(),
+                message: format!(
+                    "Transcription fell behind; dropped {}ms of {} audio",
+                    dropped_ms, source
+                ),
+                lag: None,
+                language: None,
+            },
+        );
+    }
+}
+
+/// The transcription loop's engine, how fast it is keeping up, and what is
+/// needed to recreate it.
+struct EngineSlot {
+    engine: Box<dyn AsrEngine>,
+    kind: AsrEngineKind,
+    config: AsrConfig,
+    pacing: PacingController,
+    language: LanguageTracker,
+    translation: Option<Translation>,
+}
+
+impl EngineSlot {
+    /// Transcribe and record the decode time against the audio duration.
+    /// Applies the session's language policy to what whisper detected.
+    async fn transcribe(&mut self, samples: &[i16]) -> Result<Vec<AsrSegment>, AppError> {
+        let started = Instant::now();
+        let mut result = self.engine.transcribe(samples).await;
+        let detected = result
+            .as_ref()
+            .ok()
+            .and_then(|segments| segments.first())
+            .and_then(|segment| segment.language.clone());
+        if let Some(language) = self.language.redirect(detected.as_deref()) {
+            log::info!(
+                "Decoding chunk detected as {:?} again in {}",
+                detected,
+                language
+            );
+            self.engine.set_language(&language);
+            result = self.engine.transcribe(samples).await;
+            self.engine.set_language(self.language.forced());
+        }
+        self.pacing.record(samples.len(), started.elapsed());
+
+        if let Some(segment) = result.as_ref().ok().and_then(|segments| segments.first()) {
+            let locked = self
+                .language
+                .observe(segment.language.as_deref(), segment.language_probability);
+            if let Some(language) = locked {
+                log::info!("Locking session language to {}", language);
+                self.engine.set_language(&language);
+            }
+        }
+        result
+    }
+
+    /// Add translations to `segments` of `role`'s audio when the session
+    /// translates that source. The decode counts towards pacing like any
+    /// other; a failed translation leaves the finals untranslated.
+    async fn translate(
+        &mut self,
+        role: SourceRole,
+        samples: &[i16],
+        segments: &mut [AsrSegment],
+        offset_ms: i64,
+    ) {
+        let (audio_source, _) = source_labels(role);
+        let Some(translation) = self.translation.as_mut() else {
+            return;
+        };
+        if segments.is_empty() || !translation.applies_to(audio_source) {
+            return;
+        }
+
+        let started = Instant::now();
+        let result = translation
+            .translate(self.engine.as_mut(), samples, segments, offset_ms)
+            .await;
+        self.pacing.record(samples.len(), started.elapsed());
+        if let Err(error) = result {
+            log::warn!(
+                "Translation ({} translator) of {} failed: {}",
+                translation.translator_name(),
+                audio_source,
+                error
+            );
+        }
+    }
+}
+
+/// Drop the newest part of a streaming source's new audio when the backlog
+/// exceeds `MAX_QUEUED_AUDIO_MS`, as the capture ring does when it fills,
+/// and tell the window about everything lost after the kept audio,
+/// including `ring_dropped`. Returns the milliseconds dropped here.
+fn cap_streaming_backlog(
+    samples: &mut Vec<i16>,
+    ring_dropped: u64,
+    window: &mut StreamingWindow,
+) -> i64 {
+    let max_samples = pacing::ms_to_samples(pacing::MAX_QUEUED_AUDIO_MS);
+    let keep = max_samples
+        .saturating_sub(window.window().len())
+        .min(samples.len());
+    let dropped = samples.len() - keep;
+    samples.truncate(keep);
+    let lost = dropped + ring_dropped as usize;
+    if lost > 0 {
+        window.drop_after(lost);
+    }
+    pacing::samples_to_ms(dropped)
+}
+
+/// Re-evaluate the real-time factor after a tick, adapt the pipeline when it
+/// falls behind or catches up, and tell the frontend.
+async fn adapt_to_lag(
     app: &tauri::AppHandle,
-    model_path: &str,
-    language: &str,
-    audio_source: &str,
-    speaker_role: &str,
-    samples: &[i16],
-    total_samples: &mut i64,
-    sequence: &mut u32,
+    queued_ms: i64,
+    dropped_ms: i64,
+    slot: &mut EngineSlot,
+    vads: [&mut VoiceActivitySegmenter; 2],
+    windows: [&mut StreamingWindow; 2],
 ) {
-    if samples.is_empty() {
-        return;
+    let pacing = &mut slot.pacing;
+    let stage_change = pacing.evaluate(queued_ms);
+    let lag_change = pacing.update_lagging(queued_ms);
+
+    if let Some(stage) = stage_change {
+        if stage == PacingStage::SmallerModel && !switch_to_smaller_model(app, slot).await {
+            slot.pacing.smaller_model_unavailable();
+        }
+
+        let stage = slot.pacing.stage();
+        for vad in vads {
+            vad.set_config(stage.vad_config());
+        }
+        for window in windows {
+            window.set_max_window_ms(
+                (stage != PacingStage::Normal).then_some(pacing::REDUCED_WINDOW_MS),
+            );
+        }
+        log::info!(
+            "Transcription pacing now {} (real-time factor {:.2})",
+            stage.as_str(),
+            slot.pacing.realtime_factor()
+        );
     }
 
-    let sample_count = samples.len() as i64;
-    let t_start_ms = (*total_samples * 1000) / 16000;
-    *total_samples += sample_count;
-    let t_end_ms = (*total_samples * 1000) / 16000;
-    let prosody = compute_prosody(samples);
+    let message = match (lag_change, dropped_ms > 0) {
+        (_, true) => format!(
+            "Captions are lagging; skipped {:.1}s of audio to catch up",
+            dropped_ms as f64 / 1000.0
+        ),
+        (Some(true), false) => "Captions are lagging behind the conversation".to_string(),
+        (Some(false), false) => "Captions caught up".to_string(),
+        (None, false) if stage_change.is_some() && slot.pacing.stage() != PacingStage::Normal => {
+            "Captions are lagging; reducing transcription work".to_string()
+        }
+        (None, false) => return,
+    };
+    let state = if lag_change == Some(false) && dropped_ms == 0 {
+        "listening"
+    } else {
+        "warning"
+    };
 
-    let temp_wm = WhisperManager::new(model_path.to_string(), language.to_string());
-    match temp_wm.transcribe(app, samples).await {
-        Ok(results) => {
-            for result in results {
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Status {
+            state: state.to_string(),
+            message,
+            lag: Some(LagReport {
+                realtimeFactor: slot.pacing.realtime_factor(),
+                queuedMs: queued_ms,
+                droppedMs: dropped_ms,
+                stage: slot.pacing.stage().as_str().to_string(),
+            }),
+            language: None,
+        },
+    );
+}
+
+/// Tell the frontend when the detected language of the session changes or
+/// locks.
+fn report_language(app: &tauri::AppHandle, slot: &mut EngineSlot) {
+    let Some(report) = slot.language.take_report() else {
+        return;
+    };
+    let name = language::display_name(&report.language).unwrap_or(report.language.clone());
+    let message = if report.locked {
+        format!("Language locked to {}", name)
+    } else if report.previous_language.is_none() {
+        format!("Detected language: {}", name)
+    } else {
+        format!("Language changed to {}", name)
+    };
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Status {
+            state: "listening".to_string(),
+            message,
+            lag: None,
+            language: Some(report),
+        },
+    );
+}
+
+/// Reload the engine with the next smaller model on disk. Returns false when
+/// there is none or it fails to load, leaving the current engine in place.
+async fn switch_to_smaller_model(app: &tauri::AppHandle, slot: &mut EngineSlot) -> bool {
+    let Some(model_path) = asr::smaller_model(&slot.config.model_path) else {
+        return false;
+    };
+    let config = AsrConfig {
+        model_path,
+        ..slot.config.clone()
+    };
+
+    match asr::create_engine(slot.kind, app, config.clone()).await {
+        Ok(mut engine) => {
+            // A configured language is already in `config`; only a detected
+            // one may have been locked since.
+            if slot.language.is_detecting() {
+                engine.set_language(slot.language.forced());
+            }
+            log::warn!(
+                "Transcription falling behind; switching from {} to {}",
+                slot.config.model_path,
+                config.model_path
+            );
+            slot.engine.shutdown();
+            slot.engine = engine;
+            slot.config = config;
+            true
+        }
+        Err(error) => {
+            log::error!(
+                "Failed to load smaller model {}: {}",
+                config.model_path,
+                error
+            );
+            false
+        }
+    }
+}
+
+/// Benchmark the engine, or reuse an earlier benchmark of the same setup.
+/// A model picked for this machine steps down to smaller models on disk until
+/// one keeps up with real time; one the user chose is kept. Returns `None`
+/// when the benchmark itself fails.
+async fn calibrate(
+    app: &tauri::AppHandle,
+    slot: &mut EngineSlot,
+    auto_picked: bool,
+) -> Option<hardware::Calibration> {
+    loop {
+        let engine = slot.engine.name();
+        let cached =
+            hardware::cached_calibration(app, &slot.config.model_path, engine, slot.config.threads);
+        let calibration = match cached {
+            Some(calibration) => calibration,
+            None => {
                 let _ = app.emit(
                     "asr-event",
-                    ASREvent::Final {
-                        text: result.text,
-                        tStartMs: t_start_ms + result.t_start_ms,
-                        tEndMs: t_end_ms.min(t_start_ms + result.t_end_ms),
-                        speaker: None,
-                        speakerRole: Some(speaker_role.to_string()),
-                        audioSource: Some(audio_source.to_string()),
-                        prosodyEnergy: Some(prosody.energy),
-                        prosodyPauseRatio: Some(prosody.pause_ratio),
-                        prosodyVoicedMs: Some(prosody.voiced_ms),
-                        prosodySnrDb: Some(prosody.snr_db),
-                        confidence: None,
-                        sequence: *sequence,
+                    ASREvent::Status {
+                        state: "loading".to_string(),
+                        message: "Checking transcription speed on this machine".to_string(),
+                        lag: None,
+                        language: None,
                     },
                 );
-                *sequence += 1;
+                let audio = hardware::benchmark_audio();
+                // The first decode may still be loading the model; time the second.
+                let warm_up = slot.engine.transcribe(&audio[..16_000]).await;
+                let started = Instant::now();
+                let timed = match warm_up {
+                    Ok(_) => slot.engine.transcribe(&audio).await,
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = timed {
+                    log::warn!(
+                        "Skipping calibration of {}: {}",
+                        slot.config.model_path,
+                        error
+                    );
+                    return None;
+                }
+                let calibration = hardware::Calibration::new(
+                    &slot.config.model_path,
+                    engine,
+                    slot.config.threads,
+                    started.elapsed(),
+                );
+                if let Err(error) = hardware::save_calibration(app, &calibration) {
+                    log::warn!("Failed to save calibration: {}", error);
+                }
+                calibration
             }
+        };
+
+        log::info!(
+            "Calibration of {} on {} threads: real-time factor {:.2}",
+            calibration.model_path,
+            calibration.threads,
+            calibration.realtime_factor
+        );
+        if calibration.keeps_up() {
+            return Some(calibration);
+        }
+        if !auto_picked {
+            log::warn!(
+                "{} may fall behind on this machine (real-time factor {:.2}); keeping it as chosen",
+                calibration.model_path,
+                calibration.realtime_factor
+            );
+            return Some(calibration);
+        }
+        if !switch_to_smaller_model(app, slot).await {
+            return Some(calibration);
+        }
+    }
+}
+
+fn emit_final(
+    app: &tauri::AppHandle,
+    result: AsrSegment,
+    audio_source: &str,
+    speaker_role: &str,
+    prosody: ProsodySnapshot,
+    sequence: u32,
+) {
+    let event = ASREvent::Final {
+        text: result.text,
+        tStartMs: result.t_start_ms,
+        tEndMs: result.t_end_ms,
+        speaker: None,
+        speakerRole: Some(speaker_role.to_string()),
+        audioSource: Some(audio_source.to_string()),
+        prosodyEnergy: Some(prosody.energy),
+        prosodyPauseRatio: Some(prosody.pause_ratio),
+        prosodyVoicedMs: Some(prosody.voiced_ms),
+        prosodySnrDb: Some(prosody.snr_db),
+        prosodyRawSnrDb: prosody.raw_snr_db,
+        confidence: result.confidence,
+        language: result.language,
+        languageProbability: result.language_probability,
+        translation: result.translation,
+        sequence,
+        words: result.words,
+    };
+    // Journal first, so a final the webview saw is never missing on resume.
+    with_journal(app, |journal| journal.record_final(&event));
+    let _ = app.emit("asr-event", event);
+}
+
+/// Run `action` on the session journal, if any. A journal that fails is
+/// dropped with a warning rather than stopping transcription.
+fn with_journal(
+    app: &tauri::AppHandle,
+    action: impl FnOnce(&mut SessionJournal) -> Result<(), String>,
+) {
+    let state = app.state::<TranscriptionState>();
+    let Ok(mut journal) = state.journal.lock() else {
+        return;
+    };
+    let Some(error) = journal.as_mut().and_then(|journal| action(journal).err()) else {
+        return;
+    };
+    *journal = None;
+    log::error!("Session journal failed: {}", error);
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Status {
+            state: "warning".to_string(),
+            message: format!(
+                "This session can no longer be recovered after a crash: {}",
+                error
+            ),
+            lag: None,
+            language: None,
+        },
+    );
+}
+
+/// `audioSource` and `speakerRole` reported for finals from `role`.
+fn source_labels(role: SourceRole) -> (&'static str, &'static str) {
+    match role {
+        SourceRole::Microphone => ("microphone", "SALES"),
+        SourceRole::Loopback => ("systemAudio", "CLIENT"),
+    }
+}
+
+fn emit_error(app: &tauri::AppHandle, error: &AppError, role: Option<SourceRole>) {
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Error {
+            error: error.clone(),
+            audioSource: role.map(|role| source_labels(role).0.to_string()),
+        },
+    );
+}
+
+/// Reports the session task's transcription errors without repeating the
+/// same one every chunk, and keeps the first the session cannot get past.
+struct ErrorReporter {
+    /// When each error code was last emitted, per source.
+    emitted: Vec<(&'static str, Option<SourceRole>, Instant)>,
+    fatal: Option<AppError>,
+}
+
+impl ErrorReporter {
+    fn new() -> Self {
+        Self {
+            emitted: Vec::new(),
+            fatal: None,
+        }
+    }
+
+    fn report(&mut self, app: &tauri::AppHandle, error: &AppError, role: Option<SourceRole>) {
+        // The sidecar cannot be run or ran out of restarts; every later
+        // chunk would fail the same way.
+        if matches!(error, AppError::SidecarCrashed(_)) {
+            self.fatal.get_or_insert_with(|| error.clone());
+        }
+
+        self.emitted
+            .retain(|(_, _, at)| at.elapsed() < REPEATED_ERROR_INTERVAL);
+        let repeated = self
+            .emitted
+            .iter()
+            .any(|(code, source, _)| *code == error.code() && *source == role);
+        if repeated {
+            return;
+        }
+        self.emitted.push((error.code(), role, Instant::now()));
+        emit_error(app, error, role);
+    }
+
+    /// Report an error the session cannot get past, whatever its kind.
+    fn fail(&mut self, app: &tauri::AppHandle, error: AppError) {
+        emit_error(app, &error, None);
+        self.fatal.get_or_insert(error);
+    }
+
+    /// The error that ends the session, if one was reported.
+    fn fatal(&self) -> Option<&AppError> {
+        self.fatal.as_ref()
+    }
+}
+
+/// Decode one utterance and stage its finals for cross-source duplicate
+/// suppression; `emit_released` sends them once their hold expires.
+async fn transcribe_source_chunk(
+    slot: &mut EngineSlot,
+    role: SourceRole,
+    utterance: Utterance,
+    stitcher: &mut TranscriptStitcher,
+    duplicates: &mut DuplicateSuppressor<FinalContext>,
+    processor: Option<&SourceProcessor>,
+    now_ms: i64,
+) -> Result<(), AppError> {
+    if utterance.samples.is_empty() {
+        return Ok(());
+    }
+
+    // Re-decode the tail of the previous chunk with this one so words cut at
+    // the boundary are recognised whole, then drop what was already emitted.
+    let utterance_start = utterance.start_sample;
+    let window = stitcher.extend_with_context(utterance);
+    let mut prosody = compute_prosody(&window.samples);
+    prosody.raw_snr_db = processor.map(|processor| {
+        compute_prosody(processor.raw(window.start_sample, window.samples.len())).snr_db
+    });
+
+    match slot.transcribe(&window.samples).await {
+        Ok(results) => {
+            let mut stitched = stitcher.reconcile(&window, results);
+            // Only the new audio is translated; the carried-over context was
+            // translated with the previous chunk.
+            let context_len = (utterance_start - window.start_sample) as usize;
+            slot.translate(
+                role,
+                &window.samples[context_len..],
+                &mut stitched,
+                pacing::samples_to_ms(utterance_start as usize),
+            )
+            .await;
+            for result in stitched {
+                let context = FinalContext {
+                    prosody,
+                    sequence: None,
+                };
+                duplicates.push(role, result, prosody.energy, context, now_ms);
+            }
+            Ok(())
         }
         Err(error) => {
+            let (audio_source, speaker_role) = source_labels(role);
             log::error!(
-                "Transcription error on source {} (role {}): {}",
+                "Transcription error ({} engine) on source {} (role {}): {}",
+                slot.engine.name(),
                 audio_source,
                 speaker_role,
                 error
             );
+            Err(error)
         }
     }
 }
 
-#[tauri::command]
-async fn start_transcription(
+/// Record the next `samples` of `role`, then silence for the `dropped`
+/// samples the capture ring lost after them so the track keeps its timeline.
+fn record_source(
+    app: &tauri::AppHandle,
+    recorder: &mut SessionRecorder,
+    role: SourceRole,
+    samples: &[i16],
+    dropped: u64,
+) {
+    if let Err(error) = recorder.write(role, samples, dropped) {
+        log::error!("Recording {} failed: {}", role.label(), error);
+        let _ = app.emit(
+            "asr-event",
+            ASREvent::Status {
+                state: "warning".to_string(),
+                message: format!("{} is no longer being recorded: {}", role.label(), error),
+                lag: None,
+                language: None,
+            },
+        );
+    }
+}
+
+fn emit_released(
+    app: &tauri::AppHandle,
+    finals: Vec<StagedFinal<FinalContext>>,
+    sequence: &mut u32,
+) {
+    for staged in finals {
+        let (audio_source, speaker_role) = source_labels(staged.role);
+        let final_sequence = staged.extra.sequence.unwrap_or_else(|| {
+            *sequence += 1;
+            *sequence - 1
+        });
+        emit_final(
+            app,
+            staged.segment,
+            audio_source,
+            speaker_role,
+            staged.extra.prosody,
+            final_sequence,
+        );
+    }
+}
+
+/// Emit the finals whose duplicate hold has expired (all of them without
+/// `hold`), then journal the progress of audio whose finals are now out.
+fn release_finals(
+    app: &tauri::AppHandle,
+    duplicates: &mut DuplicateSuppressor<FinalContext>,
+    progress: &mut Vec<(i64, SourceRole, i64)>,
+    now_ms: i64,
+    hold: bool,
+    sequence: &mut u32,
+) {
+    let finals = if hold {
+        duplicates.release(now_ms)
+    } else {
+        duplicates.flush(now_ms)
+    };
+    emit_released(app, finals, sequence);
+    progress.retain(|&(staged_at_ms, role, until_sample)| {
+        if hold && staged_at_ms + dedup::HOLD_MS > now_ms {
+            return true;
+        }
+        with_journal(app, |journal| journal.record_progress(role, until_sample));
+        false
+    });
+}
+
+/// Segments a streaming window committed in one tick, numbered and ready
+/// for duplicate suppression.
+struct CommittedFinals {
+    finals: Vec<(AsrSegment, FinalContext)>,
+    /// Window position once they are out, for the journal.
+    until_sample: i64,
+}
+
+/// Streaming counterpart of `transcribe_source_chunk`: re-decode the source's
+/// sliding window, emit its unstable tail as ASR_PARTIAL and return stable
+/// segments to be staged as finals.
+async fn stream_source_chunk(
+    app: &tauri::AppHandle,
+    slot: &mut EngineSlot,
+    role: SourceRole,
+    samples: &[i16],
+    window: &mut StreamingWindow,
+    processor: Option<&SourceProcessor>,
+    sequence: &mut u32,
+) -> Result<Option<CommittedFinals>, AppError> {
+    let (audio_source, speaker_role) = source_labels(role);
+    // Nothing new since the last decode; the hypothesis would not change
+    // unless it is about to be committed for good.
+    if samples.is_empty() && !window.must_flush() {
+        return Ok(None);
+    }
+    window.push(samples);
+
+    // Fresh audio is silent and nothing is waiting to be committed: let the
+    // window age out the silence without running the ASR.
+    if !audio::contains_speech(samples) && !window.has_hypothesis() {
+        window.update(Vec::new());
+        release_partial(app, role, window);
+        return Ok(None);
+    }
+
+    let segments = match slot.transcribe(window.window()).await {
+        Ok(segments) => segments,
+        Err(error) => {
+            log::error!(
+                "Streaming transcription error ({} engine) on source {} (role {}): {}",
+                slot.engine.name(),
+                audio_source,
+                speaker_role,
+                error
+            );
+            return Err(error);
+        }
+    };
+
+    let committed_start = window.start_sample();
+    let mut update = window.update(segments);
+    let mut committed = None;
+    if !update.committed.is_empty() {
+        // Committed words have settled; only they are worth translating.
+        slot.translate(
+            role,
+            &update.committed_audio,
+            &mut update.committed,
+            pacing::samples_to_ms(committed_start as usize),
+        )
+        .await;
+        let mut prosody = compute_prosody(&update.committed_audio);
+        prosody.raw_snr_db = processor.map(|processor| {
+            compute_prosody(processor.raw(committed_start, update.committed_audio.len())).snr_db
+        });
+        let finals = update
+            .committed
+            .into_iter()
+            .map(|result| {
+                let context = FinalContext {
+                    prosody,
+                    sequence: Some(window.final_sequence(sequence)),
+                };
+                (result, context)
+            })
+            .collect();
+        committed = Some(CommittedFinals {
+            finals,
+            until_sample: window.start_sample(),
+        });
+    }
+
+    if let Some(partial) = update.partial {
+        let partial_sequence = window.partial_sequence(sequence);
+        let _ = app.emit(
+            "asr-event",
+            ASREvent::Partial {
+                text: partial.text,
+                tStartMs: partial.t_start_ms,
+                tEndMs: partial.t_end_ms,
+                speakerRole: Some(speaker_role.to_string()),
+                audioSource: Some(audio_source.to_string()),
+                sequence: partial_sequence,
+            },
+        );
+    } else if !window.has_hypothesis() {
+        release_partial(app, role, window);
+    }
+    Ok(committed)
+}
+
+/// Withdraw the window's partial when no final will take over its sequence.
+fn release_partial(app: &tauri::AppHandle, role: SourceRole, window: &mut StreamingWindow) {
+    if let Some(released) = window.release_sequence() {
+        withdraw_partial(app, role, released);
+    }
+}
+
+/// Stage a streaming tick's finals for duplicate suppression like batch
+/// finals, withdrawing the partial of any copy dropped as a duplicate.
+fn stage_committed(
+    app: &tauri::AppHandle,
+    duplicates: &mut DuplicateSuppressor<FinalContext>,
+    progress: &mut Vec<(i64, SourceRole, i64)>,
+    role: SourceRole,
+    committed: CommittedFinals,
+    now_ms: i64,
+) {
+    for (result, context) in committed.finals {
+        let energy = context.prosody.energy;
+        let Some(dropped) = duplicates.push(role, result, energy, context, now_ms) else {
+            continue;
+        };
+        if let Some(sequence) = dropped.extra.sequence {
+            withdraw_partial(app, dropped.role, sequence);
+        }
+    }
+    progress.push((now_ms, role, committed.until_sample));
+}
+
+/// Clear the partial shown under `sequence` when its words were dropped
+/// rather than committed.
+fn withdraw_partial(app: &tauri::AppHandle, role: SourceRole, sequence: u32) {
+    let (audio_source, speaker_role) = source_labels(role);
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Partial {
+            text: String::new(),
+            tStartMs: 0,
+            tEndMs: 0,
+            speakerRole: Some(speaker_role.to_string()),
+            audioSource: Some(audio_source.to_string()),
+            sequence,
+        },
+    );
+}
+
+/// Everything `start_transcription` does once the session is `Starting`:
+/// load the model, open capture and spawn the session's task.
+async fn start_session(
     app: tauri::AppHandle,
-    state: State<'_, TranscriptionState>,
-    model_path: String,
+    model_path: Option<String>,
     language: String,
     enable_system_audio: Option<bool>,
-) -> Result<(), String> {
+    engine: Option<AsrEngineKind>,
+    streaming: Option<bool>,
+    capture: Option<CaptureOptions>,
+) -> Result<(), AppError> {
+    let state = app.state::<TranscriptionState>();
+    let mut profile = hardware::HardwareProfile::detect();
+    let auto_picked = model_path.is_none() && !models::has_default(&app);
+    let model_path = match model_path {
+        Some(path) => path,
+        None => models::default_model_path(&app, profile.recommended_model)?,
+    };
     let system_audio_enabled = enable_system_audio.unwrap_or(true);
-
-    // Start audio capture
-    {
-        let mut audio = state.audio.lock().map_err(|e| e.to_string())?;
-        audio.start(system_audio_enabled)?;
+    let streaming_enabled = streaming.unwrap_or(false);
+
+    let CaptureOptions {
+        processing,
+        recording,
+        journal,
+        language_policy,
+        translation,
+    } = capture.unwrap_or_default();
+    let translation = translation.map(Translation::new).transpose()?;
+    if let Some(translation) = &translation {
+        log::info!(
+            "Translating into {} with the {} translator",
+            translation.target(),
+            translation.translator_name()
+        );
     }
 
-    // Initialize whisper manager
-    {
-        let mut whisper = state.whisper.lock().map_err(|e| e.to_string())?;
-        *whisper = Some(WhisperManager::new(model_path, language));
+    // Create the engine before capture starts so model errors reach the
+    // caller. Only the sidecar's one-shot CLI fallback loads the model later,
+    // per chunk.
+    let kind = engine.unwrap_or_default();
+    let config = AsrConfig {
+        model_path,
+        language,
+        threads: profile.threads,
+    };
+    let mut slot = EngineSlot {
+        engine: asr::create_engine(kind, &app, config.clone()).await?,
+        kind,
+        language: LanguageTracker::new(&config.language, language_policy.unwrap_or_default()),
+        config,
+        pacing: PacingController::new(),
+        translation,
+    };
+    if kind != AsrEngineKind::Mock {
+        profile.calibration = calibrate(&app, &mut slot, auto_picked).await;
     }
+    profile.selected_model = Some(slot.config.model_path.clone());
+    log::info!(
+        "Transcribing with {} on {} threads ({} physical cores, recommended model {})",
+        slot.config.model_path,
+        profile.threads,
+        profile.hardware.physical_cores,
+        profile.recommended_model
+    );
+    *state.profile.lock().map_err(|e| e.to_string())? = Some(profile);
 
+    // Start audio capture
+    let loopback_error = {
+        let selection = devices::load_selection(&app);
+        let mut audio = state.audio.lock().map_err(|e| e.to_string())?;
+        audio
+            .start(system_audio_enabled, &selection)
+            .map_err(AppError::capture)?;
+        audio.loopback_error().map(str::to_string)
+    };
+    // Without a loopback stream there is only the microphone to transcribe.
+    let system_audio_enabled = system_audio_enabled && loopback_error.is_none();
+    let mut capture_processing = CaptureProcessing::new(processing, system_audio_enabled);
     {
-        let mut recording = state.is_recording.lock().map_err(|e| e.to_string())?;
-        *recording = true;
+        let mut metrics = state.echo_metrics.lock().map_err(|e| e.to_string())?;
+        *metrics = capture_processing.echo_metrics();
     }
+    // A journaled session that was cut short continues on its timeline.
+    let mut journal = journal
+        .as_ref()
+        .map(|options| SessionJournal::open(&app, options))
+        .transpose()
+        .map_err(|e| format!("Failed to open session journal: {}", e))?;
+    let start_sample = journal.as_ref().map_or(0, SessionJournal::start_sample);
+    let mut sequence = journal.as_ref().map_or(0, SessionJournal::next_sequence);
+    let leftover = journal
+        .as_mut()
+        .map(SessionJournal::take_leftover)
+        .unwrap_or_default();
+    let mut recorder = recording
+        .as_ref()
+        .map(|options| {
+            SessionRecorder::start(
+                &app,
+                options,
+                system_audio_enabled,
+                pacing::samples_to_ms(start_sample as usize),
+            )
+        })
+        .transpose()
+        .map_err(|e| format!("Failed to start recording: {}", e))?;
     {
-        let mut paused = state.is_paused.lock().map_err(|e| e.to_string())?;
-        *paused = false;
+        let mut current = state.journal.lock().map_err(|e| e.to_string())?;
+        *current = journal;
     }
 
     show_quick_note_window(&app)?;
+    let commands = {
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        set_phase(&app, &mut session, SessionPhase::Listening)?;
+        session.open_channel()
+    };
 
     app.emit(
         "asr-event",
@@ -528,165 +1522,631 @@ async fn start_transcription(
             } else {
                 "Listening (desktop mic only)...".to_string()
             },
+            lag: None,
+            language: None,
         },
     )
     .map_err(|e| e.to_string())?;
 
+    if let Some(error) = loopback_error {
+        let _ = app.emit(
+            "asr-event",
+            ASREvent::Status {
+                state: "warning".to_string(),
+                message: format!("System audio is not being captured: {}", error),
+                lag: None,
+                language: None,
+            },
+        );
+    }
+
     // Start the transcription loop in a background task
     let app_handle = app.clone();
     let system_audio_enabled_for_loop = system_audio_enabled;
 
-    tauri::async_runtime::spawn(async move {
-        let mut sequence: u32 = 0;
-        let mut mic_total_samples: i64 = 0;
-        let mut system_total_samples: i64 = 0;
+    // The loop owns the engine so the model stays loaded for the session.
+    let task = tauri::async_runtime::spawn(async move {
+        let mut mic_vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let mut system_vad = VoiceActivitySegmenter::new(VadConfig::default());
+        let mut mic_stitcher = TranscriptStitcher::new();
+        let mut system_stitcher = TranscriptStitcher::new();
+        let mut mic_window = StreamingWindow::new();
+        let mut system_window = StreamingWindow::new();
+        let mut duplicates = DuplicateSuppressor::new();
+        // Capture clock of the pipeline, shared by both sources.
+        let mut captured_samples = start_sample as usize;
+        // Transcribed-until positions waiting for their finals' release.
+        let mut pending_progress: Vec<(i64, SourceRole, i64)> = Vec::new();
+        // Set once the audio left at stop has been drained.
+        let mut drained_at_stop = false;
+        let mut inbox = SessionInbox::new(commands);
+        let mut next_health_check = Instant::now() + CAPTURE_HEALTH_INTERVAL;
+        let mut errors = ErrorReporter::new();
+
+        // Everything is positioned on the session timeline.
+        mic_vad.start_at(start_sample);
+        system_vad.start_at(start_sample);
+        mic_window.start_at(start_sample);
+        system_window.start_at(start_sample);
+        capture_processing.start_at(start_sample);
+
+        // Audio captured before a crash but never transcribed goes first.
+        for leftover in leftover {
+            let mut vad = VoiceActivitySegmenter::new(VadConfig::default());
+            vad.start_at(leftover.start_sample);
+            let mut utterances = vad.push(&leftover.samples);
+            utterances.extend(vad.flush());
+            let stitcher = match leftover.role {
+                SourceRole::Microphone => &mut mic_stitcher,
+                SourceRole::Loopback => &mut system_stitcher,
+            };
+            for utterance in utterances {
+                if let Err(error) = transcribe_source_chunk(
+                    &mut slot,
+                    leftover.role,
+                    utterance,
+                    stitcher,
+                    &mut duplicates,
+                    None,
+                    pacing::samples_to_ms(captured_samples),
+                )
+                .await
+                {
+                    errors.report(&app_handle, &error, Some(leftover.role));
+                }
+            }
+            pending_progress.push((
+                0,
+                leftover.role,
+                leftover.start_sample + leftover.samples.len() as i64,
+            ));
+        }
+        release_finals(
+            &app_handle,
+            &mut duplicates,
+            &mut pending_progress,
+            pacing::samples_to_ms(captured_samples),
+            false,
+            &mut sequence,
+        );
 
         loop {
-            let (is_recording, is_paused) = {
-                let state_ref = app_handle.state::<TranscriptionState>();
-                let recording = state_ref.is_recording.lock().map(|r| *r).unwrap_or(false);
-                let paused = state_ref.is_paused.lock().map(|p| *p).unwrap_or(false);
-                (recording, paused)
-            };
-            if !is_recording {
+            inbox.poll();
+            // After a stop, one last pass transcribes what is still buffered.
+            let stopping = inbox.is_stopping();
+            if stopping && (inbox.is_cancelled() || drained_at_stop) {
                 break;
             }
-            if is_paused {
-                tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
-                continue;
+            if let Some(error) = errors.fatal() {
+                log::error!("Ending transcription session: {}", error);
+                break;
             }
+            if !stopping && Instant::now() >= next_health_check {
+                next_health_check = Instant::now() + CAPTURE_HEALTH_INTERVAL;
+                let notices = check_capture_health(&app_handle).await;
+                report_capture_notices(&app_handle, notices);
+            }
+            let paused = inbox.is_paused();
 
             // Wait for audio to accumulate.
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            if !stopping {
+                tokio::time::sleep(if streaming_enabled {
+                    STREAMING_TICK_INTERVAL
+                } else {
+                    VAD_POLL_INTERVAL
+                })
+                .await;
+            }
 
             // Drain audio buffers by source.
-            let drained = {
+            let mut drained = {
                 let state_ref = app_handle.state::<TranscriptionState>();
-                let audio = state_ref.audio.lock().unwrap();
-                audio.drain_buffers()
+                let mut audio = match state_ref.audio.lock() {
+                    Ok(audio) => audio,
+                    Err(error) => {
+                        errors.fail(&app_handle, AppError::Internal(error.to_string()));
+                        break;
+                    }
+                };
+                if stopping {
+                    audio.finish()
+                } else {
+                    audio.drain_buffers()
+                }
             };
-
-            let has_mic_audio = !drained.microphone_samples.is_empty();
-            let has_system_audio = !drained.system_samples.is_empty();
-            if !has_mic_audio && !has_system_audio {
-                continue;
+            drained_at_stop = stopping;
+            report_capture_overflow(&app_handle, &drained);
+            if paused {
+                // Keep draining so nothing piles up in the rings, but hand the
+                // audio on as lost: every timeline moves past the pause.
+                drained.discard();
             }
 
-            // Extract whisper config without holding lock across await
-            let wm_config = {
-                let state_ref = app_handle.state::<TranscriptionState>();
-                let guard = state_ref.whisper.lock().unwrap();
-                guard
-                    .as_ref()
-                    .map(|wm| (wm.model_path().to_string(), wm.language().to_string()))
+            // Recordings keep the audio as captured, before any cleanup.
+            if let Some(recorder) = recorder.as_mut() {
+                record_source(
+                    &app_handle,
+                    recorder,
+                    SourceRole::Microphone,
+                    &drained.microphone_samples,
+                    drained.microphone_dropped,
+                );
+                if system_audio_enabled_for_loop {
+                    record_source(
+                        &app_handle,
+                        recorder,
+                        SourceRole::Loopback,
+                        &drained.system_samples,
+                        drained.system_dropped,
+                    );
+                }
+            }
+            // What the rest of the pipeline sees if processing fails: the
+            // tick's audio, lost.
+            let lost = AudioDrain {
+                microphone_samples: Vec::new(),
+                system_samples: Vec::new(),
+                microphone_dropped: drained.microphone_samples.len() as u64
+                    + drained.microphone_dropped,
+                system_dropped: drained.system_samples.len() as u64 + drained.system_dropped,
+            };
+            let job = tauri::async_runtime::spawn_blocking(move || {
+                capture_processing.process(&mut drained);
+                (capture_processing, drained)
+            });
+            (capture_processing, drained) = match job.await {
+                Ok(processed) => processed,
+                Err(error) => {
+                    // Recorded already; everything downstream moves past the
+                    // lost audio as it does past audio the rings dropped, and
+                    // cleanup starts over after it.
+                    log::error!("Audio processing failed: {}", error);
+                    let mut restarted =
+                        CaptureProcessing::new(processing, system_audio_enabled_for_loop);
+                    restarted.start_at(captured_samples as i64 + lost.microphone_dropped as i64);
+                    (restarted, lost)
+                }
             };
+            if let Some(echo) = capture_processing.echo_metrics() {
+                let state_ref = app_handle.state::<TranscriptionState>();
+                if let Ok(mut metrics) = state_ref.echo_metrics.lock() {
+                    *metrics = Some(echo);
+                };
+            }
+            // Everything the pipeline is given is journaled, before any
+            // backlog is dropped, so the tracks share the events' timeline.
+            with_journal(&app_handle, |journal| {
+                journal.record_audio(
+                    SourceRole::Microphone,
+                    &drained.microphone_samples,
+                    drained.microphone_dropped,
+                )?;
+                if system_audio_enabled_for_loop {
+                    journal.record_audio(
+                        SourceRole::Loopback,
+                        &drained.system_samples,
+                        drained.system_dropped,
+                    )?;
+                }
+                Ok(())
+            });
+
+            if streaming_enabled {
+                let mut mic_samples = drained.microphone_samples;
+                let mut system_samples = drained.system_samples;
+                captured_samples += mic_samples.len() + drained.microphone_dropped as usize;
+                let dropped_ms = cap_streaming_backlog(
+                    &mut mic_samples,
+                    drained.microphone_dropped,
+                    &mut mic_window,
+                ) + cap_streaming_backlog(
+                    &mut system_samples,
+                    drained.system_dropped,
+                    &mut system_window,
+                );
+                let queued_ms = pacing::samples_to_ms(
+                    mic_window.window().len()
+                        + mic_samples.len()
+                        + system_window.window().len()
+                        + system_samples.len(),
+                );
+                if stopping {
+                    mic_window.finish();
+                    system_window.finish();
+                }
+
+                let now_ms = pacing::samples_to_ms(captured_samples);
+                for (role, samples, window) in [
+                    (SourceRole::Microphone, &mic_samples, &mut mic_window),
+                    (SourceRole::Loopback, &system_samples, &mut system_window),
+                ] {
+                    if role == SourceRole::Loopback && !system_audio_enabled_for_loop {
+                        continue;
+                    }
+                    let committed = match stream_source_chunk(
+                        &app_handle,
+                        &mut slot,
+                        role,
+                        samples,
+                        window,
+                        capture_processing.processor(role),
+                        &mut sequence,
+                    )
+                    .await
+                    {
+                        Ok(committed) => committed,
+                        Err(error) => {
+                            errors.report(&app_handle, &error, Some(role));
+                            None
+                        }
+                    };
+                    if stopping {
+                        // The window's last decode, even if it failed: a
+                        // partial no final took over is gone for good.
+                        release_partial(&app_handle, role, window);
+                    }
+                    if let Some(committed) = committed {
+                        stage_committed(
+                            &app_handle,
+                            &mut duplicates,
+                            &mut pending_progress,
+                            role,
+                            committed,
+                            now_ms,
+                        );
+                    }
+                }
+                release_finals(
+                    &app_handle,
+                    &mut duplicates,
+                    &mut pending_progress,
+                    now_ms,
+                    system_audio_enabled_for_loop,
+                    &mut sequence,
+                );
 
-            let Some((mp, lang)) = wm_config else {
+                adapt_to_lag(
+                    &app_handle,
+                    queued_ms,
+                    dropped_ms,
+                    &mut slot,
+                    [&mut mic_vad, &mut system_vad],
+                    [&mut mic_window, &mut system_window],
+                )
+                .await;
+                report_language(&app_handle, &mut slot);
                 continue;
+            }
+
+            // Only complete utterances reach the ASR; silence never does.
+            let mut mic_utterances =
+                mic_vad.push_drained(&drained.microphone_samples, drained.microphone_dropped);
+            let mut system_utterances = if system_audio_enabled_for_loop {
+                system_vad.push_drained(&drained.system_samples, drained.system_dropped)
+            } else {
+                Vec::new()
             };
+            if stopping {
+                // Speech still in progress at stop ends here.
+                mic_utterances.extend(mic_vad.flush());
+                system_utterances.extend(system_vad.flush());
+            }
+            captured_samples +=
+                drained.microphone_samples.len() + drained.microphone_dropped as usize;
+            let now_ms = pacing::samples_to_ms(captured_samples);
+            // With one source there is nothing to hold finals back for.
+            let hold = system_audio_enabled_for_loop;
+            release_finals(
+                &app_handle,
+                &mut duplicates,
+                &mut pending_progress,
+                now_ms,
+                hold,
+                &mut sequence,
+            );
+            if mic_utterances.is_empty() && system_utterances.is_empty() {
+                continue;
+            }
 
-            let _ = app_handle.emit(
-                "asr-event",
-                ASREvent::Status {
-                    state: "processing".to_string(),
-                    message: "Processing audio...".to_string(),
-                },
+            // Never let more audio wait than the ASR could catch up on.
+            let dropped_ms = pacing::cap_utterance_queue(&mut mic_utterances)
+                + pacing::cap_utterance_queue(&mut system_utterances);
+            let queued_ms = pacing::samples_to_ms(
+                mic_utterances
+                    .iter()
+                    .chain(&system_utterances)
+                    .map(|utterance| utterance.samples.len())
+                    .sum(),
             );
 
-            if has_mic_audio {
-                transcribe_source_chunk(
-                    &app_handle,
-                    &mp,
-                    &lang,
-                    "microphone",
-                    "SALES",
-                    &drained.microphone_samples,
-                    &mut mic_total_samples,
-                    &mut sequence,
+            if !paused {
+                let _ = app_handle.emit(
+                    "asr-event",
+                    ASREvent::Status {
+                        state: "processing".to_string(),
+                        message: "Processing audio...".to_string(),
+                        lag: None,
+                        language: None,
+                    },
+                );
+            }
+
+            for (role, utterances) in [
+                (SourceRole::Microphone, &mic_utterances),
+                (SourceRole::Loopback, &system_utterances),
+            ] {
+                if let Some(last) = utterances.last() {
+                    let until_sample = last.start_sample + last.samples.len() as i64;
+                    pending_progress.push((now_ms, role, until_sample));
+                }
+            }
+
+            for utterance in mic_utterances {
+                if let Err(error) = transcribe_source_chunk(
+                    &mut slot,
+                    SourceRole::Microphone,
+                    utterance,
+                    &mut mic_stitcher,
+                    &mut duplicates,
+                    capture_processing.processor(SourceRole::Microphone),
+                    now_ms,
                 )
-                .await;
+                .await
+                {
+                    errors.report(&app_handle, &error, Some(SourceRole::Microphone));
+                }
             }
 
-            if system_audio_enabled_for_loop && has_system_audio {
-                transcribe_source_chunk(
-                    &app_handle,
-                    &mp,
-                    &lang,
-                    "systemAudio",
-                    "CLIENT",
-                    &drained.system_samples,
-                    &mut system_total_samples,
-                    &mut sequence,
+            for utterance in system_utterances {
+                if let Err(error) = transcribe_source_chunk(
+                    &mut slot,
+                    SourceRole::Loopback,
+                    utterance,
+                    &mut system_stitcher,
+                    &mut duplicates,
+                    capture_processing.processor(SourceRole::Loopback),
+                    now_ms,
                 )
-                .await;
+                .await
+                {
+                    errors.report(&app_handle, &error, Some(SourceRole::Loopback));
+                }
+            }
+            release_finals(
+                &app_handle,
+                &mut duplicates,
+                &mut pending_progress,
+                now_ms,
+                hold,
+                &mut sequence,
+            );
+            if stopping {
+                break;
             }
 
-            let _ = app_handle.emit(
-                "asr-event",
-                ASREvent::Status {
-                    state: "listening".to_string(),
-                    message: if system_audio_enabled_for_loop {
-                        "Listening (desktop mic + system loopback)...".to_string()
-                    } else {
-                        "Listening (desktop mic only)...".to_string()
+            if !paused {
+                let _ = app_handle.emit(
+                    "asr-event",
+                    ASREvent::Status {
+                        state: "listening".to_string(),
+                        message: if system_audio_enabled_for_loop {
+                            "Listening (desktop mic + system loopback)...".to_string()
+                        } else {
+                            "Listening (desktop mic only)...".to_string()
+                        },
+                        lag: None,
+                        language: None,
                     },
-                },
+                );
+            }
+
+            adapt_to_lag(
+                &app_handle,
+                queued_ms,
+                dropped_ms,
+                &mut slot,
+                [&mut mic_vad, &mut system_vad],
+                [&mut mic_window, &mut system_window],
+            )
+            .await;
+            report_language(&app_handle, &mut slot);
+        }
+
+        let now_ms = pacing::samples_to_ms(captured_samples);
+        release_finals(
+            &app_handle,
+            &mut duplicates,
+            &mut pending_progress,
+            now_ms,
+            false,
+            &mut sequence,
+        );
+        if duplicates.suppressed() > 0 {
+            log::info!(
+                "Suppressed {} cross-source duplicate finals",
+                duplicates.suppressed()
             );
         }
+        if let Some(metrics) = capture_processing.echo_metrics() {
+            log::info!("Echo cancellation: {:?}", metrics);
+        }
+        if let Some(recorder) = recorder {
+            if let Err(error) = recorder.finish() {
+                log::error!("Failed to finish recording: {}", error);
+            }
+        }
+        let journal = app_handle
+            .state::<TranscriptionState>()
+            .journal
+            .lock()
+            .ok()
+            .and_then(|mut journal| journal.take());
+        // A failed session keeps its journal so it can be resumed.
+        if let (Some(journal), None) = (journal, errors.fatal()) {
+            if let Err(error) = journal.finish() {
+                log::error!("Failed to finish session journal: {}", error);
+            }
+        }
+        slot.engine.shutdown();
+
+        let state_ref = app_handle.state::<TranscriptionState>();
+        let next = if errors.fatal().is_some() {
+            if let Ok(mut audio) = state_ref.audio.lock() {
+                audio.stop();
+            }
+            SessionPhase::Failed
+        } else {
+            SessionPhase::Stopped
+        };
+        match state_ref.session.lock() {
+            Ok(mut session) => {
+                if let Err(error) = set_phase(&app_handle, &mut session, next) {
+                    log::error!("Transcription loop ended unexpectedly: {}", error);
+                }
+            }
+            Err(error) => log::error!("Session state unavailable at session end: {}", error),
+        };
     });
+    {
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        session.attach(task);
+    }
+
+    Ok(())
+}
 
+/// Move the session to `next` and tell the webview.
+fn set_phase(
+    app: &tauri::AppHandle,
+    session: &mut SessionController,
+    next: SessionPhase,
+) -> Result<(), SessionError> {
+    let previous = session.transition(next)?;
+    log::info!("Transcription session: {:?} -> {:?}", previous, next);
+    let _ = app.emit(
+        "asr-event",
+        ASREvent::Session {
+            phase: next,
+            previousPhase: previous,
+        },
+    );
     Ok(())
 }
 
+/// Start a session. Without `model_path` the default model is used, or
+/// without one the installed model best suited to this machine.
 #[tauri::command]
-async fn stop_transcription(
+async fn start_transcription(
     app: tauri::AppHandle,
-    state: State<'_, TranscriptionState>,
-) -> Result<(), String> {
+    model_path: Option<String>,
+    language: String,
+    enable_system_audio: Option<bool>,
+    engine: Option<AsrEngineKind>,
+    streaming: Option<bool>,
+    capture: Option<CaptureOptions>,
+) -> Result<(), AppError> {
+    let state = app.state::<TranscriptionState>();
     {
-        let mut recording = state.is_recording.lock().map_err(|e| e.to_string())?;
-        *recording = false;
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        set_phase(&app, &mut session, SessionPhase::Starting)?;
     }
-    {
-        let mut paused = state.is_paused.lock().map_err(|e| e.to_string())?;
-        *paused = false;
+
+    let result = start_session(
+        app.clone(),
+        model_path,
+        language,
+        enable_system_audio,
+        engine,
+        streaming,
+        capture,
+    )
+    .await;
+    if let Err(error) = &result {
+        emit_error(&app, error, None);
+        if let Ok(mut audio) = state.audio.lock() {
+            audio.stop();
+        }
+        if let Ok(mut journal) = state.journal.lock() {
+            *journal = None;
+        }
+        if let Ok(mut session) = state.session.lock() {
+            let _ = set_phase(&app, &mut session, SessionPhase::Failed);
+        }
     }
+    result
+}
 
-    {
-        let mut audio = state.audio.lock().map_err(|e| e.to_string())?;
-        audio.stop();
+#[tauri::command]
+async fn stop_transcription(
+    app: tauri::AppHandle,
+    state: State<'_, TranscriptionState>,
+    cancel: Option<bool>,
+) -> Result<(), AppError> {
+    let cancel = cancel.unwrap_or(false);
+    let task = {
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        set_phase(&app, &mut session, SessionPhase::Draining)?;
+        session.send(SessionCommand::Stop { cancel })?;
+        session.take_task()
+    };
+
+    // Without `cancel`, the task stops capture itself once it has drained
+    // and transcribed what is buffered.
+    if cancel {
+        state.audio.lock().map_err(|e| e.to_string())?.stop();
+    }
+    let finished = match task {
+        Some(task) => task.await.is_ok(),
+        None => false,
+    };
+    if !finished {
+        state.audio.lock().map_err(|e| e.to_string())?.stop();
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        let _ = set_phase(&app, &mut session, SessionPhase::Failed);
+        return Err(AppError::Internal(
+            "The transcription session ended unexpectedly".to_string(),
+        ));
     }
 
-    app.emit(
+    let _ = app.emit(
         "asr-event",
         ASREvent::Status {
             state: "stopped".to_string(),
             message: "Transcription stopped".to_string(),
+            lag: None,
+            language: None,
         },
-    )
-    .map_err(|e| e.to_string())?;
+    );
 
     Ok(())
 }
 
+/// Current phase of the transcription session, e.g. after the webview reloads.
+#[tauri::command]
+fn get_session_phase(state: State<'_, TranscriptionState>) -> Result<SessionPhase, AppError> {
+    Ok(state.session.lock().map_err(|e| e.to_string())?.phase())
+}
+
 #[tauri::command]
 async fn pause_transcription(
     app: tauri::AppHandle,
     state: State<'_, TranscriptionState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     {
-        let mut paused = state.is_paused.lock().map_err(|e| e.to_string())?;
-        *paused = true;
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        set_phase(&app, &mut session, SessionPhase::Paused)?;
+        session.send(SessionCommand::Pause)?;
     }
 
-    app.emit(
+    let _ = app.emit(
         "asr-event",
         ASREvent::Status {
             state: "paused".to_string(),
             message: "Transcription paused".to_string(),
+            lag: None,
+            language: None,
         },
-    )
-    .map_err(|e| e.to_string())?;
+    );
 
     Ok(())
 }
@@ -695,22 +2155,26 @@ async fn pause_transcription(
 async fn resume_transcription(
     app: tauri::AppHandle,
     state: State<'_, TranscriptionState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     {
-        let mut paused = state.is_paused.lock().map_err(|e| e.to_string())?;
-        *paused = false;
+        let mut session = state.session.lock().map_err(|e| e.to_string())?;
+        set_phase(&app, &mut session, SessionPhase::Listening)?;
+        session.send(SessionCommand::Resume)?;
     }
 
-    show_quick_note_window(&app)?;
+    if let Err(error) = show_quick_note_window(&app) {
+        log::warn!("Failed to show the quick note window: {}", error);
+    }
 
-    app.emit(
+    let _ = app.emit(
         "asr-event",
         ASREvent::Status {
             state: "listening".to_string(),
             message: "Transcription resumed".to_string(),
+            lag: None,
+            language: None,
         },
-    )
-    .map_err(|e| e.to_string())?;
+    );
 
     Ok(())
 }
@@ -720,7 +2184,7 @@ async fn trigger_meeting_detected_notification(
     app: tauri::AppHandle,
     meeting_app: Option<String>,
     meeting_session_id: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let subtitle = meeting_app.unwrap_or_else(|| "Online meeting".to_string());
 
     let route = format!(
@@ -745,7 +2209,7 @@ async fn trigger_meeting_detected_notification(
 }
 
 #[tauri::command]
-fn open_meeting_capture(app: tauri::AppHandle, meeting_session_id: String) -> Result<(), String> {
+fn open_meeting_capture(app: tauri::AppHandle, meeting_session_id: String) -> Result<(), AppError> {
     let route = format!(
         "/quick-note?meetingSessionId={}&autostart=1",
         meeting_session_id
@@ -769,8 +2233,139 @@ fn dismiss_meeting_alert(app: tauri::AppHandle) {
 
 #[tauri::command]
 fn get_mic_level(state: State<'_, TranscriptionState>) -> f32 {
-    let audio = state.audio.lock().unwrap();
-    audio.get_level()
+    state
+        .audio
+        .lock()
+        .map(|audio| audio.get_level())
+        .unwrap_or(0.0)
+}
+
+/// How much speaker echo is being removed from the microphone, or `None`
+/// when echo cancellation is not running.
+#[tauri::command]
+fn get_echo_metrics(state: State<'_, TranscriptionState>) -> Option<EchoMetrics> {
+    state.echo_metrics.lock().ok().and_then(|metrics| *metrics)
+}
+
+/// Recordings kept for a meeting session, oldest first.
+#[tauri::command]
+fn list_recordings(
+    app: tauri::AppHandle,
+    meeting_session_id: String,
+) -> Result<Vec<recording::RecordedRun>, AppError> {
+    Ok(recording::list_recordings(&app, &meeting_session_id)?)
+}
+
+/// Journaled sessions that never stopped normally, most recent first.
+/// Starting one of them again with the same `journal` option resumes it.
+#[tauri::command]
+fn list_unfinished_sessions(app: tauri::AppHandle) -> Vec<journal::UnfinishedSession> {
+    journal::unfinished_sessions(&app)
+}
+
+/// ASR_FINAL events journaled for a session, in emission order.
+#[tauri::command]
+fn get_session_journal(
+    app: tauri::AppHandle,
+    meeting_session_id: String,
+) -> Result<Vec<serde_json::Value>, AppError> {
+    Ok(journal::journaled_finals(&app, &meeting_session_id)?)
+}
+
+#[tauri::command]
+fn discard_session_journal(
+    app: tauri::AppHandle,
+    meeting_session_id: String,
+) -> Result<(), AppError> {
+    Ok(journal::discard(&app, &meeting_session_id)?)
+}
+
+#[tauri::command]
+fn list_audio_devices() -> Vec<devices::AudioDeviceInfo> {
+    devices::list_devices()
+}
+
+#[tauri::command]
+fn get_audio_device_selection(app: tauri::AppHandle) -> devices::DeviceSelection {
+    devices::load_selection(&app)
+}
+
+/// Save the microphone and loopback devices by ID; `None` follows the system
+/// default. Takes effect the next time transcription starts.
+#[tauri::command]
+fn set_audio_devices(
+    app: tauri::AppHandle,
+    microphone_id: Option<String>,
+    loopback_id: Option<String>,
+) -> Result<devices::DeviceSelection, AppError> {
+    let host = cpal::default_host();
+    for id in [&microphone_id, &loopback_id].into_iter().flatten() {
+        if !devices::device_exists(&host, id) {
+            return Err(AppError::DeviceUnavailable(format!(
+                "Unknown audio device: {}",
+                id
+            )));
+        }
+    }
+
+    let selection = devices::DeviceSelection {
+        microphone_id,
+        loopback_id,
+    };
+    devices::save_selection(&app, &selection)?;
+    Ok(selection)
+}
+
+/// The machine's cores, memory and CPU features, the thread count and model
+/// size chosen from them, and the last session's model and calibration.
+#[tauri::command]
+fn get_hardware_profile(state: State<'_, TranscriptionState>) -> hardware::HardwareProfile {
+    state
+        .profile
+        .lock()
+        .ok()
+        .and_then(|profile| profile.clone())
+        .unwrap_or_else(hardware::HardwareProfile::detect)
+}
+
+/// Downloaded and bundled whisper models.
+#[tauri::command]
+fn list_installed_models(app: tauri::AppHandle) -> Vec<models::InstalledModel> {
+    models::installed_models(&app)
+}
+
+/// Whisper models that can be downloaded, smallest first.
+#[tauri::command]
+async fn list_available_models(
+    app: tauri::AppHandle,
+) -> Result<Vec<models::AvailableModel>, AppError> {
+    models::available_models(&app).await
+}
+
+/// Download and verify a model, resuming an interrupted download. Progress
+/// is reported as "model-download" events.
+#[tauri::command]
+async fn download_model(
+    app: tauri::AppHandle,
+    state: State<'_, models::ModelDownloads>,
+    model_id: String,
+) -> Result<models::InstalledModel, AppError> {
+    models::download_model(&app, &state, &model_id).await
+}
+
+#[tauri::command]
+fn delete_model(
+    app: tauri::AppHandle,
+    state: State<'_, models::ModelDownloads>,
+    model_id: String,
+) -> Result<(), AppError> {
+    models::delete(&app, &state, &model_id)
+}
+
+/// Use an installed model when `start_transcription` gets no `model_path`.
+#[tauri::command]
+fn set_default_model(app: tauri::AppHandle, model_id: String) -> Result<(), AppError> {
+    models::set_default(&app, &model_id)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -782,10 +2377,12 @@ pub fn run() {
         .plugin(tauri_plugin_notification::init())
         .manage(TranscriptionState {
             audio: Mutex::new(AudioCapture::new()),
-            whisper: Mutex::new(None),
-            is_recording: Mutex::new(false),
-            is_paused: Mutex::new(false),
+            session: Mutex::new(SessionController::new()),
+            echo_metrics: Mutex::new(None),
+            journal: Mutex::new(None),
+            profile: Mutex::new(None),
         })
+        .manage(models::ModelDownloads::default())
         .manage(MeetingDetectorState {
             active_provider_pids: Mutex::new(HashMap::new()),
             active_meeting_providers: Mutex::new(HashSet::new()),
@@ -801,6 +2398,8 @@ pub fn run() {
                 )?;
             }
 
+            start_recording_retention(app.handle().clone());
+            whisper::sweep_temp_files(app.handle());
             start_windows_meeting_detector(app.handle().clone());
             Ok(())
         })
@@ -809,10 +2408,25 @@ pub fn run() {
             stop_transcription,
             pause_transcription,
             resume_transcription,
+            get_session_phase,
             trigger_meeting_detected_notification,
             open_meeting_capture,
             dismiss_meeting_alert,
             get_mic_level,
+            get_echo_metrics,
+            list_recordings,
+            list_unfinished_sessions,
+            get_session_journal,
+            discard_session_journal,
+            list_audio_devices,
+            get_audio_device_selection,
+            set_audio_devices,
+            get_hardware_profile,
+            list_installed_models,
+            list_available_models,
+            download_model,
+            delete_model,
+            set_default_model,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
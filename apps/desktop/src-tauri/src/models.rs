@@ -0,0 +1,890 @@
+//! Whisper ggml models: what is installed, what can be downloaded, and which
+//! one transcription uses by default.
+//!
+//! Downloaded models live in `<app data>/models/ggml-<id>.bin`; models
+//! bundled with the app under `<resources>/models/` are listed too but cannot
+//! be deleted. A download is written to `<file>.part` and resumed from there
+//! with a `Range` request, then checked against the SHA-256 the model
+//! repository publishes for the file before it is renamed into place.
+
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+
+const MODELS_DIR: &str = "models";
+const SETTINGS_FILE: &str = "models.json";
+const PART_SUFFIX: &str = ".part";
+/// Minimum time between progress events of one download.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where models are listed and downloaded from: a Hugging Face style
+/// repository. Tests point it at a local server.
+#[derive(Debug, Clone)]
+pub struct ModelSource {
+    pub base_url: String,
+    pub repository: String,
+}
+
+impl Default for ModelSource {
+    fn default() -> Self {
+        Self {
+            base_url: "https://huggingface.co".to_string(),
+            repository: "ggerganov/whisper.cpp".to_string(),
+        }
+    }
+}
+
+impl ModelSource {
+    fn catalog_url(&self) -> String {
+        format!("{}/api/models/{}/tree/main", self.base_url, self.repository)
+    }
+
+    fn file_url(&self, file: &str) -> String {
+        format!(
+            "{}/{}/resolve/main/{}",
+            self.base_url, self.repository, file
+        )
+    }
+}
+
+/// A file in the repository listing; only LFS files carry a checksum.
+#[derive(Debug, Deserialize)]
+struct CatalogEntry {
+    path: String,
+    #[serde(default)]
+    lfs: Option<LfsInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsInfo {
+    /// SHA-256 of the file contents, hex encoded.
+    oid: String,
+    size: u64,
+}
+
+/// A model that can be downloaded, for `list_available_models`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableModel {
+    /// `small`, `base.en`, `large-v3-q5_0`, ...; the file is `ggml-<id>.bin`.
+    pub id: String,
+    pub file: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub installed: bool,
+    /// Bytes of an interrupted download that the next one resumes from.
+    pub downloaded_bytes: u64,
+}
+
+/// A model on disk, for `list_installed_models`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledModel {
+    pub id: String,
+    pub path: String,
+    pub size_bytes: u64,
+    /// Shipped with the app rather than downloaded; cannot be deleted.
+    pub bundled: bool,
+    pub is_default: bool,
+}
+
+/// Payload of "model-download" events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelDownloadEvent {
+    pub model_id: String,
+    /// "downloading", "verifying", "completed" or "failed".
+    pub state: &'static str,
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<AppError>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelSettings {
+    default_model: Option<String>,
+}
+
+/// Models being downloaded, so the same model is not fetched twice at once
+/// or deleted mid-download.
+#[derive(Default)]
+pub struct ModelDownloads {
+    active: Mutex<HashSet<String>>,
+}
+
+/// Marks a model as downloading until dropped.
+struct DownloadGuard<'a> {
+    downloads: &'a ModelDownloads,
+    model_id: String,
+}
+
+impl Drop for DownloadGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut active) = self.downloads.active.lock() {
+            active.remove(&self.model_id);
+        }
+    }
+}
+
+impl ModelDownloads {
+    fn begin(&self, model_id: &str) -> Result<DownloadGuard<'_>, AppError> {
+        let mut active = self.active.lock().map_err(|e| e.to_string())?;
+        if !active.insert(model_id.to_string()) {
+            return Err(AppError::InvalidState(format!(
+                "Model {} is already downloading",
+                model_id
+            )));
+        }
+        Ok(DownloadGuard {
+            downloads: self,
+            model_id: model_id.to_string(),
+        })
+    }
+
+    fn is_active(&self, model_id: &str) -> bool {
+        self.active
+            .lock()
+            .map(|active| active.contains(model_id))
+            .unwrap_or(false)
+    }
+}
+
+/// `ggml-<id>.bin` -> `<id>`.
+fn model_id(file: &str) -> Option<&str> {
+    file.strip_prefix("ggml-")?
+        .strip_suffix(".bin")
+        .filter(|id| !id.is_empty())
+}
+
+fn model_file(id: &str) -> String {
+    format!("ggml-{}.bin", id)
+}
+
+/// Model IDs name a file in the models directory; anything that could
+/// reach outside it is refused.
+fn check_model_id(id: &str) -> Result<(), AppError> {
+    let valid = !id.is_empty()
+        && id != "."
+        && id != ".."
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidInput(format!("Invalid model ID: {}", id)))
+    }
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(PART_SUFFIX);
+    PathBuf::from(name)
+}
+
+fn models_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(MODELS_DIR))
+        .map_err(|e| e.to_string())
+}
+
+fn bundled_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .resource_dir()
+        .ok()
+        .map(|dir| dir.join(MODELS_DIR))
+}
+
+/// `(id, path, size)` of every model file in `dir`, sorted by id.
+fn scan_dir(dir: &Path) -> Vec<(String, PathBuf, u64)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut models: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let id = model_id(&name)?.to_string();
+            let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((id, entry.path(), metadata.len()))
+        })
+        .collect();
+    models.sort_by(|a, b| a.0.cmp(&b.0));
+    models
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(SETTINGS_FILE))
+        .map_err(|e| e.to_string())
+}
+
+fn load_settings(app: &AppHandle) -> ModelSettings {
+    let Ok(path) = settings_path(app) else {
+        return ModelSettings::default();
+    };
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+            log::warn!("Ignoring unreadable {}: {}", path.display(), error);
+            ModelSettings::default()
+        }),
+        Err(_) => ModelSettings::default(),
+    }
+}
+
+fn save_settings(app: &AppHandle, settings: &ModelSettings) -> Result<(), String> {
+    let path = settings_path(app)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+/// Downloaded models, then bundled ones not shadowed by a download.
+pub fn installed_models(app: &AppHandle) -> Vec<InstalledModel> {
+    let default_model = load_settings(app).default_model;
+    let downloaded = models_dir(app)
+        .map(|dir| scan_dir(&dir))
+        .unwrap_or_default();
+    let bundled = bundled_dir(app)
+        .map(|dir| scan_dir(&dir))
+        .unwrap_or_default();
+
+    let mut models: Vec<InstalledModel> = Vec::new();
+    for (is_bundled, found) in [(false, downloaded), (true, bundled)] {
+        for (id, path, size_bytes) in found {
+            if models.iter().any(|model| model.id == id) {
+                continue;
+            }
+            models.push(InstalledModel {
+                is_default: default_model.as_deref() == Some(id.as_str()),
+                id,
+                path: path.to_string_lossy().into_owned(),
+                size_bytes,
+                bundled: is_bundled,
+            });
+        }
+    }
+    models
+}
+
+/// Position of a model id's size in `asr::MODEL_SIZES`; `small.en` and
+/// `small-q5_1` rank as `small`.
+fn size_rank(id: &str) -> Option<usize> {
+    let size = id.split(['.', '-']).next()?;
+    crate::asr::MODEL_SIZES.iter().position(|s| *s == size)
+}
+
+/// Path of the model for sessions started without a `model_path`: the
+/// default model, or without one the installed model closest to
+/// `recommended` (a size from the hardware profile) without exceeding it.
+pub fn default_model_path(app: &AppHandle, recommended: &str) -> Result<String, AppError> {
+    let installed = installed_models(app);
+    if let Some(id) = load_settings(app).default_model {
+        return installed
+            .into_iter()
+            .find(|model| model.id == id)
+            .map(|model| model.path)
+            .ok_or_else(|| {
+                AppError::ModelMissing(format!("The default whisper model {} is not installed", id))
+            });
+    }
+
+    let limit = size_rank(recommended).unwrap_or(0);
+    let ranked = installed
+        .into_iter()
+        .filter_map(|model| Some((size_rank(&model.id)?, model)));
+    // Prefer the largest size within the limit, then the plain multilingual
+    // model of that size; without any, the smallest installed.
+    let within = ranked
+        .clone()
+        .filter(|(rank, _)| *rank <= limit)
+        .max_by_key(|(rank, model)| (*rank, model.id == crate::asr::MODEL_SIZES[*rank]));
+    within
+        .or_else(|| ranked.min_by_key(|(rank, _)| *rank))
+        .map(|(_, model)| {
+            log::info!(
+                "No default model set; using {} (recommended {})",
+                model.id,
+                recommended
+            );
+            model.path
+        })
+        .ok_or_else(|| AppError::ModelMissing("No whisper model is installed".to_string()))
+}
+
+/// Whether the user has chosen a default model.
+pub fn has_default(app: &AppHandle) -> bool {
+    load_settings(app).default_model.is_some()
+}
+
+/// Make an installed model the default.
+pub fn set_default(app: &AppHandle, id: &str) -> Result<(), AppError> {
+    check_model_id(id)?;
+    if !installed_models(app).iter().any(|model| model.id == id) {
+        return Err(AppError::ModelMissing(format!(
+            "Whisper model {} is not installed",
+            id
+        )));
+    }
+    let mut settings = load_settings(app);
+    settings.default_model = Some(id.to_string());
+    Ok(save_settings(app, &settings)?)
+}
+
+/// Delete a downloaded model and any partial download of it. Deleting the
+/// default model clears the default.
+pub fn delete(app: &AppHandle, downloads: &ModelDownloads, id: &str) -> Result<(), AppError> {
+    check_model_id(id)?;
+    if downloads.is_active(id) {
+        return Err(AppError::InvalidState(format!(
+            "Model {} is still downloading",
+            id
+        )));
+    }
+    let path = models_dir(app)?.join(model_file(id));
+    let part = part_path(&path);
+    if !path.exists() && !part.exists() {
+        let bundled = bundled_dir(app).is_some_and(|dir| dir.join(model_file(id)).is_file());
+        return Err(if bundled {
+            AppError::InvalidInput(format!("Bundled model {} cannot be deleted", id))
+        } else {
+            AppError::ModelMissing(format!("Whisper model {} is not installed", id))
+        });
+    }
+    for file in [&path, &part] {
+        match std::fs::remove_file(file) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(AppError::Internal(format!(
+                    "Failed to delete {}: {}",
+                    file.display(),
+                    error
+                )))
+            }
+        }
+    }
+
+    let mut settings = load_settings(app);
+    if settings.default_model.as_deref() == Some(id) {
+        settings.default_model = None;
+        save_settings(app, &settings)?;
+    }
+    log::info!("Deleted whisper model {}", id);
+    Ok(())
+}
+
+fn client() -> Result<reqwest::Client, AppError> {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(15))
+        .read_timeout(Duration::from_secs(60))
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {}", e)))
+}
+
+fn download_error(context: &str, error: reqwest::Error) -> AppError {
+    if error.is_timeout() {
+        AppError::Timeout(format!("{}: {}", context, error))
+    } else {
+        AppError::DownloadFailed(format!("{}: {}", context, error))
+    }
+}
+
+/// ggml models in the repository, smallest first, marked installed or
+/// partially downloaded against `dir`.
+pub async fn fetch_catalog(
+    client: &reqwest::Client,
+    source: &ModelSource,
+    dir: &Path,
+) -> Result<Vec<AvailableModel>, AppError> {
+    let context = "Failed to list whisper models";
+    let entries: Vec<CatalogEntry> = client
+        .get(source.catalog_url())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| download_error(context, e))?
+        .json()
+        .await
+        .map_err(|e| download_error(context, e))?;
+
+    let mut models: Vec<AvailableModel> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let id = model_id(&entry.path)?.to_string();
+            // The listing is remote; only IDs safe to use as a file name
+            // make it into the catalog.
+            check_model_id(&id).ok()?;
+            let lfs = entry.lfs?;
+            let path = dir.join(model_file(&id));
+            Some(AvailableModel {
+                id,
+                installed: path.is_file(),
+                downloaded_bytes: std::fs::metadata(part_path(&path))
+                    .map(|m| m.len())
+                    .unwrap_or(0),
+                file: entry.path,
+                size_bytes: lfs.size,
+                sha256: lfs.oid.to_lowercase(),
+            })
+        })
+        .collect();
+    models.sort_by(|a, b| a.size_bytes.cmp(&b.size_bytes).then(a.id.cmp(&b.id)));
+    Ok(models)
+}
+
+/// Feed the first `len` bytes of `path` to `hasher`.
+fn hash_prefix(hasher: &mut Sha256, path: &Path, len: u64) -> Result<(), String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut reader = file.take(len);
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if read == 0 {
+            return Ok(());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Run file work on a blocking thread so the download does not stall the
+/// runtime.
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, AppError> {
+    tauri::async_runtime::spawn_blocking(work)
+        .await
+        .map_err(|e| AppError::Internal(format!("File task failed: {}", e)))
+}
+
+/// `hash_prefix` on a blocking thread.
+async fn hash_prefix_blocking(hasher: Sha256, path: &Path, len: u64) -> Result<Sha256, AppError> {
+    let path = path.to_path_buf();
+    let hashed = blocking(move || {
+        let mut hasher = hasher;
+        hash_prefix(&mut hasher, &path, len).map(|_| hasher)
+    })
+    .await?;
+    Ok(hashed?)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Download `model` into `dir`, resuming a previous partial download, and
+/// return the verified file. `on_progress` gets `(downloaded, total)` bytes.
+///
+/// A `.part` file is kept when the transfer is interrupted so the next call
+/// resumes it, and removed when the finished file fails verification.
+pub async fn download(
+    client: &reqwest::Client,
+    source: &ModelSource,
+    model: &AvailableModel,
+    dir: &Path,
+    mut on_progress: impl FnMut(u64, u64),
+) -> Result<PathBuf, AppError> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    check_model_id(&model.id)?;
+    let path = dir.join(model_file(&model.id));
+    let part = part_path(&path);
+    let total = model.size_bytes;
+
+    let mut downloaded = std::fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+    if downloaded > total {
+        downloaded = 0;
+    }
+
+    let mut hasher = Sha256::new();
+    if downloaded < total {
+        let mut request = client.get(source.file_url(&model.file));
+        if downloaded > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", downloaded));
+        }
+        let context = format!("Failed to download {}", model.file);
+        let mut response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| download_error(&context, e))?;
+
+        // Servers that ignore the range send the whole file again.
+        let resumed = downloaded > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if resumed {
+            log::info!("Resuming {} from byte {}", model.file, downloaded);
+            hasher = hash_prefix_blocking(hasher, &part, downloaded).await?;
+        } else {
+            downloaded = 0;
+        }
+        let mut file = if resumed {
+            std::fs::OpenOptions::new().append(true).open(&part)
+        } else {
+            std::fs::File::create(&part)
+        }
+        .map_err(|e| format!("Failed to open {}: {}", part.display(), e))?;
+
+        on_progress(downloaded, total);
+        let mut last_progress = Instant::now();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| download_error(&context, e))?
+        {
+            if downloaded + chunk.len() as u64 > total {
+                drop(file);
+                let _ = std::fs::remove_file(&part);
+                return Err(AppError::DownloadFailed(format!(
+                    "{} is larger than the expected {} bytes",
+                    model.file, total
+                )));
+            }
+            let written;
+            (file, written) = blocking(move || {
+                let written = file.write_all(&chunk).map(|_| chunk);
+                (file, written)
+            })
+            .await?;
+            let chunk =
+                written.map_err(|e| format!("Failed to write {}: {}", part.display(), e))?;
+            hasher.update(&chunk);
+            downloaded += chunk.len() as u64;
+            if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                on_progress(downloaded, total);
+                last_progress = Instant::now();
+            }
+        }
+        blocking(move || file.flush().and_then(|_| file.sync_all()))
+            .await?
+            .map_err(|e| format!("Failed to write {}: {}", part.display(), e))?;
+        if downloaded < total {
+            return Err(AppError::DownloadFailed(format!(
+                "Download of {} stopped at {} of {} bytes; download it again to resume",
+                model.file, downloaded, total
+            )));
+        }
+    } else {
+        hasher = hash_prefix_blocking(hasher, &part, total).await?;
+    }
+    on_progress(downloaded, total);
+
+    let digest = to_hex(&hasher.finalize());
+    if digest != model.sha256 {
+        let _ = std::fs::remove_file(&part);
+        return Err(AppError::DownloadFailed(format!(
+            "Checksum mismatch for {} (expected {}, got {}); the download was discarded",
+            model.file, model.sha256, digest
+        )));
+    }
+    std::fs::rename(&part, &path)
+        .map_err(|e| format!("Failed to move {} into place: {}", path.display(), e))?;
+    Ok(path)
+}
+
+fn emit_download(app: &AppHandle, event: ModelDownloadEvent) {
+    let _ = app.emit("model-download", event);
+}
+
+/// Models the repository offers, marked installed against the app's models
+/// directory.
+pub async fn available_models(app: &AppHandle) -> Result<Vec<AvailableModel>, AppError> {
+    let dir = models_dir(app)?;
+    fetch_catalog(&client()?, &ModelSource::default(), &dir).await
+}
+
+/// Download and verify model `id`, reporting progress as "model-download"
+/// events. Returns the installed model.
+pub async fn download_model(
+    app: &AppHandle,
+    downloads: &ModelDownloads,
+    id: &str,
+) -> Result<InstalledModel, AppError> {
+    check_model_id(id)?;
+    let _guard = downloads.begin(id)?;
+    let client = client()?;
+    let source = ModelSource::default();
+    let dir = models_dir(app)?;
+
+    let result = async {
+        let model = fetch_catalog(&client, &source, &dir)
+            .await?
+            .into_iter()
+            .find(|model| model.id == id)
+            .ok_or_else(|| AppError::InvalidInput(format!("Unknown whisper model: {}", id)))?;
+        let total = model.size_bytes;
+        download(&client, &source, &model, &dir, |downloaded, total| {
+            let state = if downloaded < total {
+                "downloading"
+            } else {
+                "verifying"
+            };
+            emit_download(
+                app,
+                ModelDownloadEvent {
+                    model_id: id.to_string(),
+                    state,
+                    downloaded_bytes: downloaded,
+                    total_bytes: total,
+                    error: None,
+                },
+            );
+        })
+        .await?;
+        Ok::<_, AppError>(total)
+    }
+    .await;
+
+    match result {
+        Ok(total) => {
+            log::info!("Downloaded whisper model {}", id);
+            emit_download(
+                app,
+                ModelDownloadEvent {
+                    model_id: id.to_string(),
+                    state: "completed",
+                    downloaded_bytes: total,
+                    total_bytes: total,
+                    error: None,
+                },
+            );
+            installed_models(app)
+                .into_iter()
+                .find(|model| model.id == id)
+                .ok_or_else(|| AppError::Internal(format!("Model {} vanished after download", id)))
+        }
+        Err(error) => {
+            log::warn!("Downloading whisper model {} failed: {}", id, error);
+            emit_download(
+                app,
+                ModelDownloadEvent {
+                    model_id: id.to_string(),
+                    state: "failed",
+                    downloaded_bytes: 0,
+                    total_bytes: 0,
+                    error: Some(error.clone()),
+                },
+            );
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// Minimal stand-in for the model repository: serves the listing and one
+    /// model file, honouring `Range` requests unless told not to, and
+    /// records the `Range` header of every file request.
+    struct StandIn {
+        source: ModelSource,
+        ranges: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl StandIn {
+        fn start(model: Vec<u8>, sha256: String, honour_range: bool) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let ranges = Arc::new(Mutex::new(Vec::new()));
+            let seen = ranges.clone();
+            let catalog = serde_json::json!([
+                { "type": "file", "path": "README.md", "size": 10 },
+                {
+                    "type": "file",
+                    "path": "ggml-tiny.bin",
+                    "size": 134,
+                    "lfs": { "oid": sha256, "size": model.len(), "pointerSize": 134 }
+                },
+                {
+                    "type": "file",
+                    "path": "ggml-../../escape.bin",
+                    "size": 134,
+                    "lfs": { "oid": sha256, "size": model.len(), "pointerSize": 134 }
+                },
+                { "type": "directory", "path": "ggml-base.bin.d" }
+            ])
+            .to_string();
+
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { continue };
+                    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let mut range = None;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("range") {
+                                range = Some(value.trim().to_string());
+                            }
+                        }
+                    }
+
+                    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                    let (status, body): (&str, &[u8]) =
+                        if path == "/api/models/test/whisper.cpp/tree/main" {
+                            ("200 OK", catalog.as_bytes())
+                        } else if path == "/test/whisper.cpp/resolve/main/ggml-tiny.bin" {
+                            seen.lock().unwrap().push(range.clone());
+                            let start = range
+                                .as_deref()
+                                .filter(|_| honour_range)
+                                .and_then(|r| r.strip_prefix("bytes="))
+                                .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok());
+                            match start {
+                                Some(start) => ("206 Partial Content", &model[start..]),
+                                None => ("200 OK", &model[..]),
+                            }
+                        } else {
+                            ("404 Not Found", b"")
+                        };
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    let _ = stream.write_all(body);
+                }
+            });
+
+            Self {
+                source: ModelSource {
+                    base_url: format!("http://127.0.0.1:{}", port),
+                    repository: "test/whisper.cpp".to_string(),
+                },
+                ranges,
+            }
+        }
+
+        fn ranges(&self) -> Vec<Option<String>> {
+            self.ranges.lock().unwrap().clone()
+        }
+    }
+
+    fn model_bytes() -> Vec<u8> {
+        (0..200_000u32).map(|n| (n * 31 % 251) as u8).collect()
+    }
+
+    fn sha256(bytes: &[u8]) -> String {
+        to_hex(&Sha256::digest(bytes))
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("models-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// List the stand-in's models and download the tiny one into `dir`.
+    fn fetch_and_download(
+        stand_in: &StandIn,
+        dir: &Path,
+    ) -> (AvailableModel, Result<PathBuf, AppError>, Vec<(u64, u64)>) {
+        tauri::async_runtime::block_on(async {
+            let client = client().unwrap();
+            let catalog = fetch_catalog(&client, &stand_in.source, dir).await.unwrap();
+            assert_eq!(catalog.len(), 1);
+            let model = catalog[0].clone();
+            let mut progress = Vec::new();
+            let result = download(&client, &stand_in.source, &model, dir, |done, total| {
+                progress.push((done, total))
+            })
+            .await;
+            (model, result, progress)
+        })
+    }
+
+    #[test]
+    fn downloads_and_verifies_model() {
+        let bytes = model_bytes();
+        let stand_in = StandIn::start(bytes.clone(), sha256(&bytes), true);
+        let dir = temp_dir("fresh");
+
+        let (model, result, progress) = fetch_and_download(&stand_in, &dir);
+        assert_eq!(model.id, "tiny");
+        assert_eq!(model.size_bytes, bytes.len() as u64);
+        assert!(!model.installed);
+        let path = result.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        assert!(!part_path(&path).exists());
+        assert_eq!(
+            progress.last(),
+            Some(&(bytes.len() as u64, bytes.len() as u64))
+        );
+        assert_eq!(stand_in.ranges(), vec![None]);
+        assert_eq!(scan_dir(&dir).len(), 1);
+    }
+
+    #[test]
+    fn resumes_partial_download() {
+        let bytes = model_bytes();
+        let stand_in = StandIn::start(bytes.clone(), sha256(&bytes), true);
+        let dir = temp_dir("resume");
+        std::fs::write(part_path(&dir.join("ggml-tiny.bin")), &bytes[..70_000]).unwrap();
+
+        let (model, result, progress) = fetch_and_download(&stand_in, &dir);
+        assert_eq!(model.downloaded_bytes, 70_000);
+        assert_eq!(std::fs::read(result.unwrap()).unwrap(), bytes);
+        assert_eq!(progress.first(), Some(&(70_000, bytes.len() as u64)));
+        assert_eq!(stand_in.ranges(), vec![Some("bytes=70000-".to_string())]);
+    }
+
+    #[test]
+    fn restarts_when_range_is_ignored() {
+        let bytes = model_bytes();
+        let stand_in = StandIn::start(bytes.clone(), sha256(&bytes), false);
+        let dir = temp_dir("no-range");
+        std::fs::write(part_path(&dir.join("ggml-tiny.bin")), &bytes[..70_000]).unwrap();
+
+        let (_, result, _) = fetch_and_download(&stand_in, &dir);
+        assert_eq!(std::fs::read(result.unwrap()).unwrap(), bytes);
+    }
+
+    #[test]
+    fn discards_download_with_wrong_checksum() {
+        let bytes = model_bytes();
+        let stand_in = StandIn::start(bytes.clone(), sha256(b"something else"), true);
+        let dir = temp_dir("mismatch");
+
+        let (_, result, _) = fetch_and_download(&stand_in, &dir);
+        assert_eq!(result.unwrap_err().code(), "downloadFailed");
+        assert!(scan_dir(&dir).is_empty());
+        assert!(!part_path(&dir.join("ggml-tiny.bin")).exists());
+    }
+
+    #[test]
+    fn model_ids_stay_inside_the_models_directory() {
+        for id in ["tiny", "base.en", "large-v3-turbo", "small.en-q5_1"] {
+            assert_eq!(check_model_id(id), Ok(()));
+        }
+        for id in ["", ".", "..", "../tiny", "sub/tiny", "C:\\tiny", "tiny bin"] {
+            assert_eq!(check_model_id(id).unwrap_err().code(), "invalidInput");
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! PulseAudio monitor sources, used as the system-audio loopback on Linux
+//! where ALSA exposes no way to record an output. PipeWire serves the same
+//! interface through pipewire-pulse.
+
+use std::io::ErrorKind;
+use std::process::{Child, Command, Stdio};
+
+const PULSE_TOOLS_HINT: &str =
+    "install pulseaudio-utils (PulseAudio) or pipewire-pulse (PipeWire) to capture system audio";
+
+fn pactl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("pactl")
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                format!("pactl was not found; {}", PULSE_TOOLS_HINT)
+            } else {
+                format!("Failed to run pactl: {}", e)
+            }
+        })?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "pactl {} failed (is a PulseAudio or PipeWire server running?): {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Names of all monitor sources, one per output sink.
+pub fn list_monitor_sources() -> Result<Vec<String>, String> {
+    // Columns: index, name, driver, sample spec, state.
+    Ok(pactl(&["list", "short", "sources"])?
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .filter(|name| name.ends_with(".monitor"))
+        .map(str::to_string)
+        .collect())
+}
+
+fn default_sink() -> Result<String, String> {
+    if let Ok(sink) = pactl(&["get-default-sink"]) {
+        let sink = sink.trim();
+        if !sink.is_empty() {
+            return Ok(sink.to_string());
+        }
+    }
+
+    // pactl before 15.0 has no get-default-sink.
+    pactl(&["info"])?
+        .lines()
+        .find_map(|line| line.strip_prefix("Default Sink:"))
+        .map(|sink| sink.trim().to_string())
+        .filter(|sink| !sink.is_empty())
+        .ok_or_else(|| "No default audio output is configured".to_string())
+}
+
+/// Monitor source of the sink currently playing system audio.
+pub fn default_monitor_source() -> Result<String, String> {
+    let sink = default_sink()?;
+    let monitor = format!("{}.monitor", sink);
+    if list_monitor_sources()?.contains(&monitor) {
+        Ok(monitor)
+    } else {
+        Err(format!(
+            "The default output {} has no monitor source, so system audio cannot be captured",
+            sink
+        ))
+    }
+}
+
+/// Start recording `source` as raw 16kHz mono s16le on the child's stdout.
+pub fn spawn_recorder(source: &str) -> Result<Child, String> {
+    Command::new("parec")
+        .args([
+            "--device",
+            source,
+            "--format=s16le",
+            "--rate=16000",
+            "--channels=1",
+            "--raw",
+            "--latency-msec=50",
+            "--client-name=AINotes",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                format!("parec was not found; {}", PULSE_TOOLS_HINT)
+            } else {
+                format!("Failed to start parec for {}: {}", source, e)
+            }
+        })
+}
@@ -0,0 +1,304 @@
+use crate::audio::{Utterance, VadConfig};
+use std::time::Duration;
+
+const SAMPLE_RATE: i64 = 16_000;
+/// Most audio allowed to wait for the ASR. Newer audio beyond it is dropped,
+/// as the capture rings do when they fill, so what is kept stays contiguous.
+pub const MAX_QUEUED_AUDIO_MS: i64 = 20_000;
+/// Smoothed real-time factor above which the pipeline counts as lagging.
+const LAGGING_RTF: f64 = 1.0;
+/// Smoothed real-time factor below which a reduced pipeline is restored.
+const RECOVERED_RTF: f64 = 0.6;
+/// Decodes observed at a stage before it may change again.
+const MIN_DECODES_PER_STAGE: u32 = 4;
+/// Weight of the newest decode in the smoothed real-time factor.
+const RTF_SMOOTHING: f64 = 0.3;
+/// Window limit, for both VAD utterances and the streaming window, while
+/// reduced.
+pub const REDUCED_WINDOW_MS: i64 = 6_000;
+
+/// How aggressively the pipeline is trading accuracy for latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PacingStage {
+    Normal,
+    /// Shorter windows and less silence around each utterance.
+    Reduced,
+    /// Reduced, and decoding with the next smaller model on disk.
+    SmallerModel,
+}
+
+impl PacingStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PacingStage::Normal => "normal",
+            PacingStage::Reduced => "reducedWindows",
+            PacingStage::SmallerModel => "smallerModel",
+        }
+    }
+
+    /// VAD tuning for this stage.
+    pub fn vad_config(self) -> VadConfig {
+        match self {
+            PacingStage::Normal => VadConfig::default(),
+            PacingStage::Reduced | PacingStage::SmallerModel => VadConfig {
+                max_utterance_ms: REDUCED_WINDOW_MS as usize,
+                end_silence_ms: 400,
+                pre_roll_ms: 90,
+                ..VadConfig::default()
+            },
+        }
+    }
+}
+
+/// Tracks how fast the ASR runs relative to real time and decides when the
+/// pipeline should shed load.
+pub struct PacingController {
+    /// Exponentially smoothed decode time / audio duration.
+    rtf: Option<f64>,
+    stage: PacingStage,
+    decodes_at_stage: u32,
+    /// Whether the last decision reported lag to the user.
+    lagging: bool,
+    /// Set once no smaller model is available, so it is not retried.
+    smallest_model: bool,
+}
+
+impl PacingController {
+    pub fn new() -> Self {
+        Self {
+            rtf: None,
+            stage: PacingStage::Normal,
+            decodes_at_stage: 0,
+            lagging: false,
+            smallest_model: false,
+        }
+    }
+
+    pub fn stage(&self) -> PacingStage {
+        self.stage
+    }
+
+    pub fn realtime_factor(&self) -> f64 {
+        self.rtf.unwrap_or(0.0)
+    }
+
+    /// Record one decode of `samples` 16kHz samples that took `elapsed`.
+    pub fn record(&mut self, samples: usize, elapsed: Duration) {
+        if samples == 0 {
+            return;
+        }
+        let audio_secs = samples as f64 / SAMPLE_RATE as f64;
+        let rtf = elapsed.as_secs_f64() / audio_secs;
+        self.rtf = Some(match self.rtf {
+            Some(previous) => previous + RTF_SMOOTHING * (rtf - previous),
+            None => rtf,
+        });
+        self.decodes_at_stage += 1;
+    }
+
+    /// Decide whether the stage should change given how much audio is
+    /// waiting. Returns the new stage when it does.
+    pub fn evaluate(&mut self, queued_ms: i64) -> Option<PacingStage> {
+        let rtf = self.rtf?;
+        if self.decodes_at_stage < MIN_DECODES_PER_STAGE {
+            return None;
+        }
+
+        let behind = rtf > LAGGING_RTF || queued_ms > MAX_QUEUED_AUDIO_MS / 2;
+        let next = if behind {
+            match self.stage {
+                PacingStage::Normal => PacingStage::Reduced,
+                PacingStage::Reduced if !self.smallest_model => PacingStage::SmallerModel,
+                stage => stage,
+            }
+        } else if rtf < RECOVERED_RTF && self.stage == PacingStage::Reduced {
+            // A smaller model is kept for the rest of the session; reloading
+            // the larger one would immediately lag again.
+            PacingStage::Normal
+        } else {
+            self.stage
+        };
+
+        if next == self.stage {
+            return None;
+        }
+        self.stage = next;
+        self.decodes_at_stage = 0;
+        Some(next)
+    }
+
+    /// The smaller model could not be loaded; stay at the reduced stage.
+    pub fn smaller_model_unavailable(&mut self) {
+        self.smallest_model = true;
+        self.stage = PacingStage::Reduced;
+    }
+
+    /// Whether the user should currently be told captions lag. Returns the
+    /// new value when it flips.
+    pub fn update_lagging(&mut self, queued_ms: i64) -> Option<bool> {
+        let rtf = self.realtime_factor();
+        let lagging = if self.lagging {
+            rtf >= RECOVERED_RTF || queued_ms > MAX_QUEUED_AUDIO_MS / 4
+        } else {
+            rtf > LAGGING_RTF || queued_ms > MAX_QUEUED_AUDIO_MS / 2
+        };
+        if lagging == self.lagging {
+            return None;
+        }
+        self.lagging = lagging;
+        Some(lagging)
+    }
+}
+
+pub fn samples_to_ms(samples: usize) -> i64 {
+    samples as i64 * 1000 / SAMPLE_RATE
+}
+
+pub fn ms_to_samples(ms: i64) -> usize {
+    (ms * SAMPLE_RATE / 1000) as usize
+}
+
+/// Drop the newest utterances until at most `MAX_QUEUED_AUDIO_MS` of audio
+/// remains queued. Returns the milliseconds dropped.
+pub fn cap_utterance_queue(queue: &mut Vec<Utterance>) -> i64 {
+    let mut queued_ms: i64 = queue.iter().map(|u| samples_to_ms(u.samples.len())).sum();
+    let mut dropped_ms = 0;
+    while queued_ms > MAX_QUEUED_AUDIO_MS && queue.len() > 1 {
+        let Some(newest) = queue.pop() else { break };
+        let ms = samples_to_ms(newest.samples.len());
+        queued_ms -= ms;
+        dropped_ms += ms;
+    }
+    dropped_ms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utterance(start_ms: i64, length_ms: i64) -> Utterance {
+        Utterance {
+            samples: vec![0; ms_to_samples(length_ms)],
+            start_sample: ms_to_samples(start_ms) as i64,
+        }
+    }
+
+    fn decode(pacing: &mut PacingController, rtf: f64) {
+        pacing.record(SAMPLE_RATE as usize, Duration::from_secs_f64(rtf));
+    }
+
+    #[test]
+    fn queue_within_the_limit_is_kept() {
+        let mut queue = vec![utterance(0, 8_000), utterance(9_000, 12_000)];
+        assert_eq!(cap_utterance_queue(&mut queue), 0);
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn queue_over_the_limit_loses_its_newest_utterances() {
+        let mut queue = vec![
+            utterance(0, 9_000),
+            utterance(10_000, 9_000),
+            utterance(20_000, 5_000),
+            utterance(26_000, 2_000),
+        ];
+
+        assert_eq!(cap_utterance_queue(&mut queue), 7_000);
+        let starts: Vec<i64> = queue.iter().map(|u| u.start_sample).collect();
+        assert_eq!(starts, [0, ms_to_samples(10_000) as i64]);
+    }
+
+    #[test]
+    fn a_single_long_utterance_is_never_dropped() {
+        let mut queue = vec![utterance(0, 30_000)];
+        assert_eq!(cap_utterance_queue(&mut queue), 0);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn stage_waits_for_enough_decodes() {
+        let mut pacing = PacingController::new();
+        assert_eq!(pacing.evaluate(0), None);
+        for _ in 0..MIN_DECODES_PER_STAGE - 1 {
+            decode(&mut pacing, 2.0);
+        }
+        assert_eq!(pacing.evaluate(0), None);
+        decode(&mut pacing, 2.0);
+        assert_eq!(pacing.evaluate(0), Some(PacingStage::Reduced));
+        assert_eq!(pacing.stage(), PacingStage::Reduced);
+    }
+
+    #[test]
+    fn slow_decodes_step_down_to_a_smaller_model_and_stay_there() {
+        let mut pacing = PacingController::new();
+        let mut stages = Vec::new();
+        for _ in 0..3 * MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 1.5);
+            stages.extend(pacing.evaluate(0));
+        }
+        assert_eq!(stages, [PacingStage::Reduced, PacingStage::SmallerModel]);
+
+        for _ in 0..2 * MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 0.1);
+            assert_eq!(pacing.evaluate(0), None);
+        }
+    }
+
+    #[test]
+    fn a_long_queue_counts_as_lagging_even_when_decodes_are_fast() {
+        let mut pacing = PacingController::new();
+        for _ in 0..MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 0.2);
+        }
+        assert_eq!(
+            pacing.evaluate(MAX_QUEUED_AUDIO_MS),
+            Some(PacingStage::Reduced)
+        );
+    }
+
+    #[test]
+    fn reduced_stage_recovers_once_decodes_are_fast() {
+        let mut pacing = PacingController::new();
+        for _ in 0..MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 2.0);
+        }
+        pacing.evaluate(0);
+        let mut recovered = None;
+        for _ in 0..4 * MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 0.1);
+            recovered = recovered.or(pacing.evaluate(0));
+        }
+        assert_eq!(recovered, Some(PacingStage::Normal));
+    }
+
+    #[test]
+    fn without_a_smaller_model_the_stage_stays_reduced() {
+        let mut pacing = PacingController::new();
+        pacing.smaller_model_unavailable();
+        for _ in 0..2 * MIN_DECODES_PER_STAGE {
+            decode(&mut pacing, 2.0);
+            assert_eq!(pacing.evaluate(0), None);
+        }
+        assert_eq!(pacing.stage(), PacingStage::Reduced);
+    }
+
+    #[test]
+    fn lagging_flips_with_hysteresis() {
+        let mut pacing = PacingController::new();
+        decode(&mut pacing, 1.5);
+        assert_eq!(pacing.update_lagging(0), Some(true));
+        assert_eq!(pacing.update_lagging(0), None);
+
+        // Between the thresholds the state holds.
+        for _ in 0..10 {
+            decode(&mut pacing, 0.8);
+        }
+        assert_eq!(pacing.update_lagging(0), None);
+
+        for _ in 0..10 {
+            decode(&mut pacing, 0.1);
+        }
+        assert_eq!(pacing.update_lagging(MAX_QUEUED_AUDIO_MS / 2), None);
+        assert_eq!(pacing.update_lagging(0), Some(false));
+    }
+}
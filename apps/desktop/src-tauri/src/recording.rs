@@ -0,0 +1,412 @@
+//! Opt-in retention of captured audio, one FLAC track per source.
+//!
+//! Each capture run gets its own directory under
+//! `<app data>/recordings/<meeting session>/<start time>/`. Tracks hold the
+//! 16kHz audio as captured, before echo cancellation and cleanup, with
+//! silence where capture lost audio, so sample `n` sits at
+//! `timelineStartMs + n * 1000 / 16000` on the `tStartMs`/`tEndMs` timeline.
+
+use crate::audio::SourceRole;
+use crate::flac::FlacWriter;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+const RECORDINGS_DIR: &str = "recordings";
+const MANIFEST_FILE: &str = "manifest.json";
+const DEFAULT_RETENTION_DAYS: u32 = 30;
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// `recording` argument of `start_transcription`; recording is off without it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingOptions {
+    pub meeting_session_id: String,
+    /// Days before the files are deleted; defaults to 30.
+    pub retention_days: Option<u32>,
+}
+
+/// Describes one capture run's recording. Written when recording starts and
+/// again when it ends, so runs interrupted by a crash are still found and
+/// expired.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingManifest {
+    pub meeting_session_id: String,
+    pub started_at_ms: i64,
+    pub expires_at_ms: i64,
+    /// Event time of each track's first sample; non-zero when the run
+    /// resumed a journaled session.
+    #[serde(default)]
+    pub timeline_start_ms: i64,
+    pub sample_rate: u32,
+    pub format: String,
+    /// Whether the run ended normally and track lengths are final.
+    pub complete: bool,
+    pub tracks: Vec<RecordedTrack>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedTrack {
+    /// "microphone" or "systemAudio", as on ASR events.
+    pub audio_source: String,
+    /// File name within the run directory.
+    pub file: String,
+    pub samples: u64,
+}
+
+/// A run found on disk, for `list_recordings`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedRun {
+    pub directory: String,
+    #[serde(flatten)]
+    pub manifest: RecordingManifest,
+}
+
+struct Track {
+    role: SourceRole,
+    writer: Option<FlacWriter>,
+}
+
+/// Streams each source of one capture run to disk.
+pub struct SessionRecorder {
+    dir: PathBuf,
+    manifest: RecordingManifest,
+    tracks: Vec<Track>,
+}
+
+impl SessionRecorder {
+    pub fn start(
+        app: &AppHandle,
+        options: &RecordingOptions,
+        system_audio: bool,
+        timeline_start_ms: i64,
+    ) -> Result<Self, String> {
+        Self::start_in(
+            &recordings_dir(app)?,
+            options,
+            system_audio,
+            timeline_start_ms,
+        )
+    }
+
+    /// `start` with the recordings directory given.
+    fn start_in(
+        root: &Path,
+        options: &RecordingOptions,
+        system_audio: bool,
+        timeline_start_ms: i64,
+    ) -> Result<Self, String> {
+        let started_at_ms = crate::chrono_like_timestamp();
+        let dir = session_dir(root, &options.meeting_session_id)?.join(started_at_ms.to_string());
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let roles: &[SourceRole] = if system_audio {
+            &[SourceRole::Microphone, SourceRole::Loopback]
+        } else {
+            &[SourceRole::Microphone]
+        };
+        let mut tracks = Vec::new();
+        let mut recorded = Vec::new();
+        for &role in roles {
+            let file = format!("{}.flac", crate::source_labels(role).0);
+            tracks.push(Track {
+                role,
+                writer: Some(FlacWriter::create(&dir.join(&file))?),
+            });
+            recorded.push(RecordedTrack {
+                audio_source: crate::source_labels(role).0.to_string(),
+                file,
+                samples: 0,
+            });
+        }
+
+        let retention_days = options.retention_days.unwrap_or(DEFAULT_RETENTION_DAYS);
+        let recorder = Self {
+            manifest: RecordingManifest {
+                meeting_session_id: options.meeting_session_id.clone(),
+                started_at_ms,
+                expires_at_ms: started_at_ms + retention_days as i64 * DAY_MS,
+                timeline_start_ms,
+                sample_rate: 16_000,
+                format: "flac".to_string(),
+                complete: false,
+                tracks: recorded,
+            },
+            dir,
+            tracks,
+        };
+        recorder.write_manifest()?;
+        log::info!("Recording capture to {}", recorder.dir.display());
+        Ok(recorder)
+    }
+
+    /// Append the next samples of `role`, then `dropped` samples of silence
+    /// where capture lost audio after them. A track that fails to write is
+    /// closed and reported once; the other track keeps recording.
+    pub fn write(&mut self, role: SourceRole, samples: &[i16], dropped: u64) -> Result<(), String> {
+        let Some(track) = self.tracks.iter_mut().find(|track| track.role == role) else {
+            return Ok(());
+        };
+        let Some(writer) = track.writer.as_mut() else {
+            return Ok(());
+        };
+        let written = writer
+            .write(samples)
+            .and_then(|()| writer.write(&vec![0; dropped as usize]));
+        if let Err(error) = written {
+            track.writer = None;
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Close the tracks and mark the run complete.
+    pub fn finish(mut self) -> Result<(), String> {
+        let mut result = Ok(());
+        for (track, recorded) in self.tracks.drain(..).zip(&mut self.manifest.tracks) {
+            let Some(writer) = track.writer else {
+                continue;
+            };
+            recorded.samples = writer.samples();
+            if let Err(error) = writer.finish() {
+                result = Err(error);
+            }
+        }
+        self.manifest.complete = true;
+        self.write_manifest()?;
+        result
+    }
+
+    fn write_manifest(&self) -> Result<(), String> {
+        write_manifest(&self.dir, &self.manifest)
+    }
+}
+
+fn write_manifest(dir: &Path, manifest: &RecordingManifest) -> Result<(), String> {
+    let path = dir.join(MANIFEST_FILE);
+    let contents = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to save {}: {}", path.display(), e))
+}
+
+fn read_manifest(dir: &Path) -> Option<RecordingManifest> {
+    let contents = std::fs::read_to_string(dir.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn recordings_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(RECORDINGS_DIR))
+        .map_err(|e| e.to_string())
+}
+
+/// Meeting session IDs become directory names; refuse anything that could
+/// escape the parent directory.
+pub fn check_session_id(meeting_session_id: &str) -> Result<(), String> {
+    let valid = !meeting_session_id.is_empty()
+        && meeting_session_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid meeting session ID: {}",
+            meeting_session_id
+        ))
+    }
+}
+
+fn session_dir(root: &Path, meeting_session_id: &str) -> Result<PathBuf, String> {
+    check_session_id(meeting_session_id)?;
+    Ok(root.join(meeting_session_id))
+}
+
+fn run_dirs(session_dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(session_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Recorded runs of a meeting session, oldest first.
+pub fn list_recordings(
+    app: &AppHandle,
+    meeting_session_id: &str,
+) -> Result<Vec<RecordedRun>, String> {
+    let mut runs: Vec<RecordedRun> =
+        run_dirs(&session_dir(&recordings_dir(app)?, meeting_session_id)?)
+            .into_iter()
+            .filter_map(|dir| {
+                let manifest = read_manifest(&dir)?;
+                Some(RecordedRun {
+                    directory: dir.to_string_lossy().into_owned(),
+                    manifest,
+                })
+            })
+            .collect();
+    runs.sort_by_key(|run| run.manifest.started_at_ms);
+    Ok(runs)
+}
+
+/// Delete runs past their expiry, and sessions left without runs.
+pub fn remove_expired(app: &AppHandle) {
+    if let Ok(root) = recordings_dir(app) {
+        remove_expired_in(&root);
+    }
+}
+
+/// `remove_expired` with the recordings directory given.
+fn remove_expired_in(root: &Path) {
+    let Ok(sessions) = std::fs::read_dir(root) else {
+        return;
+    };
+    let now = crate::chrono_like_timestamp();
+
+    for session in sessions.flatten().map(|entry| entry.path()) {
+        for run in run_dirs(&session) {
+            // Runs without a readable manifest are left for the user to judge.
+            let Some(manifest) = read_manifest(&run) else {
+                continue;
+            };
+            if manifest.expires_at_ms > now {
+                continue;
+            }
+            match std::fs::remove_dir_all(&run) {
+                Ok(()) => log::info!("Deleted expired recording {}", run.display()),
+                Err(error) => log::warn!("Failed to delete {}: {}", run.display(), error),
+            }
+        }
+        // Only succeeds once the session directory is empty.
+        let _ = std::fs::remove_dir(&session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recording-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(meeting_session_id: &str) -> RecordingOptions {
+        RecordingOptions {
+            meeting_session_id: meeting_session_id.to_string(),
+            retention_days: Some(7),
+        }
+    }
+
+    /// Write a run directory holding only a manifest.
+    fn write_run(root: &Path, session: &str, run: &str, expires_at_ms: i64) -> PathBuf {
+        let dir = root.join(session).join(run);
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = RecordingManifest {
+            meeting_session_id: session.to_string(),
+            started_at_ms: 0,
+            expires_at_ms,
+            timeline_start_ms: 0,
+            sample_rate: 16_000,
+            format: "flac".to_string(),
+            complete: true,
+            tracks: Vec::new(),
+        };
+        write_manifest(&dir, &manifest).unwrap();
+        dir
+    }
+
+    #[test]
+    fn session_ids_stay_inside_the_recordings_directory() {
+        for id in ["meeting-1", "a_b", "ABC123"] {
+            assert_eq!(check_session_id(id), Ok(()));
+        }
+        for id in ["", ".", "..", "../x", "a/b", "a\\b", "a b"] {
+            assert!(check_session_id(id).is_err(), "{}", id);
+        }
+
+        let root = temp_dir("invalid");
+        assert!(SessionRecorder::start_in(&root, &options("../escape"), false, 0).is_err());
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn manifest_is_rewritten_when_the_run_ends() {
+        let root = temp_dir("manifest");
+        let mut recorder =
+            SessionRecorder::start_in(&root, &options("meeting"), true, 1_500).unwrap();
+        let dir = recorder.dir.clone();
+
+        let started = read_manifest(&dir).unwrap();
+        assert!(!started.complete);
+        assert_eq!(started.timeline_start_ms, 1_500);
+        assert_eq!(started.expires_at_ms - started.started_at_ms, 7 * DAY_MS);
+        assert_eq!(started.tracks.len(), 2);
+        assert!(started.tracks.iter().all(|track| track.samples == 0));
+        assert!(started
+            .tracks
+            .iter()
+            .all(|track| dir.join(&track.file).is_file()));
+
+        recorder
+            .write(SourceRole::Microphone, &[100; 1_600], 0)
+            .unwrap();
+        recorder.finish().unwrap();
+
+        let finished = read_manifest(&dir).unwrap();
+        assert!(finished.complete);
+        assert_eq!(finished.tracks[0].samples, 1_600);
+        assert_eq!(finished.tracks[1].samples, 0);
+    }
+
+    #[test]
+    fn lost_audio_is_recorded_as_silence() {
+        let root = temp_dir("gap");
+        let mut recorder = SessionRecorder::start_in(&root, &options("meeting"), false, 0).unwrap();
+        let dir = recorder.dir.clone();
+        recorder
+            .write(SourceRole::Microphone, &[100; 1_600], 800)
+            .unwrap();
+        recorder
+            .write(SourceRole::Microphone, &[100; 1_600], 0)
+            .unwrap();
+        // Tracks that are not recorded ignore writes.
+        recorder
+            .write(SourceRole::Loopback, &[100; 1_600], 800)
+            .unwrap();
+        recorder.finish().unwrap();
+
+        let manifest = read_manifest(&dir).unwrap();
+        assert_eq!(manifest.tracks.len(), 1);
+        assert_eq!(manifest.tracks[0].samples, 4_000);
+    }
+
+    #[test]
+    fn removes_expired_runs_and_empty_sessions() {
+        let root = temp_dir("expired");
+        let now = crate::chrono_like_timestamp();
+        let expired = write_run(&root, "old", "1", now - 1);
+        let kept = write_run(&root, "mixed", "2", now + DAY_MS);
+        let mixed_expired = write_run(&root, "mixed", "1", now - DAY_MS);
+        let unreadable = root.join("unknown").join("1");
+        std::fs::create_dir_all(&unreadable).unwrap();
+
+        remove_expired_in(&root);
+        assert!(!expired.exists());
+        assert!(!root.join("old").exists());
+        assert!(!mixed_expired.exists());
+        assert!(kept.exists());
+        assert!(unreadable.exists());
+    }
+}
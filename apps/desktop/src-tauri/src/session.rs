@@ -0,0 +1,313 @@
+//! Lifecycle of the transcription session.
+//!
+//! One task owns capture and the ASR for a session; commands reach it over a
+//! channel. The phase here is the single source of truth for what the
+//! session is doing, and only these transitions are allowed:
+//!
+//! - Idle, Stopped or Failed -> Starting (one session at a time)
+//! - Starting -> Listening, or Failed when the model or capture cannot start
+//! - Listening <-> Paused
+//! - Listening or Paused -> Draining (stop requested), or Failed
+//! - Draining -> Stopped, or Failed
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionPhase {
+    Idle,
+    /// Loading the model and opening capture.
+    Starting,
+    Listening,
+    Paused,
+    /// Stopped capturing; transcribing what was buffered.
+    Draining,
+    Stopped,
+    Failed,
+}
+
+impl SessionPhase {
+    fn can_become(self, next: SessionPhase) -> bool {
+        use SessionPhase::*;
+        matches!(
+            (self, next),
+            (Idle | Stopped | Failed, Starting)
+                | (Starting, Listening | Failed)
+                | (Listening, Paused | Draining | Failed)
+                | (Paused, Listening | Draining | Failed)
+                | (Draining, Stopped | Failed)
+        )
+    }
+}
+
+/// Sent by the Tauri commands to the session task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionCommand {
+    Pause,
+    Resume,
+    /// End the session; buffered audio is transcribed first unless `cancel`.
+    Stop {
+        cancel: bool,
+    },
+}
+
+/// Why a session command was refused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SessionError {
+    /// The session cannot go from `from` to `to`, e.g. starting twice or
+    /// pausing a stopped session.
+    #[serde(rename_all = "camelCase")]
+    InvalidTransition {
+        from: SessionPhase,
+        to: SessionPhase,
+    },
+    /// The session task is no longer running.
+    TaskGone,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::InvalidTransition { from, to } => {
+                write!(f, "Cannot go from {:?} to {:?}", from, to)
+            }
+            SessionError::TaskGone => write!(f, "The transcription session is not running"),
+        }
+    }
+}
+
+/// Phase of the session and the handle on its task, kept in
+/// `TranscriptionState`.
+pub struct SessionController {
+    phase: SessionPhase,
+    commands: Option<Sender<SessionCommand>>,
+    task: Option<tauri::async_runtime::JoinHandle<()>>,
+}
+
+impl SessionController {
+    pub fn new() -> Self {
+        Self {
+            phase: SessionPhase::Idle,
+            commands: None,
+            task: None,
+        }
+    }
+
+    pub fn phase(&self) -> SessionPhase {
+        self.phase
+    }
+
+    /// Move to `next`, returning the phase left.
+    pub fn transition(&mut self, next: SessionPhase) -> Result<SessionPhase, SessionError> {
+        if !self.phase.can_become(next) {
+            return Err(SessionError::InvalidTransition {
+                from: self.phase,
+                to: next,
+            });
+        }
+        Ok(std::mem::replace(&mut self.phase, next))
+    }
+
+    /// Channel for a new session task; the controller keeps the sender.
+    pub fn open_channel(&mut self) -> Receiver<SessionCommand> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.commands = Some(sender);
+        receiver
+    }
+
+    pub fn attach(&mut self, task: tauri::async_runtime::JoinHandle<()>) {
+        self.task = Some(task);
+    }
+
+    pub fn send(&self, command: SessionCommand) -> Result<(), SessionError> {
+        self.commands
+            .as_ref()
+            .and_then(|commands| commands.send(command).ok())
+            .ok_or(SessionError::TaskGone)
+    }
+
+    /// The session task, to await its end.
+    pub fn take_task(&mut self) -> Option<tauri::async_runtime::JoinHandle<()>> {
+        self.commands = None;
+        self.task.take()
+    }
+}
+
+/// The session task's view of its commands.
+pub struct SessionInbox {
+    commands: Receiver<SessionCommand>,
+    paused: bool,
+    stop: Option<bool>,
+}
+
+impl SessionInbox {
+    pub fn new(commands: Receiver<SessionCommand>) -> Self {
+        Self {
+            commands,
+            paused: false,
+            stop: None,
+        }
+    }
+
+    /// Apply commands received since the last poll.
+    pub fn poll(&mut self) {
+        loop {
+            match self.commands.try_recv() {
+                Ok(SessionCommand::Pause) => self.paused = true,
+                Ok(SessionCommand::Resume) => self.paused = false,
+                Ok(SessionCommand::Stop { cancel }) => {
+                    self.stop = Some(cancel || self.stop == Some(true));
+                }
+                Err(TryRecvError::Empty) => break,
+                // Nobody can stop the session any more; do not linger.
+                Err(TryRecvError::Disconnected) => {
+                    self.stop.get_or_insert(true);
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused && self.stop.is_none()
+    }
+
+    pub fn is_stopping(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    /// Whether the stop asked for buffered audio to be discarded.
+    pub fn is_cancelled(&self) -> bool {
+        self.stop == Some(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SessionPhase::*;
+
+    fn controller_in(path: &[SessionPhase]) -> SessionController {
+        let mut controller = SessionController::new();
+        for &phase in path {
+            controller.transition(phase).unwrap();
+        }
+        controller
+    }
+
+    #[test]
+    fn a_session_runs_through_its_phases() {
+        let mut controller = SessionController::new();
+        for (next, left) in [
+            (Starting, Idle),
+            (Listening, Starting),
+            (Paused, Listening),
+            (Listening, Paused),
+            (Draining, Listening),
+            (Stopped, Draining),
+            (Starting, Stopped),
+            (Failed, Starting),
+            (Starting, Failed),
+        ] {
+            assert_eq!(controller.transition(next), Ok(left));
+            assert_eq!(controller.phase(), next);
+        }
+        assert_eq!(
+            controller_in(&[Starting, Listening, Paused]).transition(Draining),
+            Ok(Paused)
+        );
+    }
+
+    #[test]
+    fn invalid_transitions_leave_the_phase_alone() {
+        for (path, next) in [
+            (&[][..], Listening),
+            (&[][..], Draining),
+            (&[Starting][..], Starting),
+            (&[Starting][..], Paused),
+            (&[Starting, Listening][..], Starting),
+            (&[Starting, Listening][..], Stopped),
+            (&[Starting, Listening, Draining][..], Paused),
+            (&[Starting, Listening, Draining][..], Draining),
+            (&[Starting, Listening, Draining, Stopped][..], Paused),
+            (&[Starting, Failed][..], Draining),
+        ] {
+            let mut controller = controller_in(path);
+            let from = controller.phase();
+            assert_eq!(
+                controller.transition(next),
+                Err(SessionError::InvalidTransition { from, to: next })
+            );
+            assert_eq!(controller.phase(), from);
+        }
+    }
+
+    #[test]
+    fn commands_need_a_running_task() {
+        let mut controller = SessionController::new();
+        assert_eq!(
+            controller.send(SessionCommand::Pause),
+            Err(SessionError::TaskGone)
+        );
+
+        let receiver = controller.open_channel();
+        assert_eq!(controller.send(SessionCommand::Pause), Ok(()));
+        drop(receiver);
+        assert_eq!(
+            controller.send(SessionCommand::Resume),
+            Err(SessionError::TaskGone)
+        );
+    }
+
+    #[test]
+    fn stop_after_pause_is_not_paused() {
+        let mut controller = SessionController::new();
+        let mut inbox = SessionInbox::new(controller.open_channel());
+        controller.send(SessionCommand::Pause).unwrap();
+        inbox.poll();
+        assert!(inbox.is_paused());
+
+        controller
+            .send(SessionCommand::Stop { cancel: false })
+            .unwrap();
+        inbox.poll();
+        assert!(!inbox.is_paused());
+        assert!(inbox.is_stopping());
+        assert!(!inbox.is_cancelled());
+    }
+
+    #[test]
+    fn a_cancel_after_a_stop_discards_the_buffer() {
+        let mut controller = SessionController::new();
+        let mut inbox = SessionInbox::new(controller.open_channel());
+        controller
+            .send(SessionCommand::Stop { cancel: false })
+            .unwrap();
+        inbox.poll();
+        assert!(!inbox.is_cancelled());
+
+        controller
+            .send(SessionCommand::Stop { cancel: true })
+            .unwrap();
+        // A later plain stop does not take the cancel back.
+        controller
+            .send(SessionCommand::Stop { cancel: false })
+            .unwrap();
+        inbox.poll();
+        assert!(inbox.is_stopping());
+        assert!(inbox.is_cancelled());
+    }
+
+    #[test]
+    fn losing_the_controller_cancels() {
+        let mut controller = SessionController::new();
+        let mut inbox = SessionInbox::new(controller.open_channel());
+        drop(controller);
+        inbox.poll();
+        assert!(inbox.is_stopping());
+        assert!(inbox.is_cancelled());
+    }
+}
@@ -0,0 +1,332 @@
+use crate::asr::{AsrSegment, AsrWord};
+use crate::audio::Utterance;
+use crate::streaming::words;
+
+const SAMPLE_RATE: i64 = 16_000;
+/// Audio from the end of one chunk that is decoded again with the next.
+const CARRY_OVER_MS: i64 = 1_000;
+/// Emitted words remembered per source for text alignment.
+const RECENT_WORD_LIMIT: usize = 32;
+/// Slack when comparing segment timestamps against already-emitted audio.
+const TIMESTAMP_TOLERANCE_MS: i64 = 150;
+
+/// Decodes consecutive chunks of one source with overlapping context and
+/// removes words the overlap causes to be transcribed twice.
+pub struct TranscriptStitcher {
+    /// Tail of the previous window, carried into the next one.
+    context: Vec<i16>,
+    context_end_sample: i64,
+    /// Normalised words most recently emitted, oldest first.
+    recent_words: Vec<String>,
+    /// End of the last emitted segment, in ms since capture start.
+    emitted_until_ms: i64,
+}
+
+impl TranscriptStitcher {
+    pub fn new() -> Self {
+        Self {
+            context: Vec::new(),
+            context_end_sample: 0,
+            recent_words: Vec::new(),
+            emitted_until_ms: 0,
+        }
+    }
+
+    /// Prefix `utterance` with the carried-over audio when it directly
+    /// continues the previous chunk, and keep its tail for the next call.
+    pub fn extend_with_context(&mut self, utterance: Utterance) -> Utterance {
+        let contiguous =
+            !self.context.is_empty() && utterance.start_sample == self.context_end_sample;
+
+        let window = if contiguous {
+            let mut samples = std::mem::take(&mut self.context);
+            let start_sample = utterance.start_sample - samples.len() as i64;
+            samples.extend_from_slice(&utterance.samples);
+            Utterance {
+                samples,
+                start_sample,
+            }
+        } else {
+            utterance
+        };
+
+        let keep = ((CARRY_OVER_MS * SAMPLE_RATE / 1000) as usize).min(window.samples.len());
+        self.context = window.samples[window.samples.len() - keep..].to_vec();
+        self.context_end_sample = window.start_sample + window.samples.len() as i64;
+
+        window
+    }
+
+    /// Convert window-relative segments to absolute times and drop or trim
+    /// anything already emitted from the overlapping context.
+    pub fn reconcile(&mut self, window: &Utterance, segments: Vec<AsrSegment>) -> Vec<AsrSegment> {
+        let window_start_ms = window.start_sample * 1000 / SAMPLE_RATE;
+        let window_end_ms =
+            (window.start_sample + window.samples.len() as i64) * 1000 / SAMPLE_RATE;
+        let overlap_until_ms = self.emitted_until_ms + TIMESTAMP_TOLERANCE_MS;
+
+        let mut stitched = Vec::new();
+        for mut segment in segments {
+            let has_timing = segment.t_end_ms > segment.t_start_ms;
+            segment.shift(window_start_ms, window_end_ms);
+
+            // Wholly inside audio whose text was already emitted.
+            if has_timing && segment.t_end_ms <= overlap_until_ms {
+                continue;
+            }
+
+            // Straddles the boundary: drop the leading words that repeat the
+            // tail of what was already emitted.
+            if segment.t_start_ms < overlap_until_ms {
+                let segment_words: Vec<String> = words(&segment.text).collect();
+                let repeated = repeated_prefix_len(&self.recent_words, &segment_words);
+                if repeated > 0 {
+                    segment.text = drop_leading_words(&segment.text, repeated);
+                    drop_leading_timed_words(&mut segment.words, repeated);
+                    segment.t_start_ms = segment.t_start_ms.max(self.emitted_until_ms);
+                    segment.confidence = crate::asr::words_confidence(&segment.words);
+                }
+                if segment.text.is_empty() {
+                    continue;
+                }
+            }
+
+            self.remember(&segment);
+            stitched.push(segment);
+        }
+
+        stitched
+    }
+
+    fn remember(&mut self, segment: &AsrSegment) {
+        self.recent_words.extend(words(&segment.text));
+        if self.recent_words.len() > RECENT_WORD_LIMIT {
+            let excess = self.recent_words.len() - RECENT_WORD_LIMIT;
+            self.recent_words.drain(..excess);
+        }
+        self.emitted_until_ms = self.emitted_until_ms.max(segment.t_end_ms);
+    }
+}
+
+/// Number of leading words of `next` that repeat the tail of `previous`.
+///
+/// The last emitted word may have been cut mid-word at the chunk boundary
+/// ("re" vs "report"). It still anchors the alignment, but the completed
+/// word is kept so it is not lost.
+fn repeated_prefix_len(previous: &[String], next: &[String]) -> usize {
+    for k in (1..=previous.len().min(next.len())).rev() {
+        let tail = &previous[previous.len() - k..];
+        let head = &next[..k];
+        if tail[..k - 1] != head[..k - 1] {
+            continue;
+        }
+        if tail[k - 1] == head[k - 1] {
+            return k;
+        }
+        if head[k - 1].starts_with(tail[k - 1].as_str()) {
+            return k - 1;
+        }
+    }
+    0
+}
+
+/// Remove the first `count` words (as counted by `words`) from `text`,
+/// keeping the original spelling and punctuation of the rest.
+fn drop_leading_words(text: &str, count: usize) -> String {
+    let mut remaining = count;
+    text.split_whitespace()
+        .skip_while(|token| {
+            if remaining == 0 {
+                return false;
+            }
+            if words(token).next().is_some() {
+                remaining -= 1;
+            }
+            true
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Remove the timed words matching the first `count` words of the text, as
+/// `drop_leading_words` does.
+fn drop_leading_timed_words(timed: &mut Vec<AsrWord>, count: usize) {
+    let mut remaining = count;
+    let dropped = timed
+        .iter()
+        .take_while(|word| {
+            if remaining == 0 {
+                return false;
+            }
+            if words(&word.text).next().is_some() {
+                remaining -= 1;
+            }
+            true
+        })
+        .count();
+    timed.drain(..dropped);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::segment;
+
+    fn utterance(start_ms: i64, length_ms: i64) -> Utterance {
+        Utterance {
+            samples: vec![0; (length_ms * SAMPLE_RATE / 1000) as usize],
+            start_sample: start_ms * SAMPLE_RATE / 1000,
+        }
+    }
+
+    fn texts(segments: &[AsrSegment]) -> Vec<&str> {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn contiguous_chunks_carry_the_previous_tail() {
+        let mut stitcher = TranscriptStitcher::new();
+        let first = stitcher.extend_with_context(utterance(0, 3_000));
+        assert_eq!(first.start_sample, 0);
+
+        let second = stitcher.extend_with_context(utterance(3_000, 2_000));
+        assert_eq!(second.start_sample, 2 * SAMPLE_RATE);
+        assert_eq!(second.samples.len(), 3 * SAMPLE_RATE as usize);
+
+        let apart = stitcher.extend_with_context(utterance(9_000, 2_000));
+        assert_eq!(apart.start_sample, 9 * SAMPLE_RATE);
+        assert_eq!(apart.samples.len(), 2 * SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn segments_get_session_times_clamped_to_the_window() {
+        let mut stitcher = TranscriptStitcher::new();
+        let window = utterance(5_000, 2_000);
+
+        let stitched = stitcher.reconcile(&window, vec![segment("hello", 100, 2_500)]);
+
+        assert_eq!(texts(&stitched), ["hello"]);
+        assert_eq!(
+            (stitched[0].t_start_ms, stitched[0].t_end_ms),
+            (5_100, 7_000)
+        );
+    }
+
+    #[test]
+    fn segments_inside_emitted_context_are_dropped() {
+        let mut stitcher = TranscriptStitcher::new();
+        stitcher.reconcile(&utterance(0, 3_000), vec![segment("one two", 0, 3_000)]);
+
+        let stitched = stitcher.reconcile(
+            &utterance(2_000, 3_000),
+            vec![segment("two", 0, 1_000), segment("three", 1_000, 3_000)],
+        );
+
+        assert_eq!(texts(&stitched), ["three"]);
+    }
+
+    #[test]
+    fn repeated_words_across_the_boundary_are_trimmed() {
+        let mut stitcher = TranscriptStitcher::new();
+        stitcher.reconcile(
+            &utterance(0, 3_000),
+            vec![segment("We should ship it", 0, 3_000)],
+        );
+
+        let stitched = stitcher.reconcile(
+            &utterance(2_000, 3_000),
+            vec![segment("ship it, on Friday.", 500, 2_800)],
+        );
+
+        assert_eq!(texts(&stitched), ["on Friday."]);
+        assert_eq!(stitched[0].t_start_ms, 3_000);
+    }
+
+    #[test]
+    fn a_word_cut_at_the_boundary_is_completed() {
+        let mut stitcher = TranscriptStitcher::new();
+        stitcher.reconcile(
+            &utterance(0, 3_000),
+            vec![segment("the quarterly re", 0, 3_000)],
+        );
+
+        let stitched = stitcher.reconcile(
+            &utterance(2_000, 3_000),
+            vec![segment("quarterly report is late", 300, 2_900)],
+        );
+
+        assert_eq!(texts(&stitched), ["report is late"]);
+    }
+
+    #[test]
+    fn unrelated_text_in_the_overlap_is_kept() {
+        let mut stitcher = TranscriptStitcher::new();
+        stitcher.reconcile(
+            &utterance(0, 3_000),
+            vec![segment("good morning", 0, 3_000)],
+        );
+
+        let stitched = stitcher.reconcile(
+            &utterance(2_000, 3_000),
+            vec![segment("let's begin", 800, 2_500)],
+        );
+
+        assert_eq!(texts(&stitched), ["let's begin"]);
+    }
+
+    #[test]
+    fn repeated_prefix_prefers_the_longest_overlap() {
+        let previous: Vec<String> = words("a b a b").collect();
+        let next: Vec<String> = words("a b a b c").collect();
+        assert_eq!(repeated_prefix_len(&previous, &next), 4);
+        assert_eq!(repeated_prefix_len(&previous, &["c".to_string()]), 0);
+    }
+
+    #[test]
+    fn dropping_words_keeps_spelling_and_skips_punctuation() {
+        assert_eq!(drop_leading_words("Well, - ship it now!", 2), "it now!");
+        assert_eq!(drop_leading_words("ship", 3), "");
+    }
+
+    fn timed(texts: &[&str]) -> Vec<AsrWord> {
+        texts
+            .iter()
+            .enumerate()
+            .map(|(i, text)| AsrWord {
+                text: text.to_string(),
+                t_start_ms: i as i64 * 100,
+                t_end_ms: i as i64 * 100 + 100,
+                probability: 0.5 + i as f64 * 0.1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dropping_timed_words_matches_the_text() {
+        let mut words = timed(&["Well,", "-", "ship", "it", "now!"]);
+        drop_leading_timed_words(&mut words, 2);
+        assert_eq!(words, timed(&["Well,", "-", "ship", "it", "now!"])[3..]);
+
+        let mut words = timed(&["ship"]);
+        drop_leading_timed_words(&mut words, 3);
+        assert!(words.is_empty());
+    }
+
+    #[test]
+    fn trimmed_segments_lose_their_repeated_timed_words() {
+        let mut stitcher = TranscriptStitcher::new();
+        stitcher.reconcile(
+            &utterance(0, 3_000),
+            vec![segment("We should ship it", 0, 3_000)],
+        );
+
+        let mut repeated = segment("ship it, on Friday.", 500, 2_800);
+        repeated.words = timed(&["ship", "it,", "on", "Friday."]);
+        let stitched = stitcher.reconcile(&utterance(2_000, 3_000), vec![repeated]);
+
+        let words: Vec<&str> = stitched[0].words.iter().map(|w| w.text.as_str()).collect();
+        assert_eq!(words, ["on", "Friday."]);
+        assert_eq!(stitched[0].words[0].t_start_ms, 2_200);
+        assert!((stitched[0].confidence.unwrap() - 0.75).abs() < 1e-9);
+    }
+}
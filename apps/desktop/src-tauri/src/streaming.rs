@@ -0,0 +1,445 @@
+use crate::asr::AsrSegment;
+
+const SAMPLE_RATE: i64 = 16_000;
+/// Segments ending this close to the window edge may still change.
+const TAIL_GUARD_MS: i64 = 1_000;
+/// Default for committing everything once the uncommitted window grows
+/// beyond this.
+const MAX_WINDOW_MS: i64 = 15_000;
+
+/// Result of re-decoding the window once.
+pub struct StreamingUpdate {
+    /// Segments that are now stable, with absolute session times.
+    pub committed: Vec<AsrSegment>,
+    /// Audio covered by `committed`, for prosody.
+    pub committed_audio: Vec<i16>,
+    /// Unstable tail hypothesis, with absolute session times.
+    pub partial: Option<AsrSegment>,
+}
+
+/// Sliding window of not-yet-committed audio for one source.
+///
+/// Each tick re-decodes the whole window. Words that two consecutive
+/// hypotheses agree on, and that are not at the window edge, are committed
+/// and their audio is dropped from the window; the rest is reported as a
+/// partial that the UI refines in place.
+pub struct StreamingWindow {
+    pending: Vec<i16>,
+    start_sample: i64,
+    previous_words: Vec<String>,
+    reserved_sequence: Option<u32>,
+    max_window_ms: i64,
+    finishing: bool,
+    /// Samples lost right after `pending`; later audio starts past them.
+    gap: usize,
+}
+
+impl StreamingWindow {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            start_sample: 0,
+            previous_words: Vec::new(),
+            reserved_sequence: None,
+            max_window_ms: MAX_WINDOW_MS,
+            finishing: false,
+            gap: 0,
+        }
+    }
+
+    /// Force-commit threshold; `None` restores the default.
+    pub fn set_max_window_ms(&mut self, max_window_ms: Option<i64>) {
+        self.max_window_ms = max_window_ms.unwrap_or(MAX_WINDOW_MS);
+    }
+
+    /// The source has ended: the next update commits the whole window.
+    pub fn finish(&mut self) {
+        self.finishing = true;
+    }
+
+    /// Whether the next update commits the whole window, because the
+    /// source ended or the audio after the window was dropped.
+    pub fn must_flush(&self) -> bool {
+        self.finishing || self.gap > 0
+    }
+
+    /// Place the next pushed sample at `sample` on the source timeline.
+    pub fn start_at(&mut self, sample: i64) {
+        self.start_sample = sample - self.pending.len() as i64;
+    }
+
+    /// `dropped` samples following what was pushed will never arrive. The
+    /// next update commits the window so later audio is not stitched onto
+    /// it, and starts the window again after the gap.
+    pub fn drop_after(&mut self, dropped: usize) {
+        self.gap += dropped;
+    }
+
+    pub fn push(&mut self, samples: &[i16]) {
+        if self.gap > 0 {
+            // The update that would have closed the gap never ran.
+            self.close_gap();
+        }
+        self.pending.extend_from_slice(samples);
+    }
+
+    pub fn window(&self) -> &[i16] {
+        &self.pending
+    }
+
+    /// Offset of `window()[0]` from the start of the source's capture.
+    pub fn start_sample(&self) -> i64 {
+        self.start_sample
+    }
+
+    /// Whether uncommitted words are still waiting to settle.
+    pub fn has_hypothesis(&self) -> bool {
+        !self.previous_words.is_empty()
+    }
+
+    /// Sequence number for the current utterance's partials. The first final
+    /// committed from the utterance reuses it so the UI can replace in place.
+    pub fn partial_sequence(&mut self, next_sequence: &mut u32) -> u32 {
+        *self.reserved_sequence.get_or_insert_with(|| {
+            let sequence = *next_sequence;
+            *next_sequence += 1;
+            sequence
+        })
+    }
+
+    /// Give up the sequence of a partial that will not become a final, so
+    /// the UI can withdraw it.
+    pub fn release_sequence(&mut self) -> Option<u32> {
+        self.reserved_sequence.take()
+    }
+
+    /// Sequence number for a committed segment.
+    pub fn final_sequence(&mut self, next_sequence: &mut u32) -> u32 {
+        self.reserved_sequence.take().unwrap_or_else(|| {
+            let sequence = *next_sequence;
+            *next_sequence += 1;
+            sequence
+        })
+    }
+
+    /// Fold a fresh decode of `window()` into the stable/unstable split.
+    pub fn update(&mut self, segments: Vec<AsrSegment>) -> StreamingUpdate {
+        let window_ms = self.pending.len() as i64 * 1000 / SAMPLE_RATE;
+        let start_ms = self.start_sample * 1000 / SAMPLE_RATE;
+
+        if segments.is_empty() {
+            // Nothing recognised; keep only a short tail so speech starting
+            // right at the edge is not cut.
+            self.previous_words.clear();
+            if self.gap > 0 {
+                self.close_gap();
+            } else if window_ms > TAIL_GUARD_MS * 2 {
+                let keep = (TAIL_GUARD_MS * SAMPLE_RATE / 1000) as usize;
+                self.trim(self.pending.len() - keep);
+            }
+            return StreamingUpdate {
+                committed: Vec::new(),
+                committed_audio: Vec::new(),
+                partial: None,
+            };
+        }
+
+        let hypothesis: Vec<String> = segments.iter().flat_map(|s| words(&s.text)).collect();
+        let stable_words = common_prefix_len(&self.previous_words, &hypothesis);
+        let force = self.must_flush() || window_ms >= self.max_window_ms;
+
+        let mut commit_count = 0;
+        let mut committed_words = 0;
+        let mut words_seen = 0;
+        for segment in &segments {
+            words_seen += words(&segment.text).count();
+            let settled = words_seen <= stable_words
+                && segment.t_end_ms > segment.t_start_ms
+                && segment.t_end_ms <= window_ms - TAIL_GUARD_MS;
+            if !(settled || force) {
+                break;
+            }
+            commit_count += 1;
+            committed_words = words_seen;
+        }
+
+        let cut_ms = if force {
+            window_ms
+        } else if commit_count > 0 {
+            segments[commit_count - 1].t_end_ms
+        } else {
+            0
+        };
+
+        let mut segments = segments;
+        let remaining = segments.split_off(commit_count);
+        let committed: Vec<AsrSegment> = segments
+            .into_iter()
+            .map(|mut segment| {
+                segment.shift(start_ms, start_ms + window_ms);
+                segment
+            })
+            .collect();
+
+        let cut_samples = ((cut_ms * SAMPLE_RATE / 1000) as usize).min(self.pending.len());
+        let committed_audio = self.pending[..cut_samples].to_vec();
+        self.trim(cut_samples);
+        self.previous_words = hypothesis[committed_words..].to_vec();
+        self.close_gap();
+
+        let partial = if remaining.is_empty() {
+            None
+        } else {
+            let text = remaining
+                .iter()
+                .map(|s| s.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(AsrSegment {
+                text,
+                t_start_ms: start_ms + remaining[0].t_start_ms,
+                t_end_ms: start_ms + window_ms,
+                confidence: None,
+                language: remaining[0].language.clone(),
+                language_probability: remaining[0].language_probability,
+                translation: None,
+                words: Vec::new(),
+            })
+        };
+
+        StreamingUpdate {
+            committed,
+            committed_audio,
+            partial,
+        }
+    }
+
+    fn trim(&mut self, samples: usize) {
+        self.pending.drain(..samples);
+        self.start_sample += samples as i64;
+    }
+
+    /// Discard what is left of the window and move past the dropped audio.
+    fn close_gap(&mut self) {
+        if self.gap == 0 {
+            return;
+        }
+        self.trim(self.pending.len());
+        self.start_sample += self.gap as i64;
+        self.gap = 0;
+        self.previous_words.clear();
+    }
+}
+
+/// Words normalised for comparison across hypotheses.
+pub fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+}
+
+fn common_prefix_len(a: &[String], b: &[String]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::segment;
+
+    fn seconds(seconds: i64) -> Vec<i16> {
+        vec![0; (seconds * SAMPLE_RATE) as usize]
+    }
+
+    #[test]
+    fn first_hypothesis_is_only_a_partial() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(4));
+
+        let update = window.update(vec![segment("hello there", 0, 1_500)]);
+
+        assert!(update.committed.is_empty());
+        let partial = update.partial.unwrap();
+        assert_eq!(partial.text, "hello there");
+        assert_eq!((partial.t_start_ms, partial.t_end_ms), (0, 4_000));
+        assert_eq!(window.window().len(), seconds(4).len());
+        assert!(window.has_hypothesis());
+    }
+
+    #[test]
+    fn agreed_words_away_from_the_edge_are_committed() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(4));
+        window.update(vec![segment("hello there", 0, 1_500)]);
+        window.push(&seconds(1));
+
+        let update = window.update(vec![
+            segment("Hello, there.", 0, 1_500),
+            segment("how are", 1_600, 4_800),
+        ]);
+
+        assert_eq!(update.committed.len(), 1);
+        assert_eq!(update.committed[0].text, "Hello, there.");
+        assert_eq!(update.committed_audio.len(), 24_000);
+        assert_eq!(window.start_sample(), 24_000);
+        assert_eq!(window.window().len(), 56_000);
+        let partial = update.partial.unwrap();
+        assert_eq!(partial.text, "how are");
+        assert_eq!((partial.t_start_ms, partial.t_end_ms), (1_600, 5_000));
+    }
+
+    #[test]
+    fn words_inside_the_tail_guard_wait() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(3));
+        window.update(vec![segment("hello", 0, 2_500)]);
+
+        let update = window.update(vec![segment("hello", 0, 2_500)]);
+
+        assert!(update.committed.is_empty());
+        assert_eq!(update.partial.unwrap().text, "hello");
+    }
+
+    #[test]
+    fn committed_segments_get_session_times() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(4));
+        window.update(vec![segment("one", 0, 1_000)]);
+        window.update(vec![segment("one", 0, 1_000), segment("two", 1_200, 3_900)]);
+        window.push(&seconds(2));
+
+        let update = window.update(vec![segment("two", 200, 2_000)]);
+
+        assert_eq!(update.committed[0].text, "two");
+        assert_eq!(
+            (update.committed[0].t_start_ms, update.committed[0].t_end_ms),
+            (1_200, 3_000)
+        );
+        assert_eq!(window.start_sample(), 3 * SAMPLE_RATE);
+    }
+
+    #[test]
+    fn finishing_commits_everything() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(3));
+        window.finish();
+
+        let update = window.update(vec![segment("last words", 500, 2_900)]);
+
+        assert_eq!(update.committed.len(), 1);
+        assert!(update.partial.is_none());
+        assert!(window.window().is_empty());
+        assert_eq!(window.start_sample(), 48_000);
+    }
+
+    #[test]
+    fn a_full_window_is_force_committed() {
+        let mut window = StreamingWindow::new();
+        window.set_max_window_ms(Some(5_000));
+        window.push(&seconds(5));
+
+        let update = window.update(vec![segment("a long run on", 0, 5_000)]);
+
+        assert_eq!(update.committed.len(), 1);
+        assert!(window.window().is_empty());
+        assert!(!window.has_hypothesis());
+    }
+
+    #[test]
+    fn silence_keeps_only_a_short_tail() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(5));
+
+        let update = window.update(Vec::new());
+
+        assert!(update.committed.is_empty() && update.partial.is_none());
+        assert_eq!(window.window().len(), SAMPLE_RATE as usize);
+        assert_eq!(window.start_sample(), 4 * SAMPLE_RATE);
+    }
+
+    #[test]
+    fn dropped_audio_closes_the_window() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(3));
+        window.update(vec![segment("before the gap", 0, 2_000)]);
+        window.drop_after(2 * SAMPLE_RATE as usize);
+        assert!(window.must_flush());
+
+        let update = window.update(vec![segment("before the gap", 0, 2_000)]);
+
+        assert_eq!(update.committed.len(), 1);
+        assert!(window.window().is_empty());
+        assert!(!window.has_hypothesis());
+        assert!(!window.must_flush());
+        assert_eq!(window.start_sample(), 5 * SAMPLE_RATE);
+    }
+
+    #[test]
+    fn dropped_audio_after_silence_only_moves_the_timeline() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(2));
+        window.drop_after(SAMPLE_RATE as usize);
+
+        let update = window.update(Vec::new());
+
+        assert!(update.committed.is_empty());
+        assert!(window.window().is_empty());
+        assert_eq!(window.start_sample(), 3 * SAMPLE_RATE);
+    }
+
+    #[test]
+    fn a_gap_left_open_is_closed_before_new_audio() {
+        let mut window = StreamingWindow::new();
+        window.push(&seconds(2));
+        window.drop_after(SAMPLE_RATE as usize);
+
+        window.push(&seconds(1));
+
+        assert_eq!(window.start_sample(), 3 * SAMPLE_RATE);
+        assert_eq!(window.window().len(), SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn start_at_places_the_next_sample() {
+        let mut window = StreamingWindow::new();
+        window.start_at(4 * SAMPLE_RATE);
+        window.push(&seconds(3));
+        window.finish();
+
+        let update = window.update(vec![segment("resumed", 0, 2_000)]);
+
+        assert_eq!(update.committed[0].t_start_ms, 4_000);
+    }
+
+    #[test]
+    fn an_abandoned_partial_releases_its_sequence() {
+        let mut window = StreamingWindow::new();
+        let mut next = 3;
+        window.push(&seconds(4));
+        window.update(vec![segment("maybe", 0, 1_000)]);
+        assert_eq!(window.partial_sequence(&mut next), 3);
+
+        window.update(Vec::new());
+
+        assert!(!window.has_hypothesis());
+        assert_eq!(window.release_sequence(), Some(3));
+        assert_eq!(window.partial_sequence(&mut next), 4);
+    }
+
+    #[test]
+    fn first_final_reuses_the_partial_sequence() {
+        let mut window = StreamingWindow::new();
+        let mut next = 7;
+
+        assert_eq!(window.partial_sequence(&mut next), 7);
+        assert_eq!(window.partial_sequence(&mut next), 7);
+        assert_eq!(window.final_sequence(&mut next), 7);
+        assert_eq!(window.final_sequence(&mut next), 8);
+        assert_eq!(next, 9);
+    }
+}
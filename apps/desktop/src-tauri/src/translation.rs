@@ -0,0 +1,272 @@
+//! Live translation of finals next to their source-language text.
+//!
+//! A `Translator` turns a transcribed chunk into text in the target
+//! language. The built-in one decodes the chunk again with whisper's translate
+//! task, which only produces English; other translators can work from the
+//! transcribed text instead of the audio.
+
+use crate::asr::{AsrEngine, AsrFuture, AsrSegment};
+use crate::error::AppError;
+use serde::Deserialize;
+
+/// The only target whisper's translate task supports.
+const WHISPER_TARGET: &str = "en";
+
+/// `translation` in `start_transcription`'s capture options.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranslationOptions {
+    /// Language code to translate into.
+    pub target: String,
+    /// Audio sources to translate, e.g. `["systemAudio"]` for only the
+    /// other side of a call; empty translates every source.
+    #[serde(default)]
+    pub sources: Vec<String>,
+}
+
+/// Produces the translation of a chunk `engine` transcribed as `segments`.
+pub trait Translator: Send {
+    fn name(&self) -> &'static str;
+
+    /// Segments in the target language on the chunk's clock.
+    fn translate<'a>(
+        &'a mut self,
+        engine: &'a mut dyn AsrEngine,
+        samples: &'a [i16],
+        segments: &'a [AsrSegment],
+    ) -> AsrFuture<'a>;
+}
+
+/// Decodes the chunk again with the session's engine in translate mode.
+pub struct WhisperTranslator;
+
+impl Translator for WhisperTranslator {
+    fn name(&self) -> &'static str {
+        "whisper"
+    }
+
+    fn translate<'a>(
+        &'a mut self,
+        engine: &'a mut dyn AsrEngine,
+        samples: &'a [i16],
+        _segments: &'a [AsrSegment],
+    ) -> AsrFuture<'a> {
+        Box::pin(async move {
+            engine.set_translate(true);
+            let result = engine.transcribe(samples).await;
+            engine.set_translate(false);
+            result
+        })
+    }
+}
+
+/// A session's translation settings and translator.
+pub struct Translation {
+    translator: Box<dyn Translator>,
+    target: String,
+    sources: Vec<String>,
+}
+
+impl Translation {
+    pub fn new(options: TranslationOptions) -> Result<Self, AppError> {
+        let target = options.target.trim().to_lowercase();
+        let translator: Box<dyn Translator> = match target.as_str() {
+            WHISPER_TARGET => Box::new(WhisperTranslator),
+            _ => {
+                return Err(AppError::InvalidInput(format!(
+                    "Cannot translate into \"{}\"; only English (\"en\") is supported",
+                    options.target
+                )))
+            }
+        };
+        Ok(Self {
+            translator,
+            target,
+            sources: options.sources,
+        })
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn translator_name(&self) -> &'static str {
+        self.translator.name()
+    }
+
+    /// Whether finals of `audio_source` ("microphone", "systemAudio") are
+    /// translated.
+    pub fn applies_to(&self, audio_source: &str) -> bool {
+        self.sources.is_empty() || self.sources.iter().any(|source| source == audio_source)
+    }
+
+    /// Fill in `translation` on `segments`, which may be `offset_ms` later
+    /// than the chunk's clock. Segments already in the target language keep
+    /// their text.
+    pub async fn translate(
+        &mut self,
+        engine: &mut dyn AsrEngine,
+        samples: &[i16],
+        segments: &mut [AsrSegment],
+        offset_ms: i64,
+    ) -> Result<(), AppError> {
+        let in_target = |segment: &AsrSegment| segment.language.as_deref() == Some(&self.target);
+        if segments.iter().all(in_target) {
+            for segment in segments.iter_mut() {
+                segment.translation = Some(segment.text.clone());
+            }
+            return Ok(());
+        }
+
+        let translated = self.translator.translate(engine, samples, segments).await?;
+        attach(segments, translated, offset_ms);
+        Ok(())
+    }
+}
+
+/// Give each translated segment to the source segment it overlaps most, or
+/// the nearest one, keeping their order.
+fn attach(segments: &mut [AsrSegment], translated: Vec<AsrSegment>, offset_ms: i64) {
+    if segments.is_empty() {
+        return;
+    }
+    for segment in segments.iter_mut() {
+        segment.translation = Some(String::new());
+    }
+
+    for part in translated {
+        let start = part.t_start_ms + offset_ms;
+        let end = part.t_end_ms + offset_ms;
+        let midpoint = (start + end) / 2;
+        let index = (0..segments.len())
+            .max_by_key(|&index| {
+                let segment = &segments[index];
+                let overlap = end.min(segment.t_end_ms) - start.max(segment.t_start_ms);
+                let distance = if midpoint < segment.t_start_ms {
+                    segment.t_start_ms - midpoint
+                } else {
+                    (midpoint - segment.t_end_ms).max(0)
+                };
+                (overlap.max(0), -distance)
+            })
+            .unwrap_or(0);
+        if let Some(translation) = segments[index].translation.as_mut() {
+            if !translation.is_empty() {
+                translation.push(' ');
+            }
+            translation.push_str(part.text.trim());
+        }
+    }
+
+    for segment in segments.iter_mut() {
+        if segment.translation.as_deref() == Some("") {
+            segment.translation = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asr::{segment, MockEngine};
+
+    fn translations(segments: &[AsrSegment]) -> Vec<Option<&str>> {
+        segments
+            .iter()
+            .map(|segment| segment.translation.as_deref())
+            .collect()
+    }
+
+    fn options(target: &str, sources: &[&str]) -> TranslationOptions {
+        TranslationOptions {
+            target: target.to_string(),
+            sources: sources.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn translated_parts_go_to_the_overlapping_or_nearest_segment() {
+        let mut segments = vec![
+            segment("Guten Morgen.", 500, 1_500),
+            segment("Wie geht's?", 1_500, 2_500),
+            segment("Tschüss.", 4_000, 5_000),
+        ];
+        attach(
+            &mut segments,
+            vec![
+                segment(" Good morning.", 0, 900),
+                segment("How are", 1_100, 1_600),
+                segment("you?", 2_100, 2_200),
+            ],
+            500,
+        );
+
+        assert_eq!(
+            translations(&segments),
+            [Some("Good morning."), Some("How are you?"), None]
+        );
+    }
+
+    #[test]
+    fn nothing_translated_leaves_no_translation() {
+        let mut segments = vec![segment("Hallo.", 0, 1_000)];
+        attach(&mut segments, Vec::new(), 0);
+        assert_eq!(translations(&segments), [None]);
+        attach(&mut [], vec![segment("Hello.", 0, 1_000)], 0);
+    }
+
+    #[test]
+    fn audio_is_translated_onto_the_segments_clock() {
+        let mut translation = Translation::new(options(" EN ", &[])).unwrap();
+        let mut engine = MockEngine::new("de".to_string());
+        let samples: Vec<i16> = (0..16_000)
+            .map(|n| ((n as f32 * 0.07).sin() * 8_000.0) as i16)
+            .collect();
+        let mut segments = vec![
+            segment("Hallo.", 9_000, 9_400),
+            segment("Ja.", 10_000, 11_000),
+        ];
+
+        tauri::async_runtime::block_on(translation.translate(
+            &mut engine,
+            &samples,
+            &mut segments,
+            10_000,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            translations(&segments),
+            [None, Some("Mock translation segment 0.")]
+        );
+    }
+
+    #[test]
+    fn segments_in_the_target_keep_their_text() {
+        let mut translation = Translation::new(options("en", &[])).unwrap();
+        let mut engine = MockEngine::new("en".to_string());
+        let mut segments = vec![AsrSegment {
+            language: Some("en".to_string()),
+            ..segment("Hello.", 0, 1_000)
+        }];
+
+        tauri::async_runtime::block_on(translation.translate(&mut engine, &[], &mut segments, 0))
+            .unwrap();
+
+        assert_eq!(translations(&segments), [Some("Hello.")]);
+    }
+
+    #[test]
+    fn only_english_and_the_chosen_sources_are_translated() {
+        assert_eq!(
+            Translation::new(options("de", &[])).err().map(|e| e.code()),
+            Some("invalidInput")
+        );
+        let translation = Translation::new(options("en", &["systemAudio"])).unwrap();
+        assert!(translation.applies_to("systemAudio"));
+        assert!(!translation.applies_to("microphone"));
+        assert!(Translation::new(options("en", &[]))
+            .unwrap()
+            .applies_to("microphone"));
+    }
+}
@@ -1,100 +1,303 @@
+use crate::asr::AsrWord;
+use crate::error::AppError;
 use serde::{Deserialize, Serialize};
-use tauri::AppHandle;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent};
 use tauri_plugin_shell::ShellExt;
 
+/// How long to wait for whisper-server to load the model and answer /health.
+const SERVER_READY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Upper bound for a single /inference request.
+const SERVER_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+/// Consecutive crashes tolerated before giving up on the server for the session.
+const SERVER_MAX_RESTARTS: u32 = 3;
+/// Ports tried when another process takes the picked port before the server
+/// binds it.
+const SERVER_BIND_ATTEMPTS: u32 = 3;
+/// Private directory, under the app cache, for chunks handed to the CLI.
+const TEMP_DIR: &str = "whisper-input";
+/// Temp files older than this belong to no running transcription.
+const TEMP_FILE_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
 /// Result from the whisper sidecar process.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WhisperResult {
     pub text: String,
     pub t_start_ms: i64,
     pub t_end_ms: i64,
+    /// Tokens with their timing and probability, not yet joined into words.
+    pub tokens: Vec<AsrWord>,
+    /// Language code whisper decoded the chunk in.
+    pub language: Option<String>,
+    pub language_probability: Option<f64>,
 }
 
-/// Manages the whisper.cpp sidecar process.
+/// Manages the whisper.cpp sidecar for one transcription session.
+///
+/// Chunks are sent to a long-lived `whisper-server` process so the model is
+/// only loaded once. The server is restarted if it crashes. If it cannot be
+/// spawned at all, each chunk falls back to a one-shot `whisper` CLI run.
 pub struct WhisperManager {
     model_path: String,
     language: String,
+    threads: usize,
+    /// Decode into English instead of transcribing.
+    translate: bool,
+    server: Option<ServerProcess>,
+    restarts: u32,
+    server_unavailable: bool,
 }
 
 impl WhisperManager {
-    pub fn new(model_path: String, language: String) -> Self {
+    pub fn new(model_path: String, language: String, threads: usize) -> Self {
         Self {
             model_path,
             language,
+            threads,
+            translate: false,
+            server: None,
+            restarts: 0,
+            server_unavailable: false,
         }
     }
 
-    pub fn model_path(&self) -> &str {
-        &self.model_path
+    /// Decode later chunks in `language`, or "auto" to detect it. Applies to
+    /// the running server without restarting it.
+    pub fn set_language(&mut self, language: &str) {
+        self.language = language.to_string();
+    }
+
+    /// Translate later chunks into English with whisper's translate task.
+    pub fn set_translate(&mut self, translate: bool) {
+        self.translate = translate;
+    }
+
+    /// Start whisper-server and wait for it to load the model, so a model it
+    /// cannot load fails here rather than on the first chunk. Without a
+    /// server binary this succeeds and the one-shot CLI is used instead.
+    pub async fn start(&mut self, app: &AppHandle) -> Result<(), AppError> {
+        match self.ensure_server(app).await {
+            Ok(_) => Ok(()),
+            Err(ServerError::Unavailable(error)) => {
+                log::warn!(
+                    "whisper-server unavailable; falling back to one-shot sidecar: {}",
+                    error
+                );
+                self.server_unavailable = true;
+                Ok(())
+            }
+            Err(ServerError::Failed(error)) => Err(error),
+        }
     }
 
-    pub fn language(&self) -> &str {
-        &self.language
+    /// Stop the whisper-server process, if one is running.
+    pub fn shutdown(&mut self) {
+        self.server = None;
     }
 
-    /// Transcribe a chunk of audio using the whisper sidecar.
+    /// Transcribe a chunk of audio.
     /// The audio should be 16kHz mono s16le PCM.
     pub async fn transcribe(
-        &self,
+        &mut self,
         app: &AppHandle,
         audio_samples: &[i16],
-    ) -> Result<Vec<WhisperResult>, String> {
+    ) -> Result<Vec<WhisperResult>, AppError> {
         if audio_samples.is_empty() {
             return Ok(Vec::new());
         }
 
-        // Write audio to a temp file for the sidecar
-        let temp_dir = std::env::temp_dir();
-        let temp_path = temp_dir.join("ainotes_whisper_input.wav");
-        write_wav(&temp_path, audio_samples, 16000)
-            .map_err(|e| format!("Failed to write temp WAV: {}", e))?;
+        if !self.server_unavailable {
+            match self.transcribe_with_server(app, audio_samples).await {
+                Ok(results) => return Ok(results),
+                Err(ServerError::Unavailable(error)) => {
+                    log::warn!(
+                        "whisper-server unavailable; falling back to one-shot sidecar: {}",
+                        error
+                    );
+                    self.server_unavailable = true;
+                }
+                Err(ServerError::Failed(error)) => return Err(error),
+            }
+        }
+
+        self.transcribe_one_shot(app, audio_samples).await
+    }
+
+    async fn transcribe_with_server(
+        &mut self,
+        app: &AppHandle,
+        audio_samples: &[i16],
+    ) -> Result<Vec<WhisperResult>, ServerError> {
+        let wav = encode_wav(audio_samples, 16000);
+        let (http, port) = self.ensure_server(app).await?;
+
+        let result =
+            match post_inference(&http, port, wav.clone(), &self.language, self.translate).await {
+                Err(error) if !self.server_alive() => {
+                    // The server died mid-request; restart it and retry the chunk once.
+                    log::warn!("whisper-server crashed during inference: {}", error);
+                    let (http, port) = self.ensure_server(app).await?;
+                    post_inference(&http, port, wav, &self.language, self.translate).await
+                }
+                other => other,
+            };
+
+        // A server that died again is restarted for the next chunk, until
+        // its restarts run out.
+        let segments =
+            result.map_err(|error| ServerError::Failed(AppError::TranscriptionFailed(error)))?;
+        self.restarts = 0;
+        Ok(segments)
+    }
+
+    fn server_alive(&self) -> bool {
+        self.server.as_ref().is_some_and(|server| server.is_alive())
+    }
+
+    /// Return the client and port of a ready server, spawning or restarting
+    /// it as needed.
+    async fn ensure_server(
+        &mut self,
+        app: &AppHandle,
+    ) -> Result<(reqwest::Client, u16), ServerError> {
+        let model_path = self.model_path.clone();
+        let language = self.language.clone();
+        let threads = self.threads;
+        self.ensure_server_with(|| ServerProcess::spawn(app, &model_path, &language, threads))
+            .await
+    }
+
+    /// `ensure_server` with the process spawn supplied by the caller. Every
+    /// crash and every failed launch counts toward `SERVER_MAX_RESTARTS`;
+    /// only a successful inference resets the count.
+    async fn ensure_server_with(
+        &mut self,
+        spawn: impl FnMut() -> Result<ServerProcess, String>,
+    ) -> Result<(reqwest::Client, u16), ServerError> {
+        if let Some(server) = &self.server {
+            if server.is_alive() {
+                return Ok((server.http.clone(), server.port));
+            }
+            self.restarts += 1;
+            log::warn!(
+                "whisper-server exited unexpectedly; restarting (attempt {})",
+                self.restarts
+            );
+        }
+        self.server = None;
+
+        if self.restarts > SERVER_MAX_RESTARTS {
+            return Err(ServerError::Failed(AppError::SidecarCrashed(format!(
+                "whisper-server failed {} times in a row",
+                self.restarts
+            ))));
+        }
+
+        match launch_server(spawn).await {
+            Ok(server) => {
+                log::info!("whisper-server ready on 127.0.0.1:{}", server.port);
+                let ready = (server.http.clone(), server.port);
+                self.server = Some(server);
+                Ok(ready)
+            }
+            Err(error) => {
+                self.restarts += 1;
+                match error {
+                    // Whatever the last launch died of, the server is done
+                    // for this session.
+                    ServerError::Failed(error) if self.restarts > SERVER_MAX_RESTARTS => {
+                        Err(ServerError::Failed(AppError::SidecarCrashed(format!(
+                            "whisper-server failed {} times in a row: {}",
+                            self.restarts, error
+                        ))))
+                    }
+                    error => Err(error),
+                }
+            }
+        }
+    }
+
+    /// Transcribe a chunk by spawning the one-shot whisper CLI sidecar.
+    async fn transcribe_one_shot(
+        &self,
+        app: &AppHandle,
+        audio_samples: &[i16],
+    ) -> Result<Vec<WhisperResult>, AppError> {
+        // Write audio to a temp file for the sidecar; it and the CLI's JSON
+        // output are deleted when `input` drops, whatever happens below.
+        let input = TempInput::create(app, audio_samples)
+            .map_err(|e| AppError::Internal(format!("Failed to write temp WAV: {}", e)))?;
 
         // Build sidecar arguments
         let mut args = vec![
             "--model".to_string(),
             self.model_path.clone(),
-            "--output-json".to_string(),
+            "--output-json-full".to_string(),
             "--threads".to_string(),
-            "4".to_string(),
+            self.threads.to_string(),
             "--file".to_string(),
-            temp_path.to_string_lossy().to_string(),
+            input.wav.to_string_lossy().to_string(),
         ];
 
         if self.language != "auto" {
             args.push("--language".to_string());
             args.push(self.language.clone());
         }
+        if self.translate {
+            args.push("--translate".to_string());
+        }
 
         // Spawn whisper sidecar
         let shell = app.shell();
         let output = shell
             .sidecar("whisper")
-            .map_err(|e| format!("Failed to create sidecar: {}", e))?
+            .map_err(|e| AppError::SidecarCrashed(format!("Failed to create sidecar: {}", e)))?
             .args(&args)
             .output()
             .await
-            .map_err(|e| format!("Sidecar execution failed: {}", e))?;
+            .map_err(|e| AppError::SidecarCrashed(format!("Sidecar execution failed: {}", e)))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("Whisper failed: {}", stderr));
+            return Err(AppError::TranscriptionFailed(format!(
+                "Whisper failed: {}",
+                stderr
+            )));
         }
 
-        // whisper.cpp --output-json writes a .json file next to the input
-        let json_path = temp_path.with_extension("wav.json");
-        let segments: Vec<WhisperSegment> = std::fs::read_to_string(&json_path)
+        let mut language = None;
+        let segments: Vec<WhisperSegment> = std::fs::read_to_string(&input.json)
             .ok()
             .and_then(|json_str| {
                 // The JSON file has { "transcription": [ { "timestamps": {...}, "text": "..." }, ... ] }
                 serde_json::from_str::<WhisperJsonOutput>(&json_str)
                     .ok()
                     .map(|out| {
+                        language = out
+                            .result
+                            .and_then(|result| crate::language::to_code(&result.language));
                         out.transcription
                             .into_iter()
                             .map(|seg| WhisperSegment {
                                 text: seg.text,
                                 t0: parse_timestamp_ms(&seg.timestamps.from),
                                 t1: parse_timestamp_ms(&seg.timestamps.to),
+                                tokens: seg
+                                    .tokens
+                                    .into_iter()
+                                    .map(|token| AsrWord {
+                                        text: token.text,
+                                        t_start_ms: token.offsets.from,
+                                        t_end_ms: token.offsets.to,
+                                        probability: token.p,
+                                    })
+                                    .collect(),
                             })
                             .collect()
                     })
@@ -106,24 +309,349 @@ impl WhisperManager {
                 if text.is_empty() {
                     Vec::new()
                 } else {
-                    vec![WhisperSegment { text, t0: 0, t1: 0 }]
+                    vec![WhisperSegment {
+                        text,
+                        t0: 0,
+                        t1: 0,
+                        tokens: Vec::new(),
+                    }]
                 }
             });
 
-        // Clean up temp files
-        let _ = std::fs::remove_file(&temp_path);
-        let _ = std::fs::remove_file(&json_path);
+        // The CLI reports no language probability.
+        Ok(into_results(segments, language, None))
+    }
+}
+
+enum ServerError {
+    /// The server binary could not be spawned at all.
+    Unavailable(String),
+    /// The server was spawned but failed to load or to answer a request.
+    Failed(AppError),
+}
+
+/// A running `whisper-server` sidecar bound to a localhost port.
+/// The process is killed when this is dropped.
+struct ServerProcess {
+    port: u16,
+    http: reqwest::Client,
+    child: Option<CommandChild>,
+    alive: Arc<AtomicBool>,
+    /// The server reported that it could not listen on `port`.
+    bind_failed: Arc<AtomicBool>,
+}
+
+impl ServerProcess {
+    fn spawn(
+        app: &AppHandle,
+        model_path: &str,
+        language: &str,
+        threads: usize,
+    ) -> Result<Self, String> {
+        let port = pick_free_port()?;
+        let http = reqwest::Client::builder()
+            .no_proxy()
+            .connect_timeout(Duration::from_secs(2))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        let mut args = vec![
+            "--model".to_string(),
+            model_path.to_string(),
+            "--host".to_string(),
+            "127.0.0.1".to_string(),
+            "--port".to_string(),
+            port.to_string(),
+            "--threads".to_string(),
+            threads.to_string(),
+        ];
+
+        if language != "auto" {
+            args.push("--language".to_string());
+            args.push(language.to_string());
+        }
+
+        let (mut events, child) = app
+            .shell()
+            .sidecar("whisper-server")
+            .map_err(|e| format!("Failed to create sidecar: {}", e))?
+            .args(&args)
+            .spawn()
+            .map_err(|e| format!("Failed to spawn whisper-server: {}", e))?;
 
-        Ok(segments
+        let alive = Arc::new(AtomicBool::new(true));
+        let bind_failed = Arc::new(AtomicBool::new(false));
+        let alive_flag = alive.clone();
+        let bind_failed_flag = bind_failed.clone();
+        tauri::async_runtime::spawn(async move {
+            while let Some(event) = events.recv().await {
+                match event {
+                    CommandEvent::Stderr(line) => {
+                        let line = String::from_utf8_lossy(&line);
+                        if line.contains("couldn't bind") {
+                            bind_failed_flag.store(true, Ordering::SeqCst);
+                        }
+                        log::debug!("whisper-server: {}", line.trim_end());
+                    }
+                    CommandEvent::Error(error) => {
+                        log::error!("whisper-server error: {}", error);
+                    }
+                    CommandEvent::Terminated(payload) => {
+                        log::warn!(
+                            "whisper-server terminated (code {:?}, signal {:?})",
+                            payload.code,
+                            payload.signal
+                        );
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            alive_flag.store(false, Ordering::SeqCst);
+        });
+
+        Ok(Self {
+            port,
+            http,
+            child: Some(child),
+            alive,
+            bind_failed,
+        })
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    fn bind_failed(&self) -> bool {
+        self.bind_failed.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.take() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Spawn a server and wait until it is ready, retrying on another port when
+/// the picked one is taken before the server binds it.
+async fn launch_server(
+    mut spawn: impl FnMut() -> Result<ServerProcess, String>,
+) -> Result<ServerProcess, ServerError> {
+    let mut attempt = 1;
+    loop {
+        let server = spawn().map_err(ServerError::Unavailable)?;
+        match wait_until_ready(&server).await {
+            Ok(()) => return Ok(server),
+            Err(_) if server.bind_failed() && attempt < SERVER_BIND_ATTEMPTS => {
+                log::warn!(
+                    "whisper-server could not bind port {}; retrying on another",
+                    server.port
+                );
+                attempt += 1;
+            }
+            Err(error) => return Err(ServerError::Failed(error)),
+        }
+    }
+}
+
+/// Ask the OS for an unused localhost port. It is released again before the
+/// server binds it, so `ensure_server` retries when another process wins.
+fn pick_free_port() -> Result<u16, String> {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("Failed to reserve a local port: {}", e))
+}
+
+/// Poll /health until the model is loaded or the process dies.
+async fn wait_until_ready(server: &ServerProcess) -> Result<(), AppError> {
+    let deadline = Instant::now() + SERVER_READY_TIMEOUT;
+    let url = format!("http://127.0.0.1:{}/health", server.port);
+
+    loop {
+        if !server.is_alive() {
+            return Err(AppError::ModelLoadFailed(
+                "whisper-server exited while loading the model".to_string(),
+            ));
+        }
+
+        let probe = server
+            .http
+            .get(&url)
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await;
+        if probe.is_ok_and(|response| response.status() == reqwest::StatusCode::OK) {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(AppError::Timeout(
+                "Timed out waiting for whisper-server to load the model".to_string(),
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// POST a WAV chunk to the server's /inference endpoint.
+async fn post_inference(
+    http: &reqwest::Client,
+    port: u16,
+    wav: Vec<u8>,
+    language: &str,
+    translate: bool,
+) -> Result<Vec<WhisperResult>, String> {
+    let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let boundary = format!("----ainotes{:x}", nonce);
+    let body = multipart_body(&boundary, &wav, language, translate);
+    let content_type = format!("multipart/form-data; boundary={}", boundary);
+
+    let response = http
+        .post(format!("http://127.0.0.1:{}/inference", port))
+        .header(reqwest::header::CONTENT_TYPE, content_type)
+        .body(body)
+        .timeout(SERVER_REQUEST_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send request to whisper-server: {}", e))?;
+    let status = response.status();
+    let response = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read whisper-server response: {}", e))?;
+
+    if !status.is_success() {
+        return Err(format!(
+            "whisper-server returned HTTP {}: {}",
+            status,
+            String::from_utf8_lossy(&response)
+        ));
+    }
+
+    let output: ServerInferenceOutput = serde_json::from_slice(&response)
+        .map_err(|e| format!("Invalid whisper-server response: {}", e))?;
+
+    let language = output
+        .detected_language
+        .or(output.language)
+        .and_then(|language| crate::language::to_code(&language));
+    Ok(into_results(
+        output
+            .segments
             .into_iter()
-            .filter(|s| !s.text.trim().is_empty())
-            .map(|s| WhisperResult {
-                text: s.text.trim().to_string(),
-                t_start_ms: s.t0,
-                t_end_ms: s.t1,
+            .map(|seg| WhisperSegment {
+                text: seg.text,
+                t0: (seg.start * 1000.0).round() as i64,
+                t1: (seg.end * 1000.0).round() as i64,
+                tokens: seg
+                    .words
+                    .into_iter()
+                    .map(|word| AsrWord {
+                        text: word.word,
+                        t_start_ms: (word.start * 1000.0).round() as i64,
+                        t_end_ms: (word.end * 1000.0).round() as i64,
+                        probability: word.probability,
+                    })
+                    .collect(),
             })
-            .collect())
+            .collect(),
+        language,
+        output.detected_language_probability,
+    ))
+}
+
+fn multipart_body(boundary: &str, wav: &[u8], language: &str, translate: bool) -> Vec<u8> {
+    let mut body = Vec::with_capacity(wav.len() + 512);
+    for (name, value) in [
+        ("response_format", "verbose_json"),
+        ("temperature", "0.0"),
+        ("language", language),
+        ("translate", if translate { "true" } else { "false" }),
+    ] {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+            .as_bytes(),
+        );
     }
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"chunk.wav\"\r\nContent-Type: audio/wav\r\n\r\n",
+            boundary
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(wav);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+fn into_results(
+    segments: Vec<WhisperSegment>,
+    language: Option<String>,
+    language_probability: Option<f64>,
+) -> Vec<WhisperResult> {
+    segments
+        .into_iter()
+        .filter(|s| !s.text.trim().is_empty())
+        .map(|s| WhisperResult {
+            text: s.text.trim().to_string(),
+            t_start_ms: s.t0,
+            t_end_ms: s.t1,
+            tokens: s.tokens,
+            language: language.clone(),
+            language_probability,
+        })
+        .collect()
+}
+
+/// Response body of whisper-server's /inference with `response_format=verbose_json`.
+#[derive(Debug, Deserialize)]
+struct ServerInferenceOutput {
+    #[serde(default)]
+    segments: Vec<ServerSegment>,
+    /// Language name ("english") the chunk was decoded in.
+    #[serde(default)]
+    language: Option<String>,
+    /// Present when the server detected the language itself.
+    #[serde(default)]
+    detected_language: Option<String>,
+    #[serde(default)]
+    detected_language_probability: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerSegment {
+    text: String,
+    /// Seconds from the start of the uploaded chunk.
+    #[serde(default)]
+    start: f64,
+    #[serde(default)]
+    end: f64,
+    /// Tokens, despite the name; present with token timestamps.
+    #[serde(default)]
+    words: Vec<ServerWord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerWord {
+    word: String,
+    #[serde(default)]
+    start: f64,
+    #[serde(default)]
+    end: f64,
+    #[serde(default)]
+    probability: f64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -133,18 +661,45 @@ struct WhisperSegment {
     t0: i64,
     #[serde(default)]
     t1: i64,
+    #[serde(default)]
+    tokens: Vec<AsrWord>,
 }
 
-/// Top-level JSON output from whisper.cpp --output-json
+/// Top-level JSON output from whisper.cpp --output-json-full
 #[derive(Debug, Deserialize)]
 struct WhisperJsonOutput {
+    #[serde(default)]
+    result: Option<WhisperJsonResult>,
     transcription: Vec<WhisperJsonSegment>,
 }
 
+#[derive(Debug, Deserialize)]
+struct WhisperJsonResult {
+    /// Language code, e.g. "en".
+    language: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct WhisperJsonSegment {
     timestamps: WhisperTimestamps,
     text: String,
+    #[serde(default)]
+    tokens: Vec<WhisperJsonToken>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhisperJsonToken {
+    text: String,
+    offsets: WhisperOffsets,
+    #[serde(default)]
+    p: f64,
+}
+
+/// Milliseconds from the start of the input.
+#[derive(Debug, Deserialize)]
+struct WhisperOffsets {
+    from: i64,
+    to: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -189,36 +744,350 @@ fn strip_timestamps(text: &str) -> String {
         .join(" ")
 }
 
-/// Write PCM samples to a WAV file.
-fn write_wav(path: &std::path::Path, samples: &[i16], sample_rate: u32) -> std::io::Result<()> {
-    use std::io::Write;
+/// A chunk written for the one-shot CLI, plus the JSON it writes next to it.
+/// Both are removed on drop.
+struct TempInput {
+    wav: PathBuf,
+    json: PathBuf,
+}
+
+impl TempInput {
+    fn create(app: &AppHandle, samples: &[i16]) -> std::io::Result<Self> {
+        Self::create_in(&temp_dir(app)?, samples)
+    }
+
+    fn create_in(dir: &Path, samples: &[i16]) -> std::io::Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        create_private_dir(dir)?;
+
+        // Unique across concurrent chunks and app instances.
+        let name = format!(
+            "chunk-{}-{}-{}",
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let input = Self {
+            wav: dir.join(format!("{}.wav", name)),
+            // whisper.cpp --output-json writes a .json file next to the input
+            json: dir.join(format!("{}.wav.json", name)),
+        };
+        write_private(&input.wav, &encode_wav(samples, 16000))?;
+        Ok(input)
+    }
+}
+
+impl Drop for TempInput {
+    fn drop(&mut self) {
+        for path in [&self.wav, &self.json] {
+            if let Err(error) = std::fs::remove_file(path) {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("Failed to remove {}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+}
+
+fn temp_dir(app: &AppHandle) -> std::io::Result<PathBuf> {
+    app.path()
+        .app_cache_dir()
+        .map(|dir| dir.join(TEMP_DIR))
+        .map_err(std::io::Error::other)
+}
+
+/// Create `dir` readable by the current user only.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)?;
+    // `mode` does not apply to a directory that already existed.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Write a new file readable by the current user only.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(contents)
+}
+
+/// Remove chunks left behind by a crash or forced quit, and the shared temp
+/// file older versions wrote. Recent files may belong to another running
+/// instance and are kept.
+pub fn sweep_temp_files(app: &AppHandle) {
+    let legacy = std::env::temp_dir().join("ainotes_whisper_input.wav");
+    for path in [legacy.with_extension("wav.json"), legacy] {
+        let _ = std::fs::remove_file(path);
+    }
+
+    if let Ok(dir) = temp_dir(app) {
+        sweep_temp_dir(&dir);
+    }
+}
+
+/// Remove files in `dir` older than `TEMP_FILE_MAX_AGE`.
+fn sweep_temp_dir(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let orphaned = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= TEMP_FILE_MAX_AGE);
+        if !orphaned {
+            continue;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => log::info!("Removed orphaned temp file {}", path.display()),
+            Err(error) => log::warn!("Failed to remove {}: {}", path.display(), error),
+        }
+    }
+}
 
+/// Encode PCM samples as an in-memory 16-bit mono WAV file.
+fn encode_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
     let data_size = (samples.len() * 2) as u32;
     let file_size = 36 + data_size;
 
-    let mut file = std::fs::File::create(path)?;
+    let mut wav = Vec::with_capacity(44 + samples.len() * 2);
 
     // RIFF header
-    file.write_all(b"RIFF")?;
-    file.write_all(&file_size.to_le_bytes())?;
-    file.write_all(b"WAVE")?;
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&file_size.to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
 
     // fmt chunk
-    file.write_all(b"fmt ")?;
-    file.write_all(&16u32.to_le_bytes())?; // chunk size
-    file.write_all(&1u16.to_le_bytes())?; // PCM format
-    file.write_all(&1u16.to_le_bytes())?; // mono
-    file.write_all(&sample_rate.to_le_bytes())?;
-    file.write_all(&(sample_rate * 2).to_le_bytes())?; // byte rate
-    file.write_all(&2u16.to_le_bytes())?; // block align
-    file.write_all(&16u16.to_le_bytes())?; // bits per sample
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM format
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
 
     // data chunk
-    file.write_all(b"data")?;
-    file.write_all(&data_size.to_le_bytes())?;
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
     for &sample in samples {
-        file.write_all(&sample.to_le_bytes())?;
+        wav.extend_from_slice(&sample.to_le_bytes());
     }
 
-    Ok(())
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// A server whose process is already gone, or one that answers every
+    /// request with 200 OK.
+    fn stub_server(healthy: bool) -> ServerProcess {
+        let port = if healthy {
+            let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+            let port = listener.local_addr().unwrap().port();
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let _ = stream.read(&mut [0; 1024]);
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+                }
+            });
+            port
+        } else {
+            pick_free_port().unwrap()
+        };
+        ServerProcess {
+            port,
+            http: reqwest::Client::builder().no_proxy().build().unwrap(),
+            child: None,
+            alive: Arc::new(AtomicBool::new(healthy)),
+            bind_failed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("whisper-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn manager() -> WhisperManager {
+        WhisperManager::new("model.bin".to_string(), "auto".to_string(), 1)
+    }
+
+    #[test]
+    fn failed_launches_escalate_to_a_crash() {
+        tauri::async_runtime::block_on(async {
+            let mut whisper = manager();
+            let mut spawned = 0;
+            for _ in 0..SERVER_MAX_RESTARTS {
+                let result = whisper
+                    .ensure_server_with(|| {
+                        spawned += 1;
+                        Ok(stub_server(false))
+                    })
+                    .await;
+                assert!(matches!(
+                    result,
+                    Err(ServerError::Failed(AppError::ModelLoadFailed(_)))
+                ));
+            }
+
+            for _ in 0..2 {
+                let result = whisper
+                    .ensure_server_with(|| {
+                        spawned += 1;
+                        Ok(stub_server(false))
+                    })
+                    .await;
+                assert!(matches!(
+                    result,
+                    Err(ServerError::Failed(AppError::SidecarCrashed(_)))
+                ));
+            }
+            // Once the restarts run out nothing is spawned again.
+            assert_eq!(spawned, SERVER_MAX_RESTARTS + 1);
+        });
+    }
+
+    #[test]
+    fn crashes_and_failed_restarts_share_one_count() {
+        tauri::async_runtime::block_on(async {
+            let mut whisper = manager();
+            for _ in 0..SERVER_MAX_RESTARTS {
+                assert!(whisper
+                    .ensure_server_with(|| Ok(stub_server(true)))
+                    .await
+                    .is_ok());
+                // The server dies; the next call restarts it.
+                whisper
+                    .server
+                    .as_ref()
+                    .unwrap()
+                    .alive
+                    .store(false, Ordering::SeqCst);
+            }
+
+            // A ready server does not reset the count, so one more failure
+            // ends it.
+            let result = whisper.ensure_server_with(|| Ok(stub_server(false))).await;
+            assert!(matches!(
+                result,
+                Err(ServerError::Failed(AppError::SidecarCrashed(_)))
+            ));
+        });
+    }
+
+    #[test]
+    fn unspawnable_server_is_unavailable() {
+        tauri::async_runtime::block_on(async {
+            let mut whisper = manager();
+            let result = whisper
+                .ensure_server_with(|| Err("no binary".to_string()))
+                .await;
+            assert!(matches!(result, Err(ServerError::Unavailable(_))));
+        });
+    }
+
+    #[test]
+    fn concurrent_inputs_get_unique_files() {
+        let dir = temp_dir("unique");
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    (0..20)
+                        .map(|_| TempInput::create_in(&dir, &[1, 2, 3]).unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        let inputs: Vec<TempInput> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+
+        let mut names: Vec<_> = inputs.iter().map(|input| input.wav.clone()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 80);
+        assert!(inputs.iter().all(|input| input.wav.is_file()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn inputs_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("private").join("input");
+        let input = TempInput::create_in(&dir, &[1, 2, 3]).unwrap();
+
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&input.wav), 0o600);
+    }
+
+    #[test]
+    fn inputs_are_removed_on_drop() {
+        let dir = temp_dir("drop");
+        let input = TempInput::create_in(&dir, &[1, 2, 3]).unwrap();
+        std::fs::write(&input.json, "{}").unwrap();
+        let (wav, json) = (input.wav.clone(), input.json.clone());
+        drop(input);
+        assert!(!wav.exists());
+        assert!(!json.exists());
+
+        // A run that fails after writing its input leaves nothing behind.
+        let mut created = None;
+        let result = (|| -> std::io::Result<()> {
+            let input = TempInput::create_in(&dir, &[1, 2, 3])?;
+            created = Some(input.wav.clone());
+            std::fs::read_to_string(&input.json)?;
+            Ok(())
+        })();
+        assert!(result.is_err());
+        assert!(!created.unwrap().exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn sweep_removes_only_old_files() {
+        let dir = temp_dir("sweep");
+        let old = dir.join("chunk-old.wav");
+        let fresh = dir.join("chunk-fresh.wav");
+        std::fs::write(&old, b"old").unwrap();
+        std::fs::write(&fresh, b"fresh").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - TEMP_FILE_MAX_AGE - Duration::from_secs(60))
+            .unwrap();
+
+        sweep_temp_dir(&dir);
+        assert!(!old.exists());
+        assert!(fresh.exists());
+    }
 }
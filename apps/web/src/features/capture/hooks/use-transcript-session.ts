@@ -310,6 +310,7 @@ export function useTranscriptSession(
   const providerRef = useRef<ASRProvider | null>(null);
   const unsubscribeRef = useRef<(() => void) | null>(null);
   const sequenceRef = useRef(0);
+  const partialSequenceRef = useRef<number | null>(null);
   const sessionIdRef = useRef<string | null>(null);
   const noteIdRef = useRef<string | null>(options?.noteId ?? null);
   const pendingChunksRef = useRef<TranscriptSessionChunk[]>([]);
@@ -370,7 +371,13 @@ export function useTranscriptSession(
           break;
 
         case "ASR_PARTIAL":
-          setPartialText(event.text);
+          if (event.text) {
+            partialSequenceRef.current = event.sequence ?? null;
+            setPartialText(event.text);
+          } else if (partialSequenceRef.current === (event.sequence ?? null)) {
+            // An empty partial withdraws one whose words were dropped.
+            setPartialText(null);
+          }
           break;
 
         case "ASR_FINAL": {
@@ -5,7 +5,13 @@
  * and whisper.cpp transcription, and listening to Tauri events for results.
  */
 
-import type { ASREvent, ASROptions, ASRProvider } from "@ainotes/core";
+import type {
+  ASREvent,
+  ASRInstalledModel,
+  ASROptions,
+  ASRProvider,
+  ASRStopOptions,
+} from "@ainotes/core";
 
 type EventHandler = (event: ASREvent) => void;
 
@@ -27,29 +33,28 @@ export class DesktopASRProvider implements ASRProvider {
   private listeners = new Set<EventHandler>();
   private ready = false;
   private unlistenFn: (() => void) | null = null;
-  private modelPath = "";
+  /** `null` lets the desktop app use its default model. */
+  private modelPath: string | null = null;
 
   async initialize(
     modelId: string,
     onProgress?: (pct: number) => void,
   ): Promise<void> {
-    // Desktop whisper.cpp doesn't need to download a model in the browser.
-    // The model is managed by the Tauri sidecar / local filesystem.
-    // We try to resolve the model path if @tauri-apps/api is available,
-    // but it's not critical — the sidecar handles model resolution.
+    // Models are downloaded and verified by the desktop app's model manager
+    // (list_available_models / download_model), not in the browser. Use the
+    // requested model if it is installed; otherwise the app's default model.
     try {
-      // eslint-disable-next-line @typescript-eslint/no-explicit-any
-      const pathApi: any = await import("@tauri-apps/api/path");
-      const resDir: string = await pathApi.resourceDir();
-      this.modelPath = await pathApi.join(
-        resDir,
-        "models",
-        `ggml-${modelId}.bin`,
-      );
+      const { invoke } = (await import("@tauri-apps/api/core")) as {
+        invoke: TauriInvoke;
+      };
+      const installed = (await invoke(
+        "list_installed_models",
+      )) as ASRInstalledModel[];
+      this.modelPath =
+        installed.find((model) => model.id === modelId)?.path ?? null;
     } catch {
-      // @tauri-apps/api not available or not in Tauri context;
-      // sidecar will use its own default model path
-      this.modelPath = `models/ggml-${modelId}.bin`;
+      // Not in a Tauri context; start_transcription will report the model.
+      this.modelPath = null;
     }
 
     onProgress?.(100);
@@ -83,6 +88,19 @@ export class DesktopASRProvider implements ASRProvider {
       modelPath: this.modelPath,
       language: options.language,
       enableSystemAudio: options.enableSystemAudio ?? true,
+      streaming: options.streaming ?? false,
+      capture: {
+        processing: {
+          echoCancellation: options.echoCancellation ?? true,
+          noiseSuppression: options.noiseSuppression ?? false,
+          highPassFilter: options.highPassFilter ?? false,
+          automaticGainControl: options.automaticGainControl ?? false,
+        },
+        recording: options.recording ?? null,
+        journal: options.journal ?? null,
+        languagePolicy: options.languagePolicy ?? null,
+        translation: options.translation ?? null,
+      },
     });
 
     if (options.enableSystemAudio) {
@@ -95,11 +113,12 @@ export class DesktopASRProvider implements ASRProvider {
     }
   }
 
-  async stopListening(): Promise<void> {
+  async stopListening(options?: ASRStopOptions): Promise<void> {
     const { invoke } = (await import("@tauri-apps/api/core")) as {
       invoke: TauriInvoke;
     };
-    await invoke("stop_transcription");
+    // Resolves once the buffered audio's finals have been emitted.
+    await invoke("stop_transcription", { cancel: options?.cancel ?? false });
     this.cleanup();
   }
 
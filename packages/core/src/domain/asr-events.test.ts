@@ -39,6 +39,24 @@ describe("isASRStatusEvent", () => {
     };
     expect(isASRStatusEvent(event)).toBe(false);
   });
+
+  it("carries a language report when the detected language locks", () => {
+    const event: ASREvent = {
+      type: "ASR_STATUS",
+      state: "listening",
+      message: "Language locked to German",
+      language: {
+        language: "de",
+        previousLanguage: "en",
+        probability: 0.93,
+        locked: true,
+      },
+    };
+    expect(isASRStatusEvent(event)).toBe(true);
+    if (isASRStatusEvent(event)) {
+      expect(event.language?.locked).toBe(true);
+    }
+  });
 });
 
 // ─── isASRPartialEvent ───
@@ -102,4 +120,44 @@ describe("isASRFinalEvent", () => {
     expect(event.speaker).toBeNull();
     expect(event.confidence).toBeNull();
   });
+
+  it("carries timed words with probabilities", () => {
+    const event: ASREvent = {
+      type: "ASR_FINAL",
+      text: "hello world",
+      tStartMs: 1000,
+      tEndMs: 2000,
+      speaker: null,
+      confidence: 0.8,
+      sequence: 2,
+      words: [
+        { text: "hello", tStartMs: 1000, tEndMs: 1400, probability: 0.9 },
+        { text: "world", tStartMs: 1400, tEndMs: 2000, probability: 0.7 },
+      ],
+    };
+    expect(isASRFinalEvent(event)).toBe(true);
+    if (isASRFinalEvent(event)) {
+      expect(event.words?.map((w) => w.text)).toEqual(["hello", "world"]);
+    }
+  });
+
+  it("carries an English translation next to the source text", () => {
+    const event: ASREvent = {
+      type: "ASR_FINAL",
+      text: "Hola, ¿cómo estás?",
+      tStartMs: 0,
+      tEndMs: 1500,
+      speaker: null,
+      speakerRole: "CLIENT",
+      audioSource: "systemAudio",
+      confidence: 0.9,
+      language: "es",
+      translation: "Hi, how are you?",
+      sequence: 3,
+    };
+    expect(isASRFinalEvent(event)).toBe(true);
+    if (isASRFinalEvent(event)) {
+      expect(event.translation).toBe("Hi, how are you?");
+    }
+  });
 });
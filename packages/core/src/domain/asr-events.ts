@@ -7,18 +7,46 @@ export type ASRState =
   | "paused"
   | "processing"
   | "stopped"
+  | "warning"
   | "error";
 
+/** Attached to ASR_STATUS when transcription falls behind real time. */
+export interface ASRLagReport {
+  /** Smoothed decode time divided by audio duration; above 1 is lagging. */
+  readonly realtimeFactor: number;
+  readonly queuedMs: number;
+  readonly droppedMs: number;
+  readonly stage: "normal" | "reducedWindows" | "smallerModel";
+}
+
 export interface ASRStatusEvent {
   readonly type: "ASR_STATUS";
   readonly state: ASRState;
   readonly message: string;
+  readonly lag?: ASRLagReport;
+  readonly language?: ASRLanguageReport;
+}
+
+/** Attached to ASR_STATUS when an "auto" session's language changes or locks. */
+export interface ASRLanguageReport {
+  readonly language: string; // code, e.g. "de"
+  readonly previousLanguage: string | null;
+  readonly probability: number | null;
+  readonly locked: boolean; // the rest of the session is decoded in `language`
 }
 
 export interface ASRPartialEvent {
   readonly type: "ASR_PARTIAL";
   readonly text: string;
   readonly tStartMs: number;
+  readonly tEndMs?: number;
+  readonly speakerRole?: "SALES" | "CLIENT" | "UNKNOWN";
+  readonly audioSource?: "microphone" | "systemAudio" | "tabAudio";
+  /**
+   * Sequence of the ASR_FINAL that will replace this partial. An empty
+   * `text` withdraws the partial with this sequence instead.
+   */
+  readonly sequence?: number;
 }
 
 export interface ASRFinalEvent {
@@ -33,11 +61,81 @@ export interface ASRFinalEvent {
   readonly prosodyPauseRatio?: number;
   readonly prosodyVoicedMs?: number;
   readonly prosodySnrDb?: number;
-  readonly confidence: number | null;
+  readonly prosodyRawSnrDb?: number; // before noise suppression/AGC, when enabled
+  readonly confidence: number | null; // mean probability of `words`
+  readonly language?: string | null;
+  readonly languageProbability?: number | null; // only when the language was detected
+  readonly translation?: string | null; // `text` in the translation target, when its source is translated
   readonly sequence: number;
+  readonly words?: ASRWord[]; // empty when the engine gives no token timing
+}
+
+/** A word of an ASR_FINAL, for highlighting uncertain words and seeking. */
+export interface ASRWord {
+  readonly text: string;
+  readonly tStartMs: number;
+  readonly tEndMs: number;
+  readonly probability: number; // 0..1
+}
+
+export type ASRErrorCode =
+  | "deviceUnavailable"
+  | "permissionDenied"
+  | "modelMissing"
+  | "modelLoadFailed"
+  | "downloadFailed"
+  | "sidecarCrashed"
+  | "timeout"
+  | "invalidState"
+  | "transcriptionFailed"
+  | "invalidInput"
+  | "internal";
+
+/** What the UI should offer the user to recover from an error. */
+export type ASRRecoveryAction =
+  | "chooseDevice"
+  | "grantPermission"
+  | "downloadModel"
+  | "restart"
+  | "retry"
+  | "none";
+
+/** Shape of ASR_ERROR events and of rejected desktop commands. */
+export interface ASRError {
+  readonly code: ASRErrorCode;
+  readonly message: string;
+  readonly recovery: ASRRecoveryAction;
+}
+
+export interface ASRErrorEvent extends ASRError {
+  readonly type: "ASR_ERROR";
+  /** Source whose audio could not be transcribed, if any. */
+  readonly audioSource?: "microphone" | "systemAudio" | null;
+}
+
+/** Desktop transcription session lifecycle. */
+export type ASRSessionPhase =
+  | "idle"
+  | "starting"
+  | "listening"
+  | "paused"
+  | "draining"
+  | "stopped"
+  | "failed";
+
+/** Emitted on every session phase transition (desktop only). */
+export interface ASRSessionEvent {
+  readonly type: "ASR_SESSION";
+  readonly phase: ASRSessionPhase;
+  readonly previousPhase: ASRSessionPhase;
 }
 
-export type ASREvent = ASRStatusEvent | ASRPartialEvent | ASRFinalEvent;
+export type ASREvent =
+  | ASRStatusEvent
+  | ASRPartialEvent
+  | ASRFinalEvent
+  | ASRErrorEvent
+  | ASRSessionEvent;
 
 // ─── Type guards ───
 
@@ -53,12 +151,108 @@ export function isASRFinalEvent(event: ASREvent): event is ASRFinalEvent {
   return event.type === "ASR_FINAL";
 }
 
+export function isASRErrorEvent(event: ASREvent): event is ASRErrorEvent {
+  return event.type === "ASR_ERROR";
+}
+
+export function isASRSessionEvent(event: ASREvent): event is ASRSessionEvent {
+  return event.type === "ASR_SESSION";
+}
+
 // ─── ASR Provider interface (platform-agnostic) ───
 
 export interface ASROptions {
   readonly language: string; // "auto" | "en" | "es" | etc.
   readonly sampleRate: number;
   readonly enableSystemAudio?: boolean;
+  readonly streaming?: boolean;
+  readonly echoCancellation?: boolean; // remove speaker echo from the mic (needs system audio)
+  readonly noiseSuppression?: boolean;
+  readonly highPassFilter?: boolean;
+  readonly automaticGainControl?: boolean; // raise quiet speakers towards a target level
+  readonly recording?: ASRRecordingOptions; // keep per-source audio on disk (desktop only)
+  readonly journal?: ASRJournalOptions; // make the session resumable after a crash (desktop only)
+  readonly languagePolicy?: ASRLanguagePolicy; // only with language "auto" (desktop only)
+  readonly translation?: ASRTranslationOptions; // translate finals next to the source text (desktop only)
+}
+
+export interface ASRTranslationOptions {
+  readonly target: "en"; // whisper's translate task only produces English
+  readonly sources?: ("microphone" | "systemAudio")[]; // default: every source
+}
+
+export interface ASRLanguagePolicy {
+  /** Lock to a language detected with at least this probability (0..1)... */
+  readonly lockProbability?: number;
+  readonly lockSegments?: number; // ...this many decodes in a row; default 3
+  readonly allowed?: string[]; // language codes; others are decoded again in one of these
+}
+
+export interface ASRRecordingOptions {
+  readonly meetingSessionId: string;
+  readonly retentionDays?: number; // default 30
+}
+
+export interface ASRJournalOptions {
+  readonly meetingSessionId: string; // an unfinished journal with this ID is resumed
+  readonly keepAudio?: boolean; // also journal audio so untranscribed speech survives
+}
+
+export interface ASRStopOptions {
+  readonly cancel?: boolean; // discard buffered audio instead of transcribing it
+}
+
+// ─── Desktop whisper models ───
+
+/** A whisper model on disk (`list_installed_models`). */
+export interface ASRInstalledModel {
+  readonly id: string; // "small", "base.en", ...; file is ggml-<id>.bin
+  readonly path: string;
+  readonly sizeBytes: number;
+  readonly bundled: boolean; // shipped with the app; cannot be deleted
+  readonly isDefault: boolean;
+}
+
+/** A whisper model that can be downloaded (`list_available_models`). */
+export interface ASRAvailableModel {
+  readonly id: string;
+  readonly file: string;
+  readonly sizeBytes: number;
+  readonly sha256: string;
+  readonly installed: boolean;
+  readonly downloadedBytes: number; // partial download the next one resumes
+}
+
+/** Payload of "model-download" events. */
+export interface ASRModelDownloadEvent {
+  readonly modelId: string;
+  readonly state: "downloading" | "verifying" | "completed" | "failed";
+  readonly downloadedBytes: number;
+  readonly totalBytes: number;
+  readonly error?: ASRError;
+}
+
+/** Machine profile behind the desktop thread and model choice (`get_hardware_profile`). */
+export interface ASRHardwareProfile {
+  readonly hardware: {
+    readonly cpuBrand: string;
+    readonly arch: string;
+    readonly physicalCores: number;
+    readonly logicalCores: number;
+    readonly totalMemoryBytes: number;
+    readonly availableMemoryBytes: number;
+    readonly cpuFeatures: string[]; // "avx2", "fma", "neon", ...
+  };
+  readonly threads: number;
+  readonly recommendedModel: string; // model size, e.g. "small"
+  readonly selectedModel: string | null; // model path the last session used
+  readonly calibration: {
+    readonly modelPath: string;
+    readonly engine: string;
+    readonly threads: number;
+    readonly realtimeFactor: number; // decode time / audio time; <= 0.5 keeps up
+    readonly measuredAtMs: number;
+  } | null;
 }
 
 export interface ASRProvider {
@@ -72,7 +266,8 @@ export interface ASRProvider {
   isReady(): boolean;
 
   startListening(options: ASROptions): void;
-  stopListening(): void;
+  /** Transcribes buffered audio before stopping unless `cancel` is set. */
+  stopListening(options?: ASRStopOptions): void;
   pauseListening(): void;
   resumeListening(): void;
 
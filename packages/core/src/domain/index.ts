@@ -104,18 +104,38 @@ export {
 // ─── ASR Events ───
 export type {
   ASRState,
+  ASRError,
+  ASRErrorCode,
+  ASRErrorEvent,
+  ASRHardwareProfile,
+  ASRInstalledModel,
+  ASRAvailableModel,
+  ASRModelDownloadEvent,
+  ASRJournalOptions,
+  ASRLagReport,
+  ASRLanguagePolicy,
+  ASRLanguageReport,
+  ASRRecordingOptions,
+  ASRStopOptions,
+  ASRTranslationOptions,
   ASRStatusEvent,
   ASRPartialEvent,
   ASRFinalEvent,
+  ASRWord,
+  ASRSessionEvent,
+  ASRSessionPhase,
   ASREvent,
   ASROptions,
   ASRProvider,
+  ASRRecoveryAction,
 } from "./asr-events";
 
 export {
   isASRStatusEvent,
   isASRPartialEvent,
   isASRFinalEvent,
+  isASRErrorEvent,
+  isASRSessionEvent,
 } from "./asr-events";
 
 // ─── Processing Job ───